@@ -8,7 +8,8 @@ use png::*;
 
 use crate::ray::Ray;
 
-pub type Background = fn(&Ray) -> Color;
+/// Radiance arriving along rays which don't hit anything.
+pub type Background = dyn Fn(&Ray) -> Color + Sync + Send;
 
 pub type Color = Vector3<f64>;
 
@@ -1,6 +1,10 @@
-use cgmath::InnerSpace;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use cgmath::{dot, InnerSpace};
 
 use crate::base::*;
+use crate::materials::medium::Medium;
 use crate::random::RNG;
 use crate::ray::Ray;
 
@@ -14,6 +18,7 @@ pub struct Camera {
     w: Vec3,
     lens_radius: f64,
     aspect_ratio: f64,
+    medium: Option<Arc<dyn Medium>>,
 }
 
 impl Camera {
@@ -50,9 +55,24 @@ impl Camera {
             v,
             w,
             lens_radius: aperture / 2.0,
+            medium: None,
+        }
+    }
+
+    /// Places the camera inside a medium, such as an atmosphere filling the whole scene or the
+    /// water around a diver.
+    pub fn with_medium(self, medium: Arc<dyn Medium>) -> Camera {
+        Camera {
+            medium: Some(medium),
+            ..self
         }
     }
 
+    /// Medium the camera rays start in.
+    pub fn medium(&self) -> Option<&dyn Medium> {
+        self.medium.as_deref()
+    }
+
     pub fn get_ray(&self, s: f64, t: f64, rng: &mut RNG) -> Ray {
         let rd = self.lens_radius * rng.random_in_unit_disk();
         let offset = self.u * rd.x + self.v * rd.y;
@@ -63,6 +83,48 @@ impl Camera {
         )
     }
 
+    /// Point of the image (as the `s` and `t` of `get_ray`) seen by a ray leaving the lens at
+    /// `origin` along `direction`, or `None` if it goes behind the camera.
+    pub fn image_position(&self, origin: &Point3, direction: &Vec3) -> Option<(f64, f64)> {
+        let cos_theta = dot(direction.normalize(), -self.w);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        // Rays are focused on a plane at the focus distance, which the image is mapped onto
+        let focus_dist = dot(self.origin - self.lower_left_corner, self.w);
+        let on_plane = origin + direction.normalize() * (focus_dist / cos_theta);
+        let offset = on_plane - self.lower_left_corner;
+
+        Some((
+            dot(offset, self.horizontal) / self.horizontal.magnitude2(),
+            dot(offset, self.vertical) / self.vertical.magnitude2(),
+        ))
+    }
+
+    /// Area of the image (for `s` and `t` in `[0, 1]`), projected at a distance of 1 from the
+    /// lens.
+    pub fn image_area(&self) -> f64 {
+        let focus_dist = dot(self.origin - self.lower_left_corner, self.w);
+        self.horizontal.magnitude() * self.vertical.magnitude() / (focus_dist * focus_dist)
+    }
+
+    /// Cosine of the angle between `direction` and the viewing direction.
+    pub fn cos_theta(&self, direction: &Vec3) -> f64 {
+        dot(direction.normalize(), -self.w)
+    }
+
+    /// Area of the lens, 0 for a pinhole camera.
+    pub fn lens_area(&self) -> f64 {
+        PI * self.lens_radius * self.lens_radius
+    }
+
+    /// Uniform point on the lens.
+    pub fn sample_lens(&self, rng: &mut RNG) -> Point3 {
+        let rd = self.lens_radius * rng.random_in_unit_disk();
+        self.origin + self.u * rd.x + self.v * rd.y
+    }
+
     pub fn get_aspect_ratio(&self) -> f64 {
         self.aspect_ratio
     }
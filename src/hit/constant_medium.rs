@@ -1,85 +1,109 @@
-use cgmath::num_traits::Float;
-use cgmath::InnerSpace;
-
 use crate::aabb::AABB;
-use crate::base::{Color, Vec3};
+use crate::base::Color;
 use crate::hit::hit_record::HitRecord;
 use crate::hit::Hittable;
-use crate::materials::isotropic::Isotropic;
+use crate::materials::medium::{Homogeneous, Medium};
+use crate::materials::phase::{Isotropic, PhaseFunction};
 use crate::materials::textures::{SolidColor, Texture};
 use crate::random::RNG;
 use crate::ray::Ray;
 
-pub struct ConstantMedium<H: Hittable, T: Texture> {
+/// Maximum number of times a ray can cross the boundary of a medium.
+const MAX_CROSSINGS: usize = 64;
+
+/// Medium of constant density filling the inside of a closed boundary, whose own material is
+/// ignored. The boundary doesn't need to be convex, and can be made of several surfaces (e.g. a
+/// hollow sphere).
+pub struct ConstantMedium<H: Hittable, T: Texture, P: PhaseFunction = Isotropic> {
     boundary: H,
-    phase_function: Isotropic<T>,
-    neg_inv_density: f64,
+    medium: Homogeneous<T, P>,
 }
 
 impl<H: Hittable, T: Texture> ConstantMedium<H, T> {
     pub fn new(boundary: H, d: f64, texture: T) -> Self {
+        ConstantMedium::new_with_phase(boundary, d, texture, Isotropic)
+    }
+}
+
+impl<H: Hittable, T: Texture, P: PhaseFunction> ConstantMedium<H, T, P> {
+    /// Medium scattering light with the given phase function, e.g. `HenyeyGreenstein` for fog
+    /// glowing around lights.
+    pub fn new_with_phase(boundary: H, d: f64, texture: T, phase: P) -> Self {
         ConstantMedium {
             boundary,
-            neg_inv_density: -1.0 / d,
-            phase_function: Isotropic::new(texture),
+            medium: Homogeneous::new_with_phase(d, texture, phase),
         }
     }
 }
 
 impl<H: Hittable> ConstantMedium<H, SolidColor> {
     pub fn new_from_color(boundary: H, d: f64, color: Color) -> Self {
-        ConstantMedium {
-            boundary,
-            neg_inv_density: -1.0 / d,
-            phase_function: Isotropic::from_color(color),
-        }
+        ConstantMedium::new(boundary, d, SolidColor::new(color))
     }
 }
 
-impl<H: Hittable, T: Texture> Hittable for ConstantMedium<H, T> {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord> {
-        let mut rec1 = self
-            .boundary
-            .hit(&r, f64::neg_infinity(), f64::infinity(), rng)?;
-
-        let mut rec2 = self
-            .boundary
-            .hit(&r, rec1.t + 0.0001, f64::infinity(), rng)?;
-
-        if rec1.t < t_min {
-            rec1.t = t_min;
-        }
-        if rec2.t > t_max {
-            rec2.t = t_max;
-        }
-
-        if rec1.t >= rec2.t {
-            return None;
+/// Intervals of `t` in `[t_min, t_max]` where the ray is inside a closed boundary.
+///
+/// Whether the origin of the ray is inside is found from the parity of the number of times the
+/// ray crosses the boundary, which doesn't rely on the orientation of its normals.
+pub(crate) fn segments_inside<H: Hittable>(
+    boundary: &H,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+    rng: &mut RNG,
+) -> Vec<(f64, f64)> {
+    let mut crossings = Vec::new();
+    let mut t = 0.0;
+    while crossings.len() < MAX_CROSSINGS {
+        match boundary.hit(r, t + 0.0001, f64::INFINITY, rng) {
+            Some(rec) => {
+                t = rec.t;
+                crossings.push(t);
+            }
+            None => break,
         }
+    }
 
-        if rec1.t < 0.0 {
-            rec1.t = 0.0
-        }
+    let mut segments = Vec::new();
+    let mut enter = if crossings.len() % 2 == 1 {
+        Some(0.0)
+    } else {
+        None
+    };
+    for t in crossings {
+        enter = match enter {
+            Some(enter) => {
+                segments.push((f64::max(enter, t_min), f64::min(t, t_max)));
+                None
+            }
+            None => Some(t),
+        };
+    }
 
-        let ray_length = r.direction.magnitude();
-        let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-        let hit_distance = self.neg_inv_density * rng.random_double().ln();
+    segments.retain(|(enter, exit)| enter < exit);
+    segments
+}
 
-        if hit_distance > distance_inside_boundary {
-            return None;
-        }
+/// First interaction of the ray with a medium filling the inside of `boundary`.
+pub(crate) fn hit_bounded_medium<'a, H: Hittable, M: Medium>(
+    boundary: &H,
+    medium: &'a M,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+    rng: &mut RNG,
+) -> Option<HitRecord<'a>> {
+    // Free flights are memoryless, so they can be sampled independently in each segment
+    segments_inside(boundary, r, t_min, t_max, rng)
+        .into_iter()
+        .find_map(|(enter, exit)| medium.sample_interaction(r, enter, exit, rng))
+        .map(|t| medium.interaction(r, t))
+}
 
-        let t = rec1.t + hit_distance / ray_length;
-        let p = r.at(t);
-        Some(HitRecord {
-            t,
-            p,
-            front_face: true,
-            u: 0.0,                           // arbitrary
-            v: 0.0,                           // arbitrary
-            normal: Vec3::new(1.0, 0.0, 0.0), // arbitrary
-            material: &self.phase_function,   // arbitrary
-        })
+impl<H: Hittable, T: Texture, P: PhaseFunction> Hittable for ConstantMedium<H, T, P> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord<'_>> {
+        hit_bounded_medium(&self.boundary, &self.medium, r, t_min, t_max, rng)
     }
 
     fn bounding_box(&self) -> Option<AABB> {
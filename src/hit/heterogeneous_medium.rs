@@ -0,0 +1,62 @@
+use crate::aabb::AABB;
+use crate::hit::constant_medium::{hit_bounded_medium, segments_inside};
+use crate::hit::hit_record::HitRecord;
+use crate::hit::Hittable;
+use crate::materials::density::Density;
+use crate::materials::medium::{Heterogeneous, Medium};
+use crate::materials::phase::{Isotropic, PhaseFunction};
+use crate::materials::textures::Texture;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/// Participating medium whose density varies inside a closed boundary, like `ConstantMedium`.
+pub struct HeterogeneousMedium<H: Hittable, D: Density, T: Texture, P: PhaseFunction = Isotropic> {
+    boundary: H,
+    medium: Heterogeneous<D, T, P>,
+}
+
+impl<H: Hittable, D: Density, T: Texture> HeterogeneousMedium<H, D, T> {
+    /// The extinction coefficient of the medium is the density at each point multiplied by
+    /// `scale`.
+    pub fn new(boundary: H, density: D, scale: f64, albedo: T) -> Self {
+        HeterogeneousMedium::new_with_phase(boundary, density, scale, albedo, Isotropic)
+    }
+}
+
+impl<H: Hittable, D: Density, T: Texture, P: PhaseFunction> HeterogeneousMedium<H, D, T, P> {
+    pub fn new_with_phase(boundary: H, density: D, scale: f64, albedo: T, phase: P) -> Self {
+        HeterogeneousMedium {
+            boundary,
+            medium: Heterogeneous::new_with_phase(density, scale, albedo, phase),
+        }
+    }
+
+    /// Emitting medium, such as fire, where `emission` is usually a `Grid` of colors.
+    pub fn with_emission<E: Texture + 'static>(self, emission: E) -> Self {
+        HeterogeneousMedium {
+            medium: self.medium.with_emission(emission),
+            ..self
+        }
+    }
+
+    /// Fraction of the light going through the medium between `t_min` and `t_max` along the ray,
+    /// estimated without bias with ratio tracking.
+    pub fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> f64 {
+        segments_inside(&self.boundary, r, t_min, t_max, rng)
+            .into_iter()
+            .map(|(enter, exit)| self.medium.transmittance(r, enter, exit, rng))
+            .product()
+    }
+}
+
+impl<H: Hittable, D: Density, T: Texture, P: PhaseFunction> Hittable
+    for HeterogeneousMedium<H, D, T, P>
+{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord<'_>> {
+        hit_bounded_medium(&self.boundary, &self.medium, r, t_min, t_max, rng)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.boundary.bounding_box()
+    }
+}
@@ -1,6 +1,7 @@
 use cgmath::dot;
 
 use crate::base::*;
+use crate::lights::Light;
 use crate::materials::Material;
 use crate::ray::Ray;
 
@@ -10,8 +11,13 @@ pub struct HitRecord<'a> {
     pub v: f64,
     pub p: Point3,
     pub normal: Vec3,
+    /// Derivative of the point along `u`, which orients anisotropic materials. Zero when the
+    /// surface has no parameterization.
+    pub tangent: Vec3,
     pub material: &'a dyn Material,
     pub front_face: bool,
+    /// Light the surface belongs to, if it is also sampled with shadow rays.
+    pub light: Option<&'a dyn Light>,
 }
 
 impl<'a> HitRecord<'a> {
@@ -27,16 +33,35 @@ impl<'a> HitRecord<'a> {
         let tmp = HitRecord {
             p: point,
             normal: Vec3::new(0.0, 0.0, 0.0), // temporary before we calculate it bellow
+            tangent: Vec3::new(0.0, 0.0, 0.0),
             t,
             u,
             v,
             material,
             front_face: false,
+            light: None,
         };
 
         tmp.calculate_face_normal(r, outward_normal)
     }
 
+    /// Sets the derivative of the point along `u`.
+    pub fn with_tangent(self, dpdu: &Vec3) -> Self {
+        HitRecord {
+            tangent: *dpdu,
+            ..self
+        }
+    }
+
+    /// The same hit, as found by a ray coming from the other side of the surface.
+    pub fn flipped(&self) -> Self {
+        HitRecord {
+            normal: -self.normal,
+            front_face: !self.front_face,
+            ..*self
+        }
+    }
+
     fn calculate_face_normal(self, r: &Ray, outward_normal: &Vec3) -> Self {
         let front_face = dot(r.direction, *outward_normal) < 0.0;
         HitRecord {
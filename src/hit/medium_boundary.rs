@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::hit::Hittable;
+use crate::materials::medium::Medium;
+use crate::materials::Material;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/// Invisible closed surface, with normals pointing outwards, enclosing a medium.
+///
+/// Paths go straight through the surface, and the medium is added to (or removed from) the
+/// media tracked along the path. Unlike with `ConstantMedium`, media can then be nested, such as
+/// fog in a glass bottle in a tank of water, the innermost one replacing the others.
+pub struct MediumBoundary<H: Hittable> {
+    boundary: H,
+    interface: Interface,
+}
+
+impl<H: Hittable> MediumBoundary<H> {
+    /// The medium can be shared with the camera, when it starts inside the boundary.
+    pub fn new(boundary: H, medium: Arc<dyn Medium>) -> Self {
+        MediumBoundary {
+            boundary,
+            interface: Interface { medium },
+        }
+    }
+}
+
+/// Material of the boundary, letting paths through.
+struct Interface {
+    medium: Arc<dyn Medium>,
+}
+
+impl Material for Interface {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, _rng: &mut RNG) -> Option<(Color, Ray)> {
+        Some((Color::new(1.0, 1.0, 1.0), Ray::new(rec.p, ray.direction)))
+    }
+
+    fn interior_medium(&self) -> Option<&dyn Medium> {
+        Some(self.medium.as_ref())
+    }
+
+    fn is_interface(&self) -> bool {
+        true
+    }
+}
+
+impl<H: Hittable> Hittable for MediumBoundary<H> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord<'_>> {
+        let rec = self.boundary.hit(r, t_min, t_max, rng)?;
+
+        Some(HitRecord {
+            material: &self.interface,
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.boundary.bounding_box()
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use crate::aabb::AABB;
 use crate::hit::hit_record::HitRecord;
 use crate::random::RNG;
@@ -5,9 +7,12 @@ use crate::ray::Ray;
 
 pub mod bvh;
 pub mod constant_medium;
+pub mod heterogeneous_medium;
 pub mod hit_record;
+pub mod medium_boundary;
 pub mod rectangle;
 pub mod sphere;
+pub mod subsurface;
 pub mod transform;
 
 // Hittable
@@ -17,6 +22,17 @@ pub trait Hittable: Sync {
     fn bounding_box(&self) -> Option<AABB>;
 }
 
+/// Objects shared with other parts of the scene, e.g. a `SphereLight` which is also a light.
+impl<H: Hittable + Send> Hittable for Arc<H> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord<'_>> {
+        self.as_ref().hit(r, t_min, t_max, rng)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.as_ref().bounding_box()
+    }
+}
+
 // HittableList
 
 pub struct HittableList {
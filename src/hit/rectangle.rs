@@ -3,6 +3,7 @@ use std::sync::Arc;
 use crate::aabb::AABB;
 use crate::base::{Point3, Vec3};
 use crate::hit::hit_record::HitRecord;
+use crate::hit::transform::FlipFace;
 use crate::hit::{Hittable, HittableList};
 use crate::materials::Material;
 use crate::random::RNG;
@@ -33,7 +34,7 @@ impl<M: Material> XYRect<M> {
 }
 
 impl<M: Material> Hittable for XYRect<M> {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut RNG) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord> {
         let t = (self.k - r.origin.z) / r.direction.z;
         if t < t_min || t > t_max {
             return None;
@@ -45,15 +46,17 @@ impl<M: Material> Hittable for XYRect<M> {
             return None;
         }
 
-        Some(HitRecord::new(
-            t,
-            (x - self.x0) / (self.x1 - self.x0),
-            (y - self.y0) / (self.y1 - self.y0),
-            r.at(t),
-            self.mp.as_ref(),
-            &r,
-            &Vec3::new(0.0, 0.0, 1.0),
-        ))
+        let u = (x - self.x0) / (self.x1 - self.x0);
+        let v = (y - self.y0) / (self.y1 - self.y0);
+        let p = r.at(t);
+        if !self.mp.is_opaque(u, v, &p, rng) {
+            return None;
+        }
+
+        Some(
+            HitRecord::new(t, u, v, p, self.mp.as_ref(), &r, &Vec3::new(0.0, 0.0, 1.0))
+                .with_tangent(&Vec3::new(self.x1 - self.x0, 0.0, 0.0)),
+        )
     }
 
     fn bounding_box(&self) -> Option<AABB> {
@@ -89,7 +92,7 @@ impl<M: Material> XZRect<M> {
 }
 
 impl<M: Material> Hittable for XZRect<M> {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut RNG) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord> {
         let t = (self.k - r.origin.y) / r.direction.y;
         if t < t_min || t > t_max {
             return None;
@@ -101,15 +104,17 @@ impl<M: Material> Hittable for XZRect<M> {
             return None;
         }
 
-        Some(HitRecord::new(
-            t,
-            (x - self.x0) / (self.x1 - self.x0),
-            (z - self.z0) / (self.z1 - self.z0),
-            r.at(t),
-            self.mp.as_ref(),
-            &r,
-            &Vec3::new(0.0, 1.0, 0.0),
-        ))
+        let u = (x - self.x0) / (self.x1 - self.x0);
+        let v = (z - self.z0) / (self.z1 - self.z0);
+        let p = r.at(t);
+        if !self.mp.is_opaque(u, v, &p, rng) {
+            return None;
+        }
+
+        Some(
+            HitRecord::new(t, u, v, p, self.mp.as_ref(), &r, &Vec3::new(0.0, 1.0, 0.0))
+                .with_tangent(&Vec3::new(self.x1 - self.x0, 0.0, 0.0)),
+        )
     }
 
     fn bounding_box(&self) -> Option<AABB> {
@@ -145,7 +150,7 @@ impl<M: Material> YZRect<M> {
 }
 
 impl<M: Material> Hittable for YZRect<M> {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut RNG) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord> {
         let t = (self.k - r.origin.x) / r.direction.x;
         if t < t_min || t > t_max {
             return None;
@@ -157,15 +162,17 @@ impl<M: Material> Hittable for YZRect<M> {
             return None;
         }
 
-        Some(HitRecord::new(
-            t,
-            (y - self.y0) / (self.y1 - self.y0),
-            (z - self.z0) / (self.z1 - self.z0),
-            r.at(t),
-            self.mp.as_ref(),
-            &r,
-            &Vec3::new(1.0, 0.0, 0.0),
-        ))
+        let u = (y - self.y0) / (self.y1 - self.y0);
+        let v = (z - self.z0) / (self.z1 - self.z0);
+        let p = r.at(t);
+        if !self.mp.is_opaque(u, v, &p, rng) {
+            return None;
+        }
+
+        Some(
+            HitRecord::new(t, u, v, p, self.mp.as_ref(), &r, &Vec3::new(1.0, 0.0, 0.0))
+                .with_tangent(&Vec3::new(0.0, self.y1 - self.y0, 0.0)),
+        )
     }
 
     fn bounding_box(&self) -> Option<AABB> {
@@ -196,14 +203,15 @@ impl Cuboid {
             box_max.z,
             material.clone(),
         )));
-        sides.add(Box::new(XYRect::new(
+        // Sides at the minimum coordinates are flipped so that all normals point outwards
+        sides.add(Box::new(FlipFace::new(XYRect::new(
             box_min.x,
             box_max.x,
             box_min.y,
             box_max.y,
             box_min.z,
             material.clone(),
-        )));
+        ))));
 
         sides.add(Box::new(XZRect::new(
             box_min.x,
@@ -213,14 +221,14 @@ impl Cuboid {
             box_max.y,
             material.clone(),
         )));
-        sides.add(Box::new(XZRect::new(
+        sides.add(Box::new(FlipFace::new(XZRect::new(
             box_min.x,
             box_max.x,
             box_min.z,
             box_max.z,
             box_min.y,
             material.clone(),
-        )));
+        ))));
 
         sides.add(Box::new(YZRect::new(
             box_min.y,
@@ -230,14 +238,14 @@ impl Cuboid {
             box_max.x,
             material.clone(),
         )));
-        sides.add(Box::new(YZRect::new(
+        sides.add(Box::new(FlipFace::new(YZRect::new(
             box_min.y,
             box_max.y,
             box_min.z,
             box_max.z,
             box_min.x,
             material.clone(),
-        )));
+        ))));
 
         Cuboid {
             sides,
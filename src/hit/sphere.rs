@@ -27,7 +27,7 @@ impl<M: Material> Sphere<M> {
 }
 
 impl<M: Material> Hittable for Sphere<M> {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut RNG) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord> {
         let oc = r.origin - self.center;
         let a = r.direction.magnitude2();
         let half_b = dot(oc, r.direction);
@@ -39,29 +39,33 @@ impl<M: Material> Hittable for Sphere<M> {
         }
         let sqrtd = discriminant.sqrt();
 
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
+        // Find the nearest root that lies in the acceptable range, and where the surface is not
+        // cut out.
+        for root in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
             if root < t_min || t_max < root {
-                return None;
+                continue;
             }
-        }
 
-        let t = root;
-        let p = r.at(t);
-        let outward_normal = (p - self.center) / self.radius;
-        let (u, v) = calc_sphere_uv(&outward_normal);
+            let p = r.at(root);
+            let outward_normal = (p - self.center) / self.radius;
+            let (u, v) = calc_sphere_uv(&outward_normal);
+            if !self.material.is_opaque(u, v, &p, rng) {
+                continue;
+            }
 
-        Some(HitRecord::new(
-            root,
-            u,
-            v,
-            p,
-            self.material.as_ref(),
-            &r,
-            &outward_normal,
-        ))
+            // The point moves around the y axis as u grows
+            let dpdu = 2.0
+                * std::f64::consts::PI
+                * self.radius
+                * Vec3::new(outward_normal.z, 0.0, -outward_normal.x);
+
+            return Some(
+                HitRecord::new(root, u, v, p, self.material.as_ref(), &r, &outward_normal)
+                    .with_tangent(&dpdu),
+            );
+        }
+
+        None
     }
 
     fn bounding_box(&self) -> Option<AABB> {
@@ -72,7 +76,7 @@ impl<M: Material> Hittable for Sphere<M> {
     }
 }
 
-fn calc_sphere_uv(p: &Point3) -> (f64, f64) {
+pub(crate) fn calc_sphere_uv(p: &Point3) -> (f64, f64) {
     let pi = std::f64::consts::PI;
     let theta = (-p.y).acos();
     let phi = f64::atan2(-p.z, p.x) + pi;
@@ -0,0 +1,194 @@
+use cgmath::InnerSpace;
+
+use crate::aabb::AABB;
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::hit::Hittable;
+use crate::materials::microfacet::{sample_dielectric, TrowbridgeReitz};
+use crate::materials::phase::{HenyeyGreenstein, PhaseFunction};
+use crate::materials::Material;
+use crate::onb::Onb;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/// Maximum number of scattering events and internal reflections of a walk before the path is
+/// terminated.
+const MAX_STEPS: u32 = 1024;
+
+/// Translucent object, such as skin, wax or marble, where light enters through a smooth
+/// dielectric surface and scatters inside a homogeneous medium before leaving at another point.
+///
+/// Like `ConstantMedium`, it wraps the hittable giving the shape of the object (whose own
+/// material is ignored). A path entering the object performs a random walk inside the boundary,
+/// handled entirely when scattering on the surface.
+pub struct Subsurface<H: Hittable> {
+    boundary: H,
+    ir: f64,
+    interface: TrowbridgeReitz,
+    // Extinction and scattering coefficients for each color channel
+    sigma_t: Color,
+    sigma_s: Color,
+    phase: HenyeyGreenstein,
+}
+
+impl<H: Hittable> Subsurface<H> {
+    /// `albedo` is the fraction of light scattered (rather than absorbed) at each interaction with
+    /// the medium, and `mean_free_path` the average distance between interactions, for each color
+    /// channel. `anisotropy` is the Henyey-Greenstein asymmetry parameter, in `(-1, 1)`.
+    pub fn new(
+        boundary: H,
+        ir: f64,
+        albedo: Color,
+        mean_free_path: Color,
+        anisotropy: f64,
+    ) -> Subsurface<H> {
+        let sigma_t = Color::new(
+            1.0 / mean_free_path.x,
+            1.0 / mean_free_path.y,
+            1.0 / mean_free_path.z,
+        );
+
+        Subsurface {
+            boundary,
+            ir,
+            interface: TrowbridgeReitz::new(0.0, 0.0),
+            sigma_t,
+            sigma_s: albedo.multiply_with(&sigma_t),
+            phase: HenyeyGreenstein::new(anisotropy),
+        }
+    }
+
+    /// Creates the medium from its scattering and absorption coefficients, per unit of length.
+    pub fn new_from_coefficients(
+        boundary: H,
+        ir: f64,
+        sigma_s: Color,
+        sigma_a: Color,
+        anisotropy: f64,
+    ) -> Subsurface<H> {
+        Subsurface {
+            boundary,
+            ir,
+            interface: TrowbridgeReitz::new(0.0, 0.0),
+            sigma_t: sigma_s + sigma_a,
+            sigma_s,
+            phase: HenyeyGreenstein::new(anisotropy),
+        }
+    }
+
+    /// Crosses the surface at `rec`, from the side the normal points to.
+    fn sample_interface(
+        &self,
+        rec: &HitRecord,
+        direction: &Vec3,
+        rng: &mut RNG,
+    ) -> Option<(Vec3, f64)> {
+        let eta = if rec.front_face {
+            self.ir
+        } else {
+            1.0 / self.ir
+        };
+        let onb = Onb::from_w(&rec.normal);
+        let wo = onb.to_local(&-direction.normalize());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let (wi, weight) = sample_dielectric(
+            &self.interface,
+            &wo,
+            eta,
+            [
+                rng.random_double(),
+                rng.random_double(),
+                rng.random_double(),
+            ],
+        )?;
+
+        Some((onb.to_world(&wi), weight))
+    }
+}
+
+fn exp(c: Color) -> Color {
+    Color::new(c.x.exp(), c.y.exp(), c.z.exp())
+}
+
+fn average(c: &Color) -> f64 {
+    (c.x + c.y + c.z) / 3.0
+}
+
+impl<H: Hittable + Send> Material for Subsurface<H> {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
+        if !rec.front_face {
+            return None;
+        }
+
+        let (mut direction, weight) = self.sample_interface(rec, &ray.direction, rng)?;
+        if direction.dot(rec.normal) > 0.0 {
+            // Reflected on the surface
+            return Some((
+                Color::new(weight, weight, weight),
+                Ray::new(rec.p, direction),
+            ));
+        }
+
+        // Distances along the whole walk are sampled with the coefficient of one channel picked
+        // at random. The walk is weighted by the average of the pdfs it would have had with each
+        // channel (spectral MIS), which keeps chromatic media unbiased. Both are kept relative to
+        // the pdf of the chosen channel to avoid underflows on long walks.
+        let channel = usize::min((rng.random_double() * 3.0) as usize, 2);
+        let mut throughput = Color::new(weight, weight, weight);
+        let mut pdf = Color::new(1.0, 1.0, 1.0);
+        let mut origin = rec.p;
+
+        for _ in 0..MAX_STEPS {
+            let walk = Ray::new(origin, direction);
+            let exit = self.boundary.hit(&walk, 0.0001, f64::INFINITY, rng)?;
+
+            let distance = -(1.0 - rng.random_double()).ln() / self.sigma_t[channel];
+
+            if distance < exit.t {
+                let transmittance = exp(-self.sigma_t * distance);
+                let step_pdf = self.sigma_t.multiply_with(&transmittance);
+                throughput = throughput.multiply_with(&self.sigma_s.multiply_with(&transmittance))
+                    / step_pdf[channel];
+                pdf = pdf.multiply_with(&step_pdf) / step_pdf[channel];
+
+                origin = walk.at(distance);
+                direction = self.phase.sample(&direction, rng);
+                continue;
+            }
+
+            let transmittance = exp(-self.sigma_t * exit.t);
+            throughput = throughput.multiply_with(&transmittance) / transmittance[channel];
+            pdf = pdf.multiply_with(&transmittance) / transmittance[channel];
+
+            let (next, weight) = self.sample_interface(&exit, &direction, rng)?;
+            throughput *= weight;
+            origin = exit.p;
+            direction = next;
+
+            if direction.dot(exit.normal) < 0.0 {
+                // Refracted out of the object
+                return Some((throughput / average(&pdf), Ray::new(origin, direction)));
+            }
+        }
+
+        None
+    }
+}
+
+impl<H: Hittable + Send> Hittable for Subsurface<H> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord<'_>> {
+        let rec = self.boundary.hit(r, t_min, t_max, rng)?;
+
+        Some(HitRecord {
+            material: self,
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.boundary.bounding_box()
+    }
+}
@@ -35,6 +35,7 @@ impl<H: Hittable> Hittable for Translate<H> {
                 &moved_r,
                 &hit.normal,
             )
+            .with_tangent(&hit.tangent)
         })
     }
 
@@ -122,6 +123,7 @@ impl<H: Hittable> Hittable for RotateY<H> {
                 &rotated_r,
                 &self.rotate_around_y(hit.normal),
             )
+            .with_tangent(&self.rotate_around_y(hit.tangent))
         })
     }
 
@@ -129,3 +131,30 @@ impl<H: Hittable> Hittable for RotateY<H> {
         self.bbox
     }
 }
+
+// FlipFace
+
+/// Swaps the front and back faces of an object, e.g. so that a one-sided light emits towards
+/// the inside of its outward normal.
+pub struct FlipFace<H: Hittable> {
+    obj: H,
+}
+
+impl<H: Hittable> FlipFace<H> {
+    pub fn new(obj: H) -> Self {
+        FlipFace { obj }
+    }
+}
+
+impl<H: Hittable> Hittable for FlipFace<H> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord<'_>> {
+        self.obj.hit(r, t_min, t_max, rng).map(|hit| HitRecord {
+            front_face: !hit.front_face,
+            ..hit
+        })
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.obj.bounding_box()
+    }
+}
@@ -0,0 +1,752 @@
+use cgmath::{dot, InnerSpace};
+
+use crate::base::*;
+use crate::camera::Camera;
+use crate::hit::hit_record::HitRecord;
+use crate::hit::Hittable;
+use crate::integrators::{power_heuristic, transmittance, Film, PathLength};
+use crate::lights::bvh::LightBvh;
+use crate::lights::point::window;
+use crate::lights::Light;
+use crate::materials::medium::MediumStack;
+use crate::random::RNG;
+use crate::ray::Ray;
+use crate::spectrum::{Radiance, Wavelengths};
+
+/// Camera seen as a sensor, which measures the light arriving on the film with its importance.
+struct Sensor<'a> {
+    camera: &'a Camera,
+    film: &'a Film,
+    area: f64,
+    lens_area: f64,
+}
+
+impl<'a> Sensor<'a> {
+    fn new(camera: &'a Camera, film: &'a Film) -> Sensor<'a> {
+        // Pinhole cameras are treated as a lens of unit area, sampled with a density of 1
+        let lens_area = camera.lens_area();
+        Sensor {
+            camera,
+            film,
+            area: film.area(camera),
+            lens_area: if lens_area > 0.0 { lens_area } else { 1.0 },
+        }
+    }
+
+    /// Importance of the ray leaving the lens at `origin` along `direction`, and the pixel it
+    /// lands on.
+    fn importance(&self, origin: &Point3, direction: &Vec3) -> Option<(f64, usize)> {
+        let cos_theta = self.camera.cos_theta(direction);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let (s, t) = self.camera.image_position(origin, direction)?;
+        let pixel = self.film.pixel(s, t)?;
+
+        Some((
+            1.0 / (self.area * self.lens_area * cos_theta.powi(4)),
+            pixel,
+        ))
+    }
+
+    /// Density (per solid angle) of camera rays leaving the lens at `origin` along `direction`.
+    fn pdf_direction(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        match self.importance(origin, direction) {
+            Some(_) => 1.0 / (self.area * self.camera.cos_theta(direction).powi(3)),
+            None => 0.0,
+        }
+    }
+}
+
+enum Kind<'a> {
+    Camera,
+    /// Start of a path on a light.
+    Light(&'a dyn Light),
+    /// Surface or medium interaction.
+    Scattering(HitRecord<'a>),
+}
+
+/// Vertex of a subpath traced from the camera or from a light.
+struct Vertex<'a, R> {
+    kind: Kind<'a>,
+    p: Point3,
+    /// Normal of the surface, or `None` at points and medium interactions, for which densities
+    /// have no cosine term.
+    normal: Option<Vec3>,
+    /// Media the subpath was in when reaching the vertex.
+    media: MediumStack<'a>,
+    /// Product of the sampling weights of the subpath up to the vertex.
+    beta: R,
+    /// Density (per unit of area) with which the vertex is sampled from the previous vertex of
+    /// its subpath.
+    pdf_fwd: f64,
+    /// Density (per unit of area) with which the vertex would be sampled from the next one, if
+    /// the path was traced the other way.
+    pdf_rev: f64,
+    /// Whether the vertex scatters in a single direction, like a mirror, or is a punctual light.
+    delta: bool,
+}
+
+impl<'a, R: Radiance> Vertex<'a, R> {
+    /// Light the vertex lies on, if any.
+    fn light(&self) -> Option<&'a dyn Light> {
+        match &self.kind {
+            Kind::Camera => None,
+            Kind::Light(light) => Some(*light),
+            Kind::Scattering(rec) => rec.light,
+        }
+    }
+
+    /// Whether the vertex scatters differently at each wavelength, which light subpaths can't
+    /// go through (see `li`).
+    fn is_dispersive(&self) -> bool {
+        matches!(&self.kind, Kind::Scattering(rec) if rec.material.is_dispersive())
+    }
+
+    /// Density (per unit of area) of sampling `next` from the vertex, reached from `prev`.
+    fn pdf(&self, sensor: &Sensor, prev: Option<&Vertex<R>>, next: &Vertex<R>) -> f64 {
+        let direction = next.p - self.p;
+        let pdf = match (&self.kind, prev) {
+            (Kind::Camera, _) => sensor.pdf_direction(&self.p, &direction),
+            (Kind::Light(light), _) => light.pdf_emission(&self.p, &direction).1,
+            (Kind::Scattering(rec), Some(prev)) => {
+                let wo = (prev.p - self.p).normalize();
+                rec.material.pdf(rec, &wo, &direction.normalize())
+            }
+            (Kind::Scattering(_), None) => 0.0,
+        };
+
+        to_area(pdf, &self.p, &next.p, next.normal)
+    }
+
+    /// Density (per unit of area) of a path starting on the light at the vertex, and going
+    /// towards `next`.
+    fn pdf_light(&self, next: &Vertex<R>) -> f64 {
+        match self.light() {
+            Some(light) => to_area(
+                light.pdf_emission(&self.p, &(next.p - self.p)).1,
+                &self.p,
+                &next.p,
+                next.normal,
+            ),
+            None => 0.0,
+        }
+    }
+
+    /// Density (per unit of area) of a path starting at the vertex, on a light, counting the
+    /// probability of picking the light.
+    fn pdf_light_origin(&self, next: &Vertex<R>, lights: &LightBvh) -> f64 {
+        match self.light() {
+            Some(light) => {
+                lights.emitter_pmf(light) * light.pdf_emission(&self.p, &(next.p - self.p)).0
+            }
+            None => 0.0,
+        }
+    }
+
+    /// BSDF times the cosine of `wi`, for light going between the previous vertex `prev` and
+    /// the direction `wi` through the vertex.
+    fn f(&self, prev: &Vertex<R>, wi: &Vec3) -> Color {
+        match &self.kind {
+            Kind::Scattering(rec) => {
+                let wo = (prev.p - self.p).normalize();
+                rec.material.eval(rec, &wo, wi)
+            }
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Density (per solid angle) with which the material at the vertex samples `wi`, for light
+    /// going towards the previous vertex `prev`.
+    fn pdf_scattering(&self, prev: &Vertex<R>, wi: &Vec3) -> f64 {
+        match &self.kind {
+            Kind::Scattering(rec) => {
+                let wo = (prev.p - self.p).normalize();
+                rec.material.pdf(rec, &wo, wi)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Converts a density per solid angle at `from` to a density per unit of area at `to`, where
+/// the surface has the given normal.
+fn to_area(pdf: f64, from: &Point3, to: &Point3, normal: Option<Vec3>) -> f64 {
+    let offset = to - from;
+    let d2 = offset.magnitude2();
+    if d2 == 0.0 {
+        return 0.0;
+    }
+
+    let cos_theta = normal.map_or(1.0, |n| dot(n, offset).abs() / d2.sqrt());
+    pdf * cos_theta / d2
+}
+
+/// Light arriving at the camera along `r`, with bidirectional path tracing (Veach, 1997).
+///
+/// A subpath is traced from the camera and another one from a light, and every vertex of one is
+/// connected to every vertex of the other with a shadow ray. Each connection is a different way
+/// of sampling a path, and they are weighted by multiple importance sampling, so that e.g.
+/// caustics come from the subpaths started on lights. Connections to the camera itself land on
+/// any pixel, and are splatted onto `film`.
+///
+/// Light subpaths start in the medium of the camera, and stop at dispersive materials so that
+/// only the camera subpath can be restricted to a single wavelength.
+#[allow(clippy::too_many_arguments)]
+pub fn li<T: Hittable, W: Wavelengths>(
+    r: Ray,
+    world: &T,
+    background: &Background,
+    lights: &LightBvh,
+    camera: &Camera,
+    film: &Film,
+    wavelengths: &W,
+    path_length: PathLength,
+    rng: &mut RNG,
+) -> W::Radiance {
+    let sensor = Sensor::new(camera, film);
+    let media = MediumStack::from(camera.medium());
+    let wavelength = r.wavelength;
+    let max_depth = path_length.max_depth as usize;
+
+    // Camera subpath
+    let mut camera_path = vec![Vertex {
+        kind: Kind::Camera,
+        p: r.origin,
+        normal: None,
+        media: media.clone(),
+        beta: W::Radiance::white(),
+        pdf_fwd: 1.0,
+        pdf_rev: 0.0,
+        delta: false,
+    }];
+    let pdf = sensor.pdf_direction(&r.origin, &r.direction);
+    let escaped = walk(
+        r,
+        W::Radiance::white(),
+        pdf,
+        world,
+        wavelengths,
+        path_length,
+        max_depth + 2,
+        false,
+        &mut camera_path,
+        rng,
+    );
+
+    // Light subpath
+    let mut light_path = Vec::new();
+    if let Some((light, pmf)) = lights.sample_emitter(rng) {
+        if let Some(emission) = light.sample_emission(rng) {
+            let cos_theta = emission
+                .normal
+                .map_or(1.0, |n| dot(n, emission.ray.direction).abs());
+            let pdf = pmf * emission.pdf_position * emission.pdf_direction;
+
+            if pdf > 0.0 {
+                light_path.push(Vertex {
+                    kind: Kind::Light(light),
+                    p: emission.ray.origin,
+                    normal: emission.normal,
+                    media: media.clone(),
+                    beta: wavelengths.light_emission(light, &emission)
+                        * (1.0 / (pmf * emission.pdf_position)),
+                    pdf_fwd: pmf * emission.pdf_position,
+                    pdf_rev: 0.0,
+                    delta: false,
+                });
+                let ray = Ray {
+                    wavelength,
+                    ..emission.ray
+                };
+                let beta = wavelengths.light_emission(light, &emission) * (cos_theta / pdf);
+
+                walk(
+                    ray,
+                    beta,
+                    emission.pdf_direction,
+                    world,
+                    wavelengths,
+                    path_length,
+                    max_depth + 1,
+                    true,
+                    &mut light_path,
+                    rng,
+                );
+
+                // Lights with a range only light what is close enough to them
+                if let (Some(range), Some(first)) = (emission.range, light_path.get(1)) {
+                    let window = window((first.p - emission.ray.origin).magnitude(), Some(range));
+                    for vertex in light_path.iter_mut().skip(1) {
+                        vertex.beta = vertex.beta * window;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut radiance = W::Radiance::black();
+    if let Some((escaped, beta)) = escaped {
+        let last = camera_path.last().unwrap();
+        let mut emitted = background(&escaped);
+
+        // Lights at infinity are also sampled from the last vertex (s = 1), and no other
+        // strategy reaches them
+        let scattering_pdf = match camera_path.len() {
+            n if n >= 2 => last.pdf_scattering(&camera_path[n - 2], &escaped.direction.normalize()),
+            _ => 0.0,
+        };
+        for (light, pmf) in lights.infinite() {
+            let weight = if scattering_pdf > 0.0 {
+                power_heuristic(scattering_pdf, pmf * light.pdf(&last.p, &escaped.direction))
+            } else {
+                1.0
+            };
+            emitted += light.escaped(&escaped.direction) * weight;
+        }
+
+        radiance = radiance + beta.modulate(&wavelengths.illuminant(&emitted));
+    }
+
+    // Lights are sampled from the camera subpath (s = 1) even without a light subpath, which
+    // is never traced from lights at infinity
+    for t in 1..=camera_path.len() {
+        for s in 0..=light_path.len().max(1) {
+            if (s == 1 && t == 1) || s + t < 2 || s + t - 2 > max_depth {
+                continue;
+            }
+
+            let connection = Connection {
+                light_path: &light_path,
+                camera_path: &camera_path,
+                s,
+                t,
+                world,
+                lights,
+                sensor: &sensor,
+                wavelengths,
+                wavelength,
+            };
+            if let Some((contribution, pixel)) = connection.contribution(rng) {
+                match pixel {
+                    Some(pixel) => film.splat(pixel, &wavelengths.to_rgb(&contribution)),
+                    None => radiance = radiance + contribution,
+                }
+            }
+        }
+    }
+
+    radiance
+}
+
+/// Extends a subpath from its last vertex, along `r` sampled with density `pdf` (per solid
+/// angle) and weight `beta`. Returns the ray leaving the scene with its weight, if the subpath
+/// ends that way.
+#[allow(clippy::too_many_arguments)]
+fn walk<'a, T: Hittable, W: Wavelengths>(
+    mut r: Ray,
+    mut beta: W::Radiance,
+    mut pdf: f64,
+    world: &'a T,
+    wavelengths: &W,
+    path_length: PathLength,
+    max_vertices: usize,
+    from_light: bool,
+    path: &mut Vec<Vertex<'a, W::Radiance>>,
+    rng: &mut RNG,
+) -> Option<(Ray, W::Radiance)> {
+    let mut media = path.last().unwrap().media.clone();
+    // Russian roulette is based on the throughput since the start of the subpath
+    let start = beta.max_value();
+
+    while path.len() < max_vertices {
+        let surface = world.hit(&r, 0.001, f64::INFINITY, rng);
+        let interaction = media.current().and_then(|medium| {
+            let t_max = surface.as_ref().map_or(f64::INFINITY, |rec| rec.t);
+            medium
+                .sample_interaction(&r, 0.0, t_max, rng)
+                .map(|t| medium.interaction(&r, t))
+        });
+
+        let rec = match interaction.or(surface) {
+            Some(rec) => rec,
+            None => return Some((r, beta)),
+        };
+
+        // Medium interfaces aren't vertices, subpaths go straight through them
+        if rec.material.is_interface() {
+            media = media.crossed(&rec);
+            r = Ray { origin: rec.p, ..r };
+            continue;
+        }
+
+        let normal = if rec.material.is_volumetric() {
+            None
+        } else {
+            Some(rec.normal)
+        };
+        let p = rec.p;
+        let prev = path.len() - 1;
+        let pdf_fwd = to_area(pdf, &path[prev].p, &p, normal);
+        let vertex_media = media.clone();
+
+        // Light subpaths stop at dispersive materials (see `li`)
+        let scattering = if from_light && rec.material.is_dispersive() {
+            None
+        } else {
+            rec.material.scatter(&r, &rec, rng)
+        };
+        let (color, scattered) = match scattering {
+            Some(scattering) => scattering,
+            None => {
+                path.push(Vertex {
+                    kind: Kind::Scattering(rec),
+                    p,
+                    normal,
+                    media: vertex_media,
+                    beta,
+                    pdf_fwd,
+                    pdf_rev: 0.0,
+                    delta: false,
+                });
+                return None;
+            }
+        };
+        let scattered = Ray {
+            wavelength: scattered.wavelength.or(r.wavelength),
+            ..scattered
+        };
+
+        // Materials scattering in discrete directions have no density
+        let wo = -r.direction.normalize();
+        let wi = scattered.direction.normalize();
+        let pdf_scattered = rec.material.pdf(&rec, &wo, &wi);
+        let delta = pdf_scattered == 0.0;
+        path[prev].pdf_rev = if delta {
+            0.0
+        } else {
+            to_area(
+                rec.material.pdf(&rec, &wi, &wo),
+                &rec.p,
+                &path[prev].p,
+                path[prev].normal,
+            )
+        };
+
+        if dot(scattered.direction, rec.normal) < 0.0 {
+            media = media.crossed(&rec);
+        }
+
+        let attenuation = wavelengths.attenuation(&r, &rec, &scattered, &color);
+        let dispersive = rec.material.is_dispersive();
+
+        path.push(Vertex {
+            kind: Kind::Scattering(rec),
+            p,
+            normal,
+            media: vertex_media,
+            beta,
+            pdf_fwd,
+            pdf_rev: 0.0,
+            delta,
+        });
+        if dispersive {
+            beta = wavelengths.single_wavelength(beta);
+        }
+        beta = beta.modulate(&attenuation);
+        pdf = pdf_scattered;
+        r = scattered;
+
+        // Russian roulette
+        if path.len() > path_length.min_depth as usize + 1 {
+            let max = beta.max_value() / start;
+            if max < path_length.roulette_threshold {
+                let survival = max / path_length.roulette_threshold;
+                if rng.random_double() >= survival {
+                    return None;
+                }
+                beta = beta * (1.0 / survival);
+            }
+        }
+    }
+
+    None
+}
+
+/// Path made of the first `s` vertices of a light subpath, connected to the first `t` vertices of
+/// a camera subpath.
+struct Connection<'a, 'b, T: Hittable, W: Wavelengths> {
+    light_path: &'b [Vertex<'a, W::Radiance>],
+    camera_path: &'b [Vertex<'a, W::Radiance>],
+    s: usize,
+    t: usize,
+    world: &'b T,
+    lights: &'b LightBvh,
+    sensor: &'b Sensor<'b>,
+    wavelengths: &'b W,
+    wavelength: Option<f64>,
+}
+
+impl<'a, 'b, T: Hittable, W: Wavelengths> Connection<'a, 'b, T, W> {
+    /// Weighted contribution of the path, and the pixel it lands on when it doesn't go through
+    /// the pixel being rendered.
+    fn contribution(&self, rng: &mut RNG) -> Option<(W::Radiance, Option<usize>)> {
+        let (s, t) = (self.s, self.t);
+        let wavelengths = self.wavelengths;
+
+        if s == 0 {
+            // The camera subpath reached an emitting surface by itself
+            let pt = &self.camera_path[t - 1];
+            let prev = &self.camera_path[t - 2];
+            let rec = match &pt.kind {
+                Kind::Scattering(rec) => rec,
+                _ => return None,
+            };
+            let ray = Ray {
+                wavelength: self.wavelength,
+                ..Ray::new(prev.p, pt.p - prev.p)
+            };
+            let emitted = wavelengths.emitted(&ray, rec);
+            if emitted.max_value() <= 0.0 {
+                return None;
+            }
+
+            // Emitters which aren't lights can only be reached this way
+            let weight = match rec.light {
+                Some(light) if self.lights.emitter_pmf(light) > 0.0 => self.mis_weight(None),
+                _ => 1.0,
+            };
+            return Some((pt.beta.modulate(&emitted) * weight, None));
+        }
+
+        if t == 1 {
+            // The light subpath is connected to a point on the lens
+            let qs = &self.light_path[s - 1];
+            if qs.delta {
+                return None;
+            }
+            let camera = self.sensor.camera;
+            let lens = camera.sample_lens(rng);
+            let to_lens = lens - qs.p;
+            let distance = to_lens.magnitude();
+            let (importance, pixel) = self.sensor.importance(&lens, &-to_lens)?;
+            let pdf = distance * distance / (camera.cos_theta(&-to_lens) * self.sensor.lens_area);
+
+            let wi = to_lens / distance;
+            let f = qs.f(&self.light_path[s - 2], &wi);
+            if f == Color::new(0.0, 0.0, 0.0) {
+                return None;
+            }
+            let transmittance = self.transmittance(qs, &wi, distance, rng);
+            if transmittance <= 0.0 {
+                return None;
+            }
+
+            let sampled = Vertex {
+                kind: Kind::Camera,
+                p: lens,
+                normal: None,
+                media: self.camera_path[0].media.clone(),
+                beta: W::Radiance::white(),
+                pdf_fwd: 0.0,
+                pdf_rev: 0.0,
+                delta: false,
+            };
+            let weight = self.mis_weight(Some(&sampled));
+            let reflectance =
+                wavelengths.scattered(&f, qs.pdf_scattering(&self.light_path[s - 2], &wi));
+            let contribution =
+                qs.beta.modulate(&reflectance) * (importance / pdf * transmittance * weight);
+            return Some((contribution, Some(pixel)));
+        }
+
+        let pt = &self.camera_path[t - 1];
+        if pt.delta {
+            return None;
+        }
+        let pt_minus = &self.camera_path[t - 2];
+
+        if s == 1 {
+            // A point is sampled on a light, as when sampling lights in the path tracer
+            let (light, pmf) = self.lights.sample(&pt.p, rng)?;
+            let sample = light.sample(&pt.p, rng)?;
+            let f = pt.f(pt_minus, &sample.direction);
+            if f == Color::new(0.0, 0.0, 0.0) {
+                return None;
+            }
+            let transmittance = self.transmittance(pt, &sample.direction, sample.distance, rng);
+            if transmittance <= 0.0 {
+                return None;
+            }
+
+            let scattering_pdf = pt.pdf_scattering(pt_minus, &sample.direction);
+
+            // Lights at infinity are only otherwise reached by paths escaping the scene
+            let weight = if self.lights.emitter_pmf(light) > 0.0 {
+                let mut sampled = Vertex {
+                    kind: Kind::Light(light),
+                    p: pt.p + sample.direction * sample.distance,
+                    normal: sample.normal,
+                    media: pt.media.clone(),
+                    beta: W::Radiance::white(),
+                    pdf_fwd: 0.0,
+                    pdf_rev: 0.0,
+                    delta: false,
+                };
+                sampled.pdf_fwd = sampled.pdf_light_origin(pt, self.lights);
+                self.mis_weight(Some(&sampled))
+            } else {
+                match sample.pdf {
+                    Some(light_pdf) => power_heuristic(pmf * light_pdf, scattering_pdf),
+                    None => 1.0,
+                }
+            };
+
+            let reflectance = wavelengths.scattered(&f, scattering_pdf);
+            let contribution = pt
+                .beta
+                .modulate(&reflectance)
+                .modulate(&wavelengths.light_sample(light, &pt.p, &sample))
+                * (transmittance * weight / pmf);
+            return Some((contribution, None));
+        }
+
+        // Both subpaths are connected with a shadow ray between their last vertices
+        let qs = &self.light_path[s - 1];
+        if qs.delta {
+            return None;
+        }
+        let offset = qs.p - pt.p;
+        let distance = offset.magnitude();
+        let direction = offset / distance;
+
+        let f_pt = pt.f(pt_minus, &direction);
+        let f_qs = qs.f(&self.light_path[s - 2], &-direction);
+        if f_pt == Color::new(0.0, 0.0, 0.0) || f_qs == Color::new(0.0, 0.0, 0.0) {
+            return None;
+        }
+        let transmittance = self.transmittance(pt, &direction, distance, rng);
+        if transmittance <= 0.0 {
+            return None;
+        }
+
+        let weight = self.mis_weight(None);
+        let contribution = pt
+            .beta
+            .modulate(&wavelengths.scattered(&f_pt, pt.pdf_scattering(pt_minus, &direction)))
+            .modulate(&wavelengths.scattered(
+                &f_qs,
+                qs.pdf_scattering(&self.light_path[s - 2], &-direction),
+            ))
+            .modulate(&qs.beta)
+            * (transmittance * weight / (distance * distance));
+        Some((contribution, None))
+    }
+
+    /// Fraction of the light going from `from` along `direction`, up to `distance`.
+    fn transmittance(
+        &self,
+        from: &Vertex<'a, W::Radiance>,
+        direction: &Vec3,
+        distance: f64,
+        rng: &mut RNG,
+    ) -> f64 {
+        let media = match &from.kind {
+            Kind::Scattering(rec) if dot(*direction, rec.normal) < 0.0 => from.media.crossed(rec),
+            _ => from.media.clone(),
+        };
+        let ray = Ray {
+            wavelength: self.wavelength,
+            ..Ray::new(from.p, *direction)
+        };
+
+        // Stop just before the other vertex, which may be on a surface
+        transmittance(&ray, distance - 0.001, self.world, &media, rng)
+    }
+
+    /*
+    Weight of the path among every way of sampling it with the power heuristic, following pbrt.
+    Moving the connection one vertex towards the light or the camera gives another strategy,
+    whose density relative to this one is the ratio of the reverse and forward densities of
+    that vertex. `sampled` is the vertex sampled for the connection when a subpath has a single
+    vertex, which replaces it.
+     */
+    fn mis_weight(&self, sampled: Option<&Vertex<'a, W::Radiance>>) -> f64 {
+        let (s, t) = (self.s, self.t);
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        let mut light: Vec<&Vertex<W::Radiance>> = self.light_path.iter().take(s).collect();
+        let mut camera: Vec<&Vertex<W::Radiance>> = self.camera_path.iter().take(t).collect();
+        if let Some(sampled) = sampled {
+            if s == 1 {
+                light = vec![sampled];
+            } else if t == 1 {
+                camera = vec![sampled];
+            }
+        }
+
+        // Forward and reverse densities, and whether each vertex is a delta, for this path
+        let densities = |v: &&Vertex<W::Radiance>| (v.pdf_fwd, v.pdf_rev, v.delta);
+        let mut light_pdfs: Vec<(f64, f64, bool)> = light.iter().map(densities).collect();
+        let mut camera_pdfs: Vec<(f64, f64, bool)> = camera.iter().map(densities).collect();
+
+        // The reverse densities at and next to the connection only depend on it
+        let pt = camera[t - 1];
+        let pt_minus = t.checked_sub(2).map(|i| camera[i]);
+        let qs = s.checked_sub(1).map(|i| light[i]);
+        let qs_minus = s.checked_sub(2).map(|i| light[i]);
+
+        camera_pdfs[t - 1].2 = false;
+        camera_pdfs[t - 1].1 = match (qs, pt_minus) {
+            (Some(qs), _) => qs.pdf(self.sensor, qs_minus, pt),
+            (None, Some(pt_minus)) => pt.pdf_light_origin(pt_minus, self.lights),
+            (None, None) => 0.0,
+        };
+        if let Some(pt_minus) = pt_minus {
+            camera_pdfs[t - 2].1 = match qs {
+                Some(qs) => pt.pdf(self.sensor, Some(qs), pt_minus),
+                None => pt.pdf_light(pt_minus),
+            };
+        }
+        if let Some(qs) = qs {
+            light_pdfs[s - 1].2 = false;
+            light_pdfs[s - 1].1 = pt.pdf(self.sensor, pt_minus, qs);
+        }
+        if let (Some(qs), Some(qs_minus)) = (qs, qs_minus) {
+            light_pdfs[s - 2].1 = qs.pdf(self.sensor, Some(pt), qs_minus);
+        }
+
+        // Deltas have no density, and can't be connected: they only cancel out in the ratios
+        let remap = |pdf: f64| if pdf == 0.0 { 1.0 } else { pdf };
+        let mut sum = 0.0;
+
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            // Light subpaths would have to be scattered at this vertex for the next strategies
+            if camera[i].is_dispersive() {
+                break;
+            }
+            ratio *= remap(camera_pdfs[i].1) / remap(camera_pdfs[i].0);
+            if !camera_pdfs[i].2 && !camera_pdfs[i - 1].2 {
+                sum += ratio * ratio;
+            }
+        }
+
+        let mut ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light_pdfs[i].1) / remap(light_pdfs[i].0);
+            let delta_light = if i > 0 {
+                light_pdfs[i - 1].2
+            } else {
+                light[0].normal.is_none()
+            };
+            if !light_pdfs[i].2 && !delta_light {
+                sum += ratio * ratio;
+            }
+        }
+
+        1.0 / (1.0 + sum)
+    }
+}
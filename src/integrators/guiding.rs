@@ -0,0 +1,415 @@
+use std::f64::consts::PI;
+use std::sync::Mutex;
+
+use cgmath::InnerSpace;
+
+use crate::aabb::AABB;
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/// Probability of sampling the learned light instead of the material at a scattering event.
+const GUIDED_FRACTION: f64 = 0.5;
+
+/// Number of samples above which a region of space is split, for a pass of 1 sample per pixel.
+/// It grows with the square root of the samples per pixel of the pass.
+const SPATIAL_THRESHOLD: f64 = 12000.0;
+
+/// Fraction of the light of a region above which a cell of directions is subdivided.
+const DIRECTIONAL_THRESHOLD: f64 = 0.01;
+
+/// Maximum depth of the quadtrees of directions.
+const MAX_DEPTH: u32 = 20;
+
+/// Node of a quadtree of directions, with the light recorded in each of its quadrants.
+#[derive(Clone)]
+struct QuadNode {
+    sums: [f64; 4],
+    /// Index of the node subdividing each quadrant, 0 for quadrants which are leaves.
+    children: [usize; 4],
+}
+
+impl QuadNode {
+    fn new() -> QuadNode {
+        QuadNode {
+            sums: [0.0; 4],
+            children: [0; 4],
+        }
+    }
+}
+
+/// Distribution of the light arriving in a region of space, as a quadtree over the square onto
+/// which the sphere of directions is mapped. Each cell holds the light recorded in its
+/// directions, and cells are subdivided where a lot of light arrives.
+#[derive(Clone)]
+struct DirectionTree {
+    nodes: Vec<QuadNode>,
+    /// Number of records.
+    samples: u64,
+}
+
+/// Point of the unit square where `direction` is mapped, with the cosine of its polar angle and
+/// its azimuth. The mapping preserves areas.
+fn to_square(direction: &Vec3) -> (f64, f64) {
+    let direction = direction.normalize();
+    let cos_theta = direction.z.clamp(-1.0, 1.0);
+    let phi = direction.y.atan2(direction.x);
+    let phi = if phi < 0.0 { phi + 2.0 * PI } else { phi };
+
+    (
+        ((cos_theta + 1.0) / 2.0).min(1.0 - f64::EPSILON),
+        (phi / (2.0 * PI)).min(1.0 - f64::EPSILON),
+    )
+}
+
+fn from_square(x: f64, y: f64) -> Vec3 {
+    let cos_theta = 2.0 * x - 1.0;
+    let sin_theta = f64::max(0.0, 1.0 - cos_theta * cos_theta).sqrt();
+    let phi = 2.0 * PI * y;
+
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Quadrant of the unit square containing `(x, y)`, and the position in that quadrant.
+fn quadrant(x: f64, y: f64) -> (usize, f64, f64) {
+    let right = x >= 0.5;
+    let top = y >= 0.5;
+    (
+        right as usize + 2 * top as usize,
+        2.0 * x - right as u32 as f64,
+        2.0 * y - top as u32 as f64,
+    )
+}
+
+impl DirectionTree {
+    fn new() -> DirectionTree {
+        DirectionTree {
+            nodes: vec![QuadNode::new()],
+            samples: 0,
+        }
+    }
+
+    fn total(&self) -> f64 {
+        self.nodes[0].sums.iter().sum()
+    }
+
+    fn record(&mut self, direction: &Vec3, value: f64) {
+        let (mut x, mut y) = to_square(direction);
+        let mut node = 0;
+        loop {
+            let (i, qx, qy) = quadrant(x, y);
+            self.nodes[node].sums[i] += value;
+            node = self.nodes[node].children[i];
+            if node == 0 {
+                break;
+            }
+            x = qx;
+            y = qy;
+        }
+        self.samples += 1;
+    }
+
+    /// Samples a direction in proportion to the recorded light, or uniformly if there is none.
+    fn sample(&self, rng: &mut RNG) -> Vec3 {
+        if self.total() <= 0.0 {
+            return from_square(rng.random_double(), rng.random_double());
+        }
+
+        let (mut x, mut y) = (0.0, 0.0);
+        let mut size = 1.0;
+        let mut node = 0;
+        loop {
+            let sums = &self.nodes[node].sums;
+            let mut u = rng.random_double() * sums.iter().sum::<f64>();
+            let mut i = 0;
+            while i < 3 && (u >= sums[i] || sums[i] <= 0.0) {
+                u -= sums[i];
+                i += 1;
+            }
+
+            size /= 2.0;
+            x += size * (i % 2) as f64;
+            y += size * (i / 2) as f64;
+            node = self.nodes[node].children[i];
+            if node == 0 {
+                return from_square(
+                    x + size * rng.random_double(),
+                    y + size * rng.random_double(),
+                );
+            }
+        }
+    }
+
+    /// Density (per solid angle) with which `sample` picks `direction`.
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        if self.total() <= 0.0 {
+            return 1.0 / (4.0 * PI);
+        }
+
+        let (mut x, mut y) = to_square(direction);
+        let mut pdf = 1.0;
+        let mut node = 0;
+        loop {
+            let (i, qx, qy) = quadrant(x, y);
+            let sums = &self.nodes[node].sums;
+            let total: f64 = sums.iter().sum();
+            if sums[i] <= 0.0 {
+                return 0.0;
+            }
+            pdf *= 4.0 * sums[i] / total;
+
+            node = self.nodes[node].children[i];
+            if node == 0 {
+                return pdf / (4.0 * PI);
+            }
+            x = qx;
+            y = qy;
+        }
+    }
+
+    /// Empty tree to record the next pass into, subdividing the cells which received more than
+    /// `DIRECTIONAL_THRESHOLD` of the light, and merging the others.
+    fn refined(&self) -> DirectionTree {
+        let mut tree = DirectionTree::new();
+        let total = self.total();
+        if total > 0.0 {
+            tree.nodes.clear();
+            self.refine(Some(0), self.nodes[0].sums, 1, total, &mut tree);
+        }
+
+        tree
+    }
+
+    /// Adds the refined version of `node` (or of a leaf holding `sums`) to `tree`, returning
+    /// its index.
+    fn refine(
+        &self,
+        node: Option<usize>,
+        sums: [f64; 4],
+        depth: u32,
+        total: f64,
+        tree: &mut DirectionTree,
+    ) -> usize {
+        let index = tree.nodes.len();
+        tree.nodes.push(QuadNode::new());
+
+        for (i, sum) in sums.iter().enumerate() {
+            if sum / total <= DIRECTIONAL_THRESHOLD || depth >= MAX_DEPTH {
+                continue;
+            }
+
+            // Leaves are split with their light spread evenly
+            let child = node
+                .map(|node| self.nodes[node].children[i])
+                .filter(|&child| child != 0);
+            let child_sums = match child {
+                Some(child) => self.nodes[child].sums,
+                None => [sum / 4.0; 4],
+            };
+            tree.nodes[index].children[i] = self.refine(child, child_sums, depth + 1, total, tree);
+        }
+
+        index
+    }
+}
+
+/// Region of space, with the light learned at the previous pass and recorded at the current one.
+struct Leaf {
+    aabb: AABB,
+    sampling: DirectionTree,
+    recording: Mutex<DirectionTree>,
+}
+
+enum SpatialNode {
+    Interior {
+        axis: usize,
+        split: f64,
+        children: [usize; 2],
+    },
+    Leaf(Leaf),
+}
+
+/// Distribution of the light arriving at each point of the scene, learned from the paths traced
+/// during training passes, to sample the directions where light comes from (practical path
+/// guiding, with an SD-tree).
+///
+/// Space is divided by a binary tree, splitting regions in half where many paths scatter, and
+/// each region holds a quadtree of the light arriving from each direction. After each training
+/// pass, the light recorded during the pass is used for sampling, and the trees are refined to
+/// record the next pass.
+pub struct Guide {
+    nodes: Vec<SpatialNode>,
+    recording: bool,
+}
+
+impl Guide {
+    /// Guide over `aabb`, usually the bounds of the scene, with nothing learned yet.
+    pub fn new(aabb: AABB) -> Guide {
+        Guide {
+            nodes: vec![SpatialNode::Leaf(Leaf {
+                aabb,
+                sampling: DirectionTree::new(),
+                recording: Mutex::new(DirectionTree::new()),
+            })],
+            recording: true,
+        }
+    }
+
+    fn leaf(&self, p: &Point3) -> &Leaf {
+        let mut node = 0;
+        loop {
+            match &self.nodes[node] {
+                SpatialNode::Interior {
+                    axis,
+                    split,
+                    children,
+                } => node = children[(p[*axis] >= *split) as usize],
+                SpatialNode::Leaf(leaf) => return leaf,
+            }
+        }
+    }
+
+    /// Whether paths record the light arriving at their scattering events.
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Uses the light recorded during the pass of `samples_per_pixel` samples that just ended,
+    /// and prepares the trees for the next pass. Regions where many paths scattered are split.
+    pub fn end_pass(&mut self, samples_per_pixel: u32) {
+        let threshold = SPATIAL_THRESHOLD * (samples_per_pixel as f64).sqrt();
+
+        let mut stack: Vec<usize> = (0..self.nodes.len())
+            .filter(|&i| matches!(self.nodes[i], SpatialNode::Leaf(_)))
+            .collect();
+        for &i in &stack {
+            if let SpatialNode::Leaf(leaf) = &mut self.nodes[i] {
+                let recorded =
+                    std::mem::replace(leaf.recording.get_mut().unwrap(), DirectionTree::new());
+                *leaf.recording.get_mut().unwrap() = recorded.refined();
+                leaf.sampling = recorded;
+            }
+        }
+
+        while let Some(i) = stack.pop() {
+            let leaf = match &self.nodes[i] {
+                SpatialNode::Leaf(leaf) if leaf.sampling.samples as f64 > threshold => leaf,
+                _ => continue,
+            };
+
+            // Split along the longest axis, with both halves starting from the same light
+            let (min, max) = (leaf.aabb.min(), leaf.aabb.max());
+            let extent = max - min;
+            let axis = if extent.x > extent.y && extent.x > extent.z {
+                0
+            } else if extent.y > extent.z {
+                1
+            } else {
+                2
+            };
+            let split = (min[axis] + max[axis]) / 2.0;
+            let (mut lower_max, mut upper_min) = (max, min);
+            lower_max[axis] = split;
+            upper_min[axis] = split;
+
+            let mut sampling = leaf.sampling.clone();
+            sampling.samples /= 2;
+            let recording = leaf.recording.lock().unwrap().clone();
+            let first = self.nodes.len();
+            for aabb in [AABB::new(min, lower_max), AABB::new(upper_min, max)] {
+                self.nodes.push(SpatialNode::Leaf(Leaf {
+                    aabb,
+                    sampling: sampling.clone(),
+                    recording: Mutex::new(recording.clone()),
+                }));
+            }
+            self.nodes[i] = SpatialNode::Interior {
+                axis,
+                split,
+                children: [first, first + 1],
+            };
+            stack.extend([first, first + 1]);
+        }
+    }
+
+    /// Stops recording, once the training passes are over.
+    pub fn end_training(&mut self) {
+        self.recording = false;
+    }
+
+    /// Records the light `radiance` arriving at `p` from `direction`, which was sampled with
+    /// density `pdf`.
+    pub fn record(&self, p: &Point3, direction: &Vec3, radiance: f64, pdf: f64) {
+        if self.recording && radiance.is_finite() && pdf > 0.0 {
+            let leaf = self.leaf(p);
+            leaf.recording
+                .lock()
+                .unwrap()
+                .record(direction, radiance / pdf);
+        }
+    }
+
+    /// Direction scattered at `rec` towards `wo`, given the `scattering` sampled by its
+    /// material. Half of the time, the direction is instead sampled from the light learned
+    /// around `rec`. Both are weighted by the density of the mixture of the two, which is
+    /// returned along (0 for directions the material scatters into discretely).
+    pub fn scatter(
+        &self,
+        rec: &HitRecord,
+        wo: &Vec3,
+        scattering: (Color, Ray),
+        rng: &mut RNG,
+    ) -> (Color, Ray, f64) {
+        let (color, scattered) = scattering;
+        if !is_guided(rec, wo) {
+            let pdf = rec.material.pdf(rec, wo, &scattered.direction.normalize());
+            return (color, scattered, pdf);
+        }
+
+        if rng.random_double() < GUIDED_FRACTION {
+            let scattered = Ray {
+                direction: self.leaf(&rec.p).sampling.sample(rng),
+                ..scattered
+            };
+            let wi = scattered.direction.normalize();
+            let pdf = self.pdf(rec, wo, &wi);
+            if pdf <= 0.0 {
+                return (Color::new(0.0, 0.0, 0.0), scattered, 0.0);
+            }
+
+            return (rec.material.eval(rec, wo, &wi) / pdf, scattered, pdf);
+        }
+
+        let wi = scattered.direction.normalize();
+        let material_pdf = rec.material.pdf(rec, wo, &wi);
+        // Discrete directions of the material can't be sampled by the guide
+        if material_pdf <= 0.0 {
+            return (color / (1.0 - GUIDED_FRACTION), scattered, 0.0);
+        }
+
+        // The weight of the material is kept, as its pdf may only approximate the density of
+        // the directions it scatters into (see `Coated`)
+        let pdf = self.pdf(rec, wo, &wi);
+        (color * (material_pdf / pdf), scattered, pdf)
+    }
+
+    /// Density (per solid angle) with which `scatter` picks `wi` when leaving towards `wo`.
+    pub fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f64 {
+        let material_pdf = rec.material.pdf(rec, wo, wi);
+        if !is_guided(rec, wo) {
+            return material_pdf;
+        }
+
+        GUIDED_FRACTION * self.leaf(&rec.p).sampling.pdf(wi)
+            + (1.0 - GUIDED_FRACTION) * material_pdf
+    }
+}
+
+/// Whether directions are guided at `rec`, for materials which scatter light around, with a
+/// density that guided directions can be weighed against.
+fn is_guided(rec: &HitRecord, wo: &Vec3) -> bool {
+    !rec.material.is_interface()
+        && !rec.material.is_dispersive()
+        && rec.material.pdf(rec, wo, &rec.normal) > 0.0
+}
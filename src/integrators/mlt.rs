@@ -0,0 +1,112 @@
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+
+use crate::base::*;
+use crate::integrators::{luminance, Film};
+use crate::random::RNG;
+
+/// Standard deviation of the small steps, which move each random number of a path.
+const SIGMA: f64 = 0.01;
+
+/// Probability of a large step, drawing every random number of a path again.
+const LARGE_STEP_PROBABILITY: f64 = 0.3;
+
+/// Settings of Metropolis light transport.
+#[derive(Copy, Clone)]
+pub struct Metropolis {
+    /// Number of Markov chains, run independently.
+    pub chains: u64,
+    /// Number of paths traced to estimate the brightness of the image, and to start the chains.
+    pub bootstrap_samples: u64,
+    /// Total number of mutations, over every chain.
+    pub mutations: u64,
+}
+
+/// Renders the image with primary sample space Metropolis light transport onto `film`. Like the
+/// rendered pixels, each pixel is the sum over its samples, taking the number of mutations per
+/// pixel as the number of samples.
+///
+/// `sample` traces a path through the image position `(s, t)`, drawing all its random numbers
+/// from the given `RNG`. Each of the independent chains mutates these numbers, and moves to the
+/// mutated path with a probability based on how bright it is, so that paths are sampled in
+/// proportion to their luminance. Once a bright but hard to find path is found, e.g. through a
+/// small opening, nearby paths are explored by small mutations, while large mutations keep
+/// finding other paths. The paths are weighted by the average luminance of the image, which is
+/// estimated beforehand from `bootstrap_samples` independent paths.
+pub fn render(
+    film: &Film,
+    settings: Metropolis,
+    sample: &(dyn Fn(f64, f64, &mut RNG) -> Color + Sync),
+    bar: &ProgressBar,
+) {
+    // Paths through a position of the film, which covers s in [0, width / (width - 1)]
+    let scale = film.width as f64 / (film.width - 1) as f64;
+    let path = |rng: &mut RNG| {
+        let s = rng.random_double() * scale;
+        let t = rng.random_double();
+        (sample(s, t, rng), film.pixel(s, t))
+    };
+
+    // Bootstrap
+    let weights: Vec<f64> = (0..settings.bootstrap_samples)
+        .into_par_iter()
+        .map(|seed| {
+            let mut rng = RNG::new_primary(seed, SIGMA, LARGE_STEP_PROBABILITY);
+            luminance(&path(&mut rng).0)
+        })
+        .collect();
+    let cdf: Vec<f64> = weights
+        .iter()
+        .scan(0.0, |sum, weight| {
+            *sum += weight;
+            Some(*sum)
+        })
+        .collect();
+    let total = cdf.last().copied().unwrap_or(0.0);
+    if total <= 0.0 {
+        bar.finish();
+        return;
+    }
+    let b = total / settings.bootstrap_samples as f64;
+
+    (0..settings.chains).into_par_iter().for_each(|chain| {
+        let mut decisions = RNG::new();
+        let mutations = settings.mutations / settings.chains
+            + u64::from(chain < settings.mutations % settings.chains);
+
+        // Start from a bootstrap path picked according to its luminance, traced again
+        let u = decisions.random_double() * total;
+        let seed = cdf.partition_point(|&sum| sum <= u).min(cdf.len() - 1);
+        let mut rng = RNG::new_primary(seed as u64, SIGMA, LARGE_STEP_PROBABILITY);
+        let (mut current, mut current_pixel) = path(&mut rng);
+        rng.reseed();
+
+        for _ in 0..mutations {
+            rng.mutate();
+            let (proposed, proposed_pixel) = path(&mut rng);
+
+            // Both paths are splatted, weighted by the probability of moving to the proposal
+            let accept = f64::min(1.0, luminance(&proposed) / luminance(&current));
+            if let (true, Some(pixel)) = (accept > 0.0, proposed_pixel) {
+                film.splat(pixel, &(proposed * (accept * b / luminance(&proposed))));
+            }
+            if let (true, Some(pixel)) = (accept < 1.0, current_pixel) {
+                film.splat(
+                    pixel,
+                    &(current * ((1.0 - accept) * b / luminance(&current))),
+                );
+            }
+
+            if decisions.random_double() < accept {
+                current = proposed;
+                current_pixel = proposed_pixel;
+                rng.accept();
+            } else {
+                rng.reject();
+            }
+        }
+
+        bar.inc(1);
+    });
+    bar.finish();
+}
@@ -0,0 +1,143 @@
+use std::sync::Mutex;
+
+use clap::ArgEnum;
+
+use crate::base::*;
+use crate::camera::Camera;
+use crate::hit::Hittable;
+use crate::materials::medium::MediumStack;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+pub mod bdpt;
+pub mod guiding;
+pub mod mlt;
+pub mod path;
+pub mod sppm;
+
+/// Algorithm used to compute the light reaching the camera.
+#[derive(ArgEnum, Clone, Copy)]
+pub enum Integrator {
+    /// Paths traced from the camera, sampling lights at each scattering event
+    Path,
+    /// Bidirectional path tracing, connecting paths from the camera to paths from the lights
+    Bdpt,
+    /// Stochastic progressive photon mapping, gathering photons traced from the lights around
+    /// the surfaces seen by the camera (RGB only)
+    Sppm,
+    /// Primary sample space Metropolis light transport, mutating the random numbers of the path
+    /// tracer to explore bright paths
+    Mlt,
+}
+
+/// Light landing on any pixel, such as light traced from the lights straight to the camera, which
+/// is added to the image once every pixel is rendered.
+pub struct Film {
+    width: u32,
+    height: u32,
+    splats: Vec<Mutex<Color>>,
+}
+
+impl Film {
+    /// Film of `width` by `height` pixels, with pixel `(i, j)` covering the image positions
+    /// `s` in `[i, i + 1] / (width - 1)` and `t` in `[j, j + 1] / height`, like the rendered
+    /// pixels.
+    pub fn new(width: u32, height: u32) -> Film {
+        Film {
+            width,
+            height,
+            splats: (0..width * height)
+                .map(|_| Mutex::new(Color::new(0.0, 0.0, 0.0)))
+                .collect(),
+        }
+    }
+
+    /// Index of the pixel at an image position, in the order pixels are rendered (top row
+    /// first), or `None` outside the image.
+    fn pixel(&self, s: f64, t: f64) -> Option<usize> {
+        if s < 0.0 || t < 0.0 {
+            return None;
+        }
+
+        let i = (s * (self.width - 1) as f64) as u32;
+        let j = (t * self.height as f64) as u32;
+        if i >= self.width || j >= self.height {
+            return None;
+        }
+
+        Some(((self.height - 1 - j) * self.width + i) as usize)
+    }
+
+    /// Area covered by the pixels, projected at a distance of 1 from the lens.
+    fn area(&self, camera: &Camera) -> f64 {
+        camera.image_area() * self.width as f64 / (self.width - 1) as f64
+    }
+
+    fn splat(&self, pixel: usize, color: &Color) {
+        *self.splats[pixel].lock().unwrap() += *color;
+    }
+
+    /// Light splatted onto each pixel, in the order pixels are rendered. Like the rendered
+    /// pixels, it is the sum over the samples of each pixel.
+    pub fn into_splats(self) -> Vec<Color> {
+        self.splats
+            .into_iter()
+            .map(|splat| splat.into_inner().unwrap())
+            .collect()
+    }
+}
+
+/// Limits on the length of paths.
+#[derive(Copy, Clone)]
+pub struct PathLength {
+    /// Number of bounces after which paths are always terminated.
+    pub max_depth: u32,
+    /// Number of bounces before paths can be terminated by Russian roulette.
+    pub min_depth: u32,
+    /// Paths whose throughput falls below this value are randomly terminated, with a probability
+    /// growing as the throughput decreases. Surviving paths are weighted up to compensate.
+    pub roulette_threshold: f64,
+}
+
+/// Brightness of a color, as a scalar for Metropolis light transport and path guiding.
+fn luminance(color: &Color) -> f64 {
+    f64::max(0.0, 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z)
+}
+
+/// Weight of a sample taken with density `pdf`, when the same light can also be reached with a
+/// strategy of density `other_pdf`.
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    pdf * pdf / (pdf * pdf + other_pdf * other_pdf)
+}
+
+/// Fraction of the light going along `r` from its origin up to `t_max`, e.g. for a shadow ray
+/// towards a light. The ray is attenuated by the media it goes through, and blocked by surfaces
+/// other than medium interfaces.
+pub fn transmittance<T: Hittable>(
+    r: &Ray,
+    t_max: f64,
+    world: &T,
+    media: &MediumStack,
+    rng: &mut RNG,
+) -> f64 {
+    let mut media = media.clone();
+    let mut t_min = 0.001;
+    let mut transmittance = 1.0;
+
+    loop {
+        let surface = world.hit(r, t_min, t_max, rng);
+        if let Some(medium) = media.current() {
+            let t_end = surface.as_ref().map_or(t_max, |rec| rec.t);
+            transmittance *= medium.transmittance(r, t_min, t_end, rng);
+        }
+
+        match surface {
+            Some(rec) if rec.material.is_interface() => {
+                media = media.crossed(&rec);
+                t_min = rec.t + 0.001;
+            }
+            Some(_) => return 0.0,
+            None => return transmittance,
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use cgmath::{dot, InnerSpace};
+
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::hit::Hittable;
+use crate::integrators::guiding::Guide;
+use crate::integrators::{luminance, power_heuristic, transmittance, PathLength};
+use crate::lights::bvh::LightBvh;
+use crate::materials::medium::MediumStack;
+use crate::random::RNG;
+use crate::ray::Ray;
+use crate::spectrum::{Radiance, Wavelengths};
+
+#[allow(clippy::too_many_arguments)]
+pub fn ray_color<T: Hittable, W: Wavelengths>(
+    mut r: Ray,
+    world: &T,
+    background: &Background,
+    lights: &LightBvh,
+    wavelengths: &W,
+    media: &MediumStack,
+    guide: Option<&Guide>,
+    path_length: PathLength,
+    rng: &mut RNG,
+) -> W::Radiance {
+    let mut radiance = W::Radiance::black();
+    let mut throughput = W::Radiance::white();
+    let mut media = media.clone();
+    // Position and density of the last scattering event, for lights hit by the path
+    let mut last_scatter: Option<(Point3, f64)> = None;
+    // Scattering events whose incident light is recorded by the guide, with the light found so
+    // far and the throughput after them
+    let mut recorded: Vec<(Point3, Vec3, f64, Color, W::Radiance)> = Vec::new();
+
+    for depth in 0..path_length.max_depth {
+        let surface = world.hit(&r, 0.001, f64::INFINITY, rng);
+
+        // The path may interact with the medium it is in before reaching the surface
+        let interaction = media.current().and_then(|medium| {
+            let t_max = surface.as_ref().map_or(f64::INFINITY, |rec| rec.t);
+            medium
+                .sample_interaction(&r, 0.0, t_max, rng)
+                .map(|t| medium.interaction(&r, t))
+        });
+
+        let rec = match interaction.or(surface) {
+            Some(rec) => rec,
+            None => {
+                let mut escaped = background(&r);
+                // Lights at infinity are also sampled at the last scattering event
+                for (light, pmf) in lights.infinite() {
+                    let weight = match last_scatter {
+                        Some((p, scattering_pdf)) => {
+                            power_heuristic(scattering_pdf, pmf * light.pdf(&p, &r.direction))
+                        }
+                        None => 1.0,
+                    };
+                    escaped += light.escaped(&r.direction) * weight;
+                }
+                radiance = radiance + throughput.modulate(&wavelengths.illuminant(&escaped));
+                break;
+            }
+        };
+
+        let emitted = wavelengths.emitted(&r, &rec);
+
+        // Lights are also reached by sampling them at the last scattering event, so their
+        // emission is weighted against it
+        let emitted = match (rec.light, last_scatter) {
+            (Some(light), Some((p, scattering_pdf))) => {
+                let light_pdf = lights.pmf(&p, light) * light.pdf(&p, &r.direction);
+                emitted * power_heuristic(scattering_pdf, light_pdf)
+            }
+            _ => emitted,
+        };
+        let emitted =
+            emitted + direct_light(&r, &rec, world, lights, wavelengths, &media, guide, rng);
+        radiance = radiance + throughput.modulate(&emitted);
+
+        let scattering = match rec.material.scatter(&r, &rec, rng) {
+            Some(scattering) => scattering,
+            None => break,
+        };
+        let wo = -r.direction.normalize();
+        let (color, scattered, scattering_pdf) = match guide {
+            Some(guide) => guide.scatter(&rec, &wo, scattering, rng),
+            None => {
+                let (color, scattered) = scattering;
+                let pdf = rec
+                    .material
+                    .pdf(&rec, &wo, &scattered.direction.normalize());
+                (color, scattered, pdf)
+            }
+        };
+
+        // Once a path is restricted to a wavelength, it keeps it for every bounce
+        let scattered = Ray {
+            wavelength: scattered.wavelength.or(r.wavelength),
+            ..scattered
+        };
+        if rec.material.is_dispersive() {
+            throughput = wavelengths.single_wavelength(throughput);
+        }
+        let attenuation = wavelengths.attenuation(&r, &rec, &scattered, &color);
+        throughput = throughput.modulate(&attenuation);
+
+        if dot(scattered.direction, rec.normal) < 0.0 {
+            media = media.crossed(&rec);
+        }
+
+        // Paths going through a medium interface keep the last actual scattering
+        if !rec.material.is_interface() {
+            last_scatter = if scattering_pdf > 0.0 {
+                Some((rec.p, scattering_pdf))
+            } else {
+                None
+            };
+        }
+
+        if guide.is_some_and(|guide| guide.is_recording()) && scattering_pdf > 0.0 {
+            recorded.push((
+                rec.p,
+                scattered.direction,
+                scattering_pdf,
+                wavelengths.to_rgb(&radiance),
+                throughput,
+            ));
+        }
+
+        r = scattered;
+
+        // Russian roulette
+        if depth + 1 >= path_length.min_depth {
+            let max = throughput.max_value();
+            if max < path_length.roulette_threshold {
+                let survival = max / path_length.roulette_threshold;
+                if rng.random_double() >= survival {
+                    break;
+                }
+                throughput = throughput * (1.0 / survival);
+            }
+        }
+    }
+
+    // Light arriving at each recorded scattering event is what the path found after it
+    if let Some(guide) = guide {
+        let total = wavelengths.to_rgb(&radiance);
+        for (p, direction, pdf, found, throughput) in recorded {
+            let throughput = luminance(&wavelengths.to_rgb(&throughput));
+            if throughput > 0.0 {
+                guide.record(
+                    &p,
+                    &direction,
+                    luminance(&(total - found)) / throughput,
+                    pdf,
+                );
+            }
+        }
+    }
+
+    radiance
+}
+
+/// Light reaching `rec` directly from one of the lights and scattered back along `r`. The light
+/// is picked according to its estimated contribution, and sampled with a shadow ray.
+#[allow(clippy::too_many_arguments)]
+fn direct_light<T: Hittable, W: Wavelengths>(
+    r: &Ray,
+    rec: &HitRecord,
+    world: &T,
+    lights: &LightBvh,
+    wavelengths: &W,
+    media: &MediumStack,
+    guide: Option<&Guide>,
+    rng: &mut RNG,
+) -> W::Radiance {
+    let (light, pmf) = match lights.sample(&rec.p, rng) {
+        Some(light) => light,
+        None => return W::Radiance::black(),
+    };
+    let sample = match light.sample(&rec.p, rng) {
+        Some(sample) => sample,
+        None => return W::Radiance::black(),
+    };
+
+    let wo = -r.direction.normalize();
+    let f = rec.material.eval(rec, &wo, &sample.direction);
+    if f == Color::new(0.0, 0.0, 0.0) {
+        return W::Radiance::black();
+    }
+    let material_pdf = rec.material.pdf(rec, &wo, &sample.direction);
+
+    let shadow_ray = Ray {
+        wavelength: r.wavelength,
+        ..Ray::new(rec.p, sample.direction)
+    };
+    let media = if dot(sample.direction, rec.normal) < 0.0 {
+        media.crossed(rec)
+    } else {
+        media.clone()
+    };
+    // Stop the shadow ray just before the light, which may be a surface
+    let transmittance = transmittance(&shadow_ray, sample.distance - 0.001, world, &media, rng);
+    if transmittance <= 0.0 {
+        return W::Radiance::black();
+    }
+
+    // Lights which paths can hit are also reached by scattering, so the sample is weighted
+    // against it
+    let weight = match sample.pdf {
+        Some(light_pdf) => {
+            let scattering_pdf = match guide {
+                Some(guide) => guide.pdf(rec, &wo, &sample.direction),
+                None => material_pdf,
+            };
+            power_heuristic(pmf * light_pdf, scattering_pdf)
+        }
+        None => 1.0,
+    };
+
+    wavelengths
+        .scattered(&f, material_pdf)
+        .modulate(&wavelengths.light_sample(light, &rec.p, &sample))
+        * (transmittance * weight / pmf)
+}
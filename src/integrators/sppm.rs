@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::Mutex;
+
+use cgmath::{dot, InnerSpace};
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+
+use crate::base::*;
+use crate::camera::Camera;
+use crate::hit::hit_record::HitRecord;
+use crate::hit::Hittable;
+use crate::integrators::{transmittance, PathLength};
+use crate::lights::bvh::LightBvh;
+use crate::lights::point::window;
+use crate::materials::medium::MediumStack;
+use crate::random::RNG;
+use crate::ray::Ray;
+use crate::spectrum::Radiance;
+
+/// Radius within which photons are first gathered, in pixels at the distance of the visible
+/// point.
+const INITIAL_RADIUS: f64 = 2.0;
+
+/// Fraction of the photons gathered at an iteration which are kept when shrinking the radius.
+const ALPHA: f64 = 2.0 / 3.0;
+
+/// Surface seen by the camera through a pixel, where photons are gathered.
+struct VisiblePoint<'a> {
+    rec: HitRecord<'a>,
+    /// Unit vector towards the camera.
+    wo: Vec3,
+    /// Throughput from the camera to the point.
+    beta: Color,
+}
+
+/// Estimate of the light reaching the camera through a pixel, refined at each iteration.
+struct Pixel {
+    /// Light seen directly or through specular bounces, and directly lighting the visible points,
+    /// summed over the iterations.
+    direct: Color,
+    /// Radius within which photons are gathered, 0 until the pixel sees a surface.
+    radius: f64,
+    /// Number of photons accounted for by `flux`.
+    photons: f64,
+    /// Flux of the photons gathered within `radius` and reflected towards the camera, shrunk
+    /// along with the radius.
+    flux: Color,
+    /// Flux and number of the photons gathered at the current iteration.
+    gathered: Mutex<(Color, u64)>,
+}
+
+/// Uniform grid hashing the visible points into every cell they overlap, to find the points
+/// around a photon.
+struct Grid {
+    cell_size: f64,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+}
+
+impl Grid {
+    fn new(points: &[Option<VisiblePoint>], pixels: &[Pixel]) -> Grid {
+        // Cells the size of the average radius
+        let radii: Vec<f64> = points
+            .iter()
+            .zip(pixels)
+            .filter(|(point, _)| point.is_some())
+            .map(|(_, pixel)| pixel.radius)
+            .collect();
+        let cell_size = radii.iter().sum::<f64>() / radii.len().max(1) as f64;
+
+        let mut grid = Grid {
+            cell_size,
+            cells: HashMap::new(),
+        };
+        for (index, point) in points.iter().enumerate() {
+            if let Some(point) = point {
+                let radius = Vec3::new(1.0, 1.0, 1.0) * pixels[index].radius;
+                let (x0, y0, z0) = grid.cell(&(point.rec.p - radius));
+                let (x1, y1, z1) = grid.cell(&(point.rec.p + radius));
+                for x in x0..=x1 {
+                    for y in y0..=y1 {
+                        for z in z0..=z1 {
+                            grid.cells.entry((x, y, z)).or_default().push(index);
+                        }
+                    }
+                }
+            }
+        }
+
+        grid
+    }
+
+    fn cell(&self, p: &Point3) -> (i64, i64, i64) {
+        (
+            (p.x / self.cell_size).floor() as i64,
+            (p.y / self.cell_size).floor() as i64,
+            (p.z / self.cell_size).floor() as i64,
+        )
+    }
+
+    /// Visible points whose radius may contain `p`.
+    fn get(&self, p: &Point3) -> &[usize] {
+        self.cells.get(&self.cell(p)).map_or(&[], |cell| cell)
+    }
+}
+
+/// Renders the image with stochastic progressive photon mapping, in linear sRGB, as the sum over
+/// the `iterations` of the light reaching each pixel (like the samples of the other
+/// integrators). Pixels are in the order of the rendered image, from the top row.
+///
+/// Each iteration traces one path per pixel from the camera through specular bounces, up to a
+/// visible point on a diffuse or glossy surface which is lit directly by the lights. Then
+/// `photons` photons are traced from the lights, and those landing near the visible points after
+/// at least one bounce are gathered as indirect light. The gathering radius of each pixel
+/// shrinks as photons are gathered, so that the estimate converges, even for caustics which
+/// paths from the camera can't find.
+///
+/// Photons come from the lights of the scene, so the background and emitting surfaces which
+/// aren't lights are only seen directly or through specular bounces, and lights at infinity only
+/// light surfaces directly. Media scatter paths and photons without gathering them.
+#[allow(clippy::too_many_arguments)]
+pub fn render<T: Hittable>(
+    world: &T,
+    background: &Background,
+    lights: &LightBvh,
+    camera: &Camera,
+    width: u32,
+    height: u32,
+    iterations: u32,
+    photons: u64,
+    path_length: PathLength,
+    bar: &ProgressBar,
+) -> Vec<Color> {
+    let mut pixels: Vec<Pixel> = (0..width * height)
+        .map(|_| Pixel {
+            direct: Color::black(),
+            radius: 0.0,
+            photons: 0.0,
+            flux: Color::black(),
+            gathered: Mutex::new((Color::black(), 0)),
+        })
+        .collect();
+    let media = MediumStack::from(camera.medium());
+    // Size of a pixel at a distance of 1 from the camera
+    let footprint = (camera.image_area() / (width * height) as f64).sqrt();
+
+    for _ in 0..iterations {
+        // Visible points
+        let points: Vec<Option<VisiblePoint>> = pixels
+            .par_iter_mut()
+            .enumerate()
+            .map_init(RNG::new, |rng, (index, pixel)| {
+                let i = index as u32 % width;
+                let j = height - 1 - index as u32 / width;
+                let u = ((i as f64) + rng.random_double()) / (width - 1) as f64;
+                let v = ((j as f64) + rng.random_double()) / height as f64;
+
+                let r = camera.get_ray(u, v, rng);
+                let (direct, point) =
+                    visible_point(r, world, background, lights, &media, path_length, rng);
+                pixel.direct += direct;
+
+                let (point, distance) = point?;
+                if pixel.radius == 0.0 {
+                    pixel.radius = INITIAL_RADIUS * footprint * distance;
+                }
+                Some(point)
+            })
+            .collect();
+
+        // Photons
+        let grid = Grid::new(&points, &pixels);
+        (0..photons)
+            .into_par_iter()
+            .for_each_init(RNG::new, |rng, _| {
+                trace_photon(world, lights, &media, path_length, rng, |p, wi, beta| {
+                    for &index in grid.get(p) {
+                        let point = points[index].as_ref().unwrap();
+                        let pixel = &pixels[index];
+                        if (point.rec.p - p).magnitude2() > pixel.radius * pixel.radius {
+                            continue;
+                        }
+
+                        // Photons carry flux per unit of projected area
+                        let cos_theta = dot(point.rec.normal, *wi).abs();
+                        if cos_theta < 1e-8 {
+                            continue;
+                        }
+                        let f = point.rec.material.eval(&point.rec, &point.wo, wi) / cos_theta;
+
+                        let mut gathered = pixel.gathered.lock().unwrap();
+                        gathered.0 += f.modulate(beta);
+                        gathered.1 += 1;
+                    }
+                });
+            });
+
+        // Shrink the radius of the pixels which gathered photons, keeping the flux density
+        for (pixel, point) in pixels.iter_mut().zip(&points) {
+            let (flux, gathered) =
+                std::mem::replace(pixel.gathered.get_mut().unwrap(), (Color::black(), 0));
+            if let (Some(point), true) = (point, gathered > 0) {
+                let photons = pixel.photons + ALPHA * gathered as f64;
+                let radius = pixel.radius * (photons / (pixel.photons + gathered as f64)).sqrt();
+                pixel.flux = (pixel.flux + point.beta.modulate(&flux))
+                    * (radius * radius / (pixel.radius * pixel.radius));
+                pixel.photons = photons;
+                pixel.radius = radius;
+            }
+        }
+
+        bar.inc(1);
+    }
+    bar.finish();
+
+    pixels
+        .iter()
+        .map(|pixel| {
+            if pixel.radius == 0.0 {
+                return pixel.direct;
+            }
+
+            pixel.direct + pixel.flux / (photons as f64 * PI * pixel.radius * pixel.radius)
+        })
+        .collect()
+}
+
+/// Follows `r` from the camera through specular bounces and media, up to a surface where photons
+/// are gathered. Returns the light found on the way and lighting that surface directly, and the
+/// surface with the distance travelled to it.
+fn visible_point<'a, T: Hittable>(
+    mut r: Ray,
+    world: &'a T,
+    background: &Background,
+    lights: &LightBvh,
+    media: &MediumStack<'a>,
+    path_length: PathLength,
+    rng: &mut RNG,
+) -> (Color, Option<(VisiblePoint<'a>, f64)>) {
+    let mut radiance = Color::black();
+    let mut beta = Color::white();
+    let mut media = media.clone();
+    let mut distance = 0.0;
+
+    for _ in 0..path_length.max_depth {
+        let surface = world.hit(&r, 0.001, f64::INFINITY, rng);
+        let interaction = media.current().and_then(|medium| {
+            let t_max = surface.as_ref().map_or(f64::INFINITY, |rec| rec.t);
+            medium
+                .sample_interaction(&r, 0.0, t_max, rng)
+                .map(|t| medium.interaction(&r, t))
+        });
+
+        let rec = match interaction.or(surface) {
+            Some(rec) => rec,
+            None => {
+                let escaped = lights.infinite().fold(background(&r), |sum, (light, _)| {
+                    sum + light.escaped(&r.direction)
+                });
+                radiance += beta.modulate(&escaped);
+                break;
+            }
+        };
+        distance += rec.t * r.direction.magnitude();
+        radiance += beta.modulate(&rec.material.emitted(&r, &rec));
+
+        let (color, scattered) = match rec.material.scatter(&r, &rec, rng) {
+            Some(scattering) => scattering,
+            None => break,
+        };
+        let scattered = Ray {
+            wavelength: scattered.wavelength.or(r.wavelength),
+            ..scattered
+        };
+        let wo = -r.direction.normalize();
+        let pdf = rec
+            .material
+            .pdf(&rec, &wo, &scattered.direction.normalize());
+
+        // Photons are gathered on surfaces which don't scatter in discrete directions
+        if pdf > 0.0 && !rec.material.is_volumetric() && !rec.material.is_interface() {
+            radiance += beta.modulate(&direct_light(&rec, &wo, world, lights, &media, rng));
+            return (radiance, Some((VisiblePoint { rec, wo, beta }, distance)));
+        }
+
+        beta = beta.modulate(&color);
+        if dot(scattered.direction, rec.normal) < 0.0 {
+            media = media.crossed(&rec);
+        }
+        r = scattered;
+    }
+
+    (radiance, None)
+}
+
+/// Light reaching `rec` directly from one of the lights and scattered towards `wo`. Emitting
+/// surfaces aren't hit by the paths of `visible_point` once they reach a visible point, so unlike
+/// the path tracer every light is only reached with shadow rays.
+fn direct_light<T: Hittable>(
+    rec: &HitRecord,
+    wo: &Vec3,
+    world: &T,
+    lights: &LightBvh,
+    media: &MediumStack,
+    rng: &mut RNG,
+) -> Color {
+    let (light, pmf) = match lights.sample(&rec.p, rng) {
+        Some(light) => light,
+        None => return Color::black(),
+    };
+    let sample = match light.sample(&rec.p, rng) {
+        Some(sample) => sample,
+        None => return Color::black(),
+    };
+
+    let f = rec.material.eval(rec, wo, &sample.direction);
+    if f == Color::black() {
+        return Color::black();
+    }
+
+    let media = if dot(sample.direction, rec.normal) < 0.0 {
+        media.crossed(rec)
+    } else {
+        media.clone()
+    };
+    let shadow_ray = Ray::new(rec.p, sample.direction);
+    let transmittance = transmittance(&shadow_ray, sample.distance - 0.001, world, &media, rng);
+
+    f.modulate(&sample.radiance) * (transmittance / pmf)
+}
+
+/// Traces a photon from one of the lights, calling `deposit` with its position, the unit vector
+/// towards where it came from and its flux, wherever it lands on a surface after bouncing at
+/// least once. Light reaching surfaces straight from the lights is left to `direct_light`.
+fn trace_photon<T: Hittable>(
+    world: &T,
+    lights: &LightBvh,
+    media: &MediumStack,
+    path_length: PathLength,
+    rng: &mut RNG,
+    mut deposit: impl FnMut(&Point3, &Vec3, &Color),
+) {
+    let (light, pmf) = match lights.sample_emitter(rng) {
+        Some(light) => light,
+        None => return,
+    };
+    let emission = match light.sample_emission(rng) {
+        Some(emission) => emission,
+        None => return,
+    };
+    let pdf = pmf * emission.pdf_position * emission.pdf_direction;
+    if pdf <= 0.0 {
+        return;
+    }
+
+    let origin = emission.ray.origin;
+    let cos_theta = emission
+        .normal
+        .map_or(1.0, |n| dot(n, emission.ray.direction).abs());
+    let mut beta = emission.radiance * (cos_theta / pdf);
+    let mut r = emission.ray;
+    let mut media = media.clone();
+    let mut bounced = false;
+
+    for _ in 0..path_length.max_depth {
+        let surface = world.hit(&r, 0.001, f64::INFINITY, rng);
+        let interaction = media.current().and_then(|medium| {
+            let t_max = surface.as_ref().map_or(f64::INFINITY, |rec| rec.t);
+            medium
+                .sample_interaction(&r, 0.0, t_max, rng)
+                .map(|t| medium.interaction(&r, t))
+        });
+
+        let rec = match interaction.or(surface) {
+            Some(rec) => rec,
+            None => return,
+        };
+
+        if rec.material.is_interface() {
+            media = media.crossed(&rec);
+            r = Ray { origin: rec.p, ..r };
+            continue;
+        }
+
+        // Lights with a range fade out with the distance to the first surface
+        if !bounced {
+            beta *= window((rec.p - origin).magnitude(), emission.range);
+        }
+
+        let (color, scattered) = match rec.material.scatter(&r, &rec, rng) {
+            Some(scattering) => scattering,
+            None => return,
+        };
+        let scattered = Ray {
+            wavelength: scattered.wavelength.or(r.wavelength),
+            ..scattered
+        };
+        let wo = -r.direction.normalize();
+        let pdf = rec
+            .material
+            .pdf(&rec, &wo, &scattered.direction.normalize());
+
+        if bounced && pdf > 0.0 && !rec.material.is_volumetric() {
+            deposit(&rec.p, &wo, &beta);
+        }
+        bounced = true;
+
+        // Russian roulette, keeping the flux of surviving photons about constant
+        let scattered_beta = beta.modulate(&color);
+        let survival = f64::min(1.0, scattered_beta.max_value() / beta.max_value());
+        if survival <= 0.0 || rng.random_double() >= survival {
+            return;
+        }
+        beta = scattered_beta / survival;
+
+        if dot(scattered.direction, rec.normal) < 0.0 {
+            media = media.crossed(&rec);
+        }
+        r = scattered;
+    }
+}
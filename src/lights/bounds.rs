@@ -0,0 +1,132 @@
+use std::f64::consts::PI;
+
+use cgmath::{dot, InnerSpace};
+
+use crate::aabb::AABB;
+use crate::base::*;
+
+/// Cone of directions around an axis, containing every direction making an angle of cosine at
+/// least `cos_theta` with it.
+#[derive(Copy, Clone)]
+pub struct DirectionCone {
+    pub axis: Vec3,
+    pub cos_theta: f64,
+}
+
+impl DirectionCone {
+    pub fn new(axis: Vec3, cos_theta: f64) -> DirectionCone {
+        DirectionCone {
+            axis: axis.normalize(),
+            cos_theta,
+        }
+    }
+
+    /// Cone containing every direction.
+    pub fn entire_sphere() -> DirectionCone {
+        DirectionCone::new(Vec3::new(0.0, 0.0, 1.0), -1.0)
+    }
+
+    /// Smallest cone containing both cones.
+    pub fn union(a: &DirectionCone, b: &DirectionCone) -> DirectionCone {
+        let theta_a = a.cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_b = b.cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_d = dot(a.axis, b.axis).clamp(-1.0, 1.0).acos();
+
+        // One of the cones may already contain the other
+        if f64::min(theta_d + theta_b, PI) <= theta_a {
+            return *a;
+        }
+        if f64::min(theta_d + theta_a, PI) <= theta_b {
+            return *b;
+        }
+
+        let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+        if theta_o >= PI {
+            return DirectionCone::entire_sphere();
+        }
+
+        // Rotate the axis of `a` towards the axis of `b`
+        let rotation_axis = a.axis.cross(b.axis);
+        if rotation_axis.magnitude2() == 0.0 {
+            return DirectionCone::entire_sphere();
+        }
+        let axis = rotate(&a.axis, &rotation_axis.normalize(), theta_o - theta_a);
+
+        DirectionCone::new(axis, theta_o.cos())
+    }
+}
+
+/// Rotates `v` by `angle` around the unit vector `k`.
+fn rotate(v: &Vec3, k: &Vec3, angle: f64) -> Vec3 {
+    v * angle.cos() + k.cross(*v) * angle.sin() + k * dot(*k, *v) * (1.0 - angle.cos())
+}
+
+/// Conservative bounds on the light emitted by one or many lights, used to estimate how much they
+/// contribute at a point.
+///
+/// Light is emitted from inside `aabb`, with a total power `phi`. Every emitting surface has its
+/// normal inside `normals`, and emits in directions up to an angle of cosine `cos_theta_e` from
+/// its normal.
+#[derive(Copy, Clone)]
+pub struct LightBounds {
+    pub aabb: AABB,
+    pub phi: f64,
+    pub normals: DirectionCone,
+    pub cos_theta_e: f64,
+    pub two_sided: bool,
+}
+
+impl LightBounds {
+    pub fn union(a: &LightBounds, b: &LightBounds) -> LightBounds {
+        LightBounds {
+            aabb: AABB::surrounding_box(&a.aabb, &b.aabb),
+            phi: a.phi + b.phi,
+            normals: DirectionCone::union(&a.normals, &b.normals),
+            cos_theta_e: f64::min(a.cos_theta_e, b.cos_theta_e),
+            two_sided: a.two_sided || b.two_sided,
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        (self.aabb.min() + self.aabb.max()) / 2.0
+    }
+
+    /// Estimate of the light arriving at `p`. It is the power divided by the squared distance,
+    /// reduced by the smallest angle between the normals and the direction towards `p`.
+    pub fn importance(&self, p: &Point3) -> f64 {
+        let center = self.centroid();
+        let radius = (self.aabb.max() - self.aabb.min()).magnitude() / 2.0;
+
+        // Don't let the estimate blow up close to and inside the bounds
+        let offset = p - center;
+        let d2 = f64::max(offset.magnitude2(), f64::max(radius * radius / 4.0, 1e-8));
+        if offset.magnitude2() == 0.0 {
+            return self.phi / d2;
+        }
+
+        let wi = offset.normalize();
+        let cos_theta_w = dot(self.normals.axis, wi);
+        let cos_theta_w = if self.two_sided {
+            cos_theta_w.abs()
+        } else {
+            cos_theta_w
+        };
+        let theta_w = cos_theta_w.clamp(-1.0, 1.0).acos();
+        let theta_o = self.normals.cos_theta.clamp(-1.0, 1.0).acos();
+
+        // Half angle of the bounding sphere of the lights seen from `p`
+        let theta_b = if d2 <= radius * radius {
+            PI
+        } else {
+            (radius / d2.sqrt()).asin()
+        };
+
+        // Smallest angle between a normal and a direction from the lights to `p`
+        let theta = f64::max(0.0, theta_w - theta_o - theta_b);
+        if theta.cos() <= self.cos_theta_e {
+            return 0.0;
+        }
+
+        self.phi * theta.cos() / d2
+    }
+}
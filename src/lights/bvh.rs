@@ -0,0 +1,250 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::base::*;
+use crate::lights::bounds::LightBounds;
+use crate::lights::Light;
+use crate::random::RNG;
+
+enum Node {
+    Leaf {
+        light: usize,
+        bounds: LightBounds,
+    },
+    Interior {
+        left: Box<Node>,
+        right: Box<Node>,
+        bounds: LightBounds,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            Node::Leaf { bounds, .. } => bounds,
+            Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Hierarchy of the lights of a scene, to pick one light to sample at each scattering event
+/// with a probability proportional to its estimated contribution.
+///
+/// Lights are grouped by their position in space, and each node bounds the power and the
+/// emission directions of the lights under it. Picking a light walks down the tree, choosing
+/// each child with a probability proportional to its importance at the shading point. Lights at
+/// infinity, such as the sun, can't be bounded and are picked separately.
+pub struct LightBvh {
+    lights: Vec<Arc<dyn Light>>,
+    infinite: Vec<usize>,
+    bounded: Vec<usize>,
+    root: Option<Node>,
+    /// Path from the root to the leaf of each light, as one bit per level (set when going to
+    /// the right), keyed by the address of the light.
+    trails: HashMap<usize, u64>,
+}
+
+impl LightBvh {
+    pub fn new(lights: Vec<Arc<dyn Light>>) -> LightBvh {
+        let mut infinite = Vec::new();
+        let mut bounded = Vec::new();
+        for (i, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                // Lights which don't emit anything can never be picked
+                Some(bounds) if bounds.phi > 0.0 => bounded.push((i, bounds)),
+                Some(_) => {}
+                None => infinite.push(i),
+            }
+        }
+
+        let mut trails = HashMap::new();
+        let bounded_lights = bounded.iter().map(|(i, _)| *i).collect();
+        let root = if bounded.is_empty() {
+            None
+        } else {
+            Some(LightBvh::build(&lights, bounded, 0, 0, &mut trails))
+        };
+
+        LightBvh {
+            lights,
+            infinite,
+            bounded: bounded_lights,
+            root,
+            trails,
+        }
+    }
+
+    /*
+    Build the tree by recursively sorting the lights along the longest axis of the bounds of
+    their centroids, and splitting them into two halves.
+     */
+    fn build(
+        lights: &[Arc<dyn Light>],
+        mut bounded: Vec<(usize, LightBounds)>,
+        trail: u64,
+        depth: u32,
+        trails: &mut HashMap<usize, u64>,
+    ) -> Node {
+        if bounded.len() == 1 {
+            let (light, bounds) = bounded.remove(0);
+            trails.insert(key(lights[light].as_ref()), trail);
+            return Node::Leaf { light, bounds };
+        }
+
+        let centroids = bounded
+            .iter()
+            .map(|(_, bounds)| bounds.centroid())
+            .map(|c| AABB::new(c, c))
+            .reduce(|a, b| AABB::surrounding_box(&a, &b))
+            .unwrap();
+        let axis = match centroids.longest_axis() {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        };
+        bounded.sort_by(|(_, a), (_, b)| {
+            a.centroid()[axis]
+                .partial_cmp(&b.centroid()[axis])
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let right = bounded.split_off(bounded.len() / 2);
+        let left = LightBvh::build(lights, bounded, trail, depth + 1, trails);
+        let right = LightBvh::build(lights, right, trail | (1 << depth), depth + 1, trails);
+        let bounds = LightBounds::union(left.bounds(), right.bounds());
+
+        Node::Interior {
+            left: Box::new(left),
+            right: Box::new(right),
+            bounds,
+        }
+    }
+
+    /// Probability of picking one of the lights at infinity instead of walking the tree.
+    fn infinite_probability(&self) -> f64 {
+        let bvh = if self.root.is_some() { 1.0 } else { 0.0 };
+        self.infinite.len() as f64 / (self.infinite.len() as f64 + bvh)
+    }
+
+    /// Picks a light to sample at `p`, and gives the probability with which it was picked.
+    pub fn sample(&self, p: &Point3, rng: &mut RNG) -> Option<(&dyn Light, f64)> {
+        let p_infinite = self.infinite_probability();
+        let u = rng.random_double();
+        if u < p_infinite {
+            let i = ((u / p_infinite) * self.infinite.len() as f64) as usize;
+            let i = i.min(self.infinite.len() - 1);
+            let pmf = p_infinite / self.infinite.len() as f64;
+            return Some((self.lights[self.infinite[i]].as_ref(), pmf));
+        }
+
+        let mut node = self.root.as_ref()?;
+        let mut pmf = 1.0 - p_infinite;
+        loop {
+            match node {
+                Node::Leaf { light, .. } => return Some((self.lights[*light].as_ref(), pmf)),
+                Node::Interior { left, right, .. } => {
+                    let (p_left, p_right) = children_probabilities(left, right, p)?;
+                    if rng.random_double() < p_left {
+                        node = left;
+                        pmf *= p_left;
+                    } else {
+                        node = right;
+                        pmf *= p_right;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Probability with which `sample` picks `light` at `p`.
+    pub fn pmf(&self, p: &Point3, light: &dyn Light) -> f64 {
+        let trail = match self.trails.get(&key(light)) {
+            Some(trail) => *trail,
+            None => {
+                let infinite = self
+                    .infinite
+                    .iter()
+                    .any(|i| key(self.lights[*i].as_ref()) == key(light));
+                return if infinite {
+                    self.infinite_probability() / self.infinite.len() as f64
+                } else {
+                    0.0
+                };
+            }
+        };
+
+        let mut node = match &self.root {
+            Some(root) => root,
+            None => return 0.0,
+        };
+        let mut pmf = 1.0 - self.infinite_probability();
+        let mut depth = 0;
+        while let Node::Interior { left, right, .. } = node {
+            let (p_left, p_right) = match children_probabilities(left, right, p) {
+                Some(probabilities) => probabilities,
+                None => return 0.0,
+            };
+            if trail & (1 << depth) == 0 {
+                node = left;
+                pmf *= p_left;
+            } else {
+                node = right;
+                pmf *= p_right;
+            }
+            depth += 1;
+        }
+
+        pmf
+    }
+
+    /// Lights at infinity, which paths escaping the scene may reach, with the probability with
+    /// which `sample` picks them.
+    pub fn infinite(&self) -> impl Iterator<Item = (&dyn Light, f64)> {
+        let pmf = self.infinite_probability() / self.infinite.len() as f64;
+        self.infinite
+            .iter()
+            .map(move |i| (self.lights[*i].as_ref(), pmf))
+    }
+
+    /// Picks uniformly one of the lights which paths can start from, i.e. every light except
+    /// the ones at infinity, and gives the probability with which it was picked.
+    pub fn sample_emitter(&self, rng: &mut RNG) -> Option<(&dyn Light, f64)> {
+        if self.bounded.is_empty() {
+            return None;
+        }
+
+        let i = ((rng.random_double() * self.bounded.len() as f64) as usize)
+            .min(self.bounded.len() - 1);
+        Some((
+            self.lights[self.bounded[i]].as_ref(),
+            1.0 / self.bounded.len() as f64,
+        ))
+    }
+
+    /// Probability with which `sample_emitter` picks `light`.
+    pub fn emitter_pmf(&self, light: &dyn Light) -> f64 {
+        if self.trails.contains_key(&key(light)) {
+            1.0 / self.bounded.len() as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Probabilities of going down to each child at `p`, or `None` if neither contributes.
+fn children_probabilities(left: &Node, right: &Node, p: &Point3) -> Option<(f64, f64)> {
+    let left = left.bounds().importance(p);
+    let right = right.bounds().importance(p);
+    if left + right <= 0.0 {
+        return None;
+    }
+
+    Some((left / (left + right), right / (left + right)))
+}
+
+/// Identifies a light by its address, e.g. to find the light a path has hit.
+fn key(light: &dyn Light) -> usize {
+    light as *const dyn Light as *const u8 as usize
+}
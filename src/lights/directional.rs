@@ -0,0 +1,83 @@
+use std::f64::consts::PI;
+
+use cgmath::InnerSpace;
+
+use crate::base::*;
+use crate::lights::bounds::LightBounds;
+use crate::lights::{Light, LightSample};
+use crate::onb::Onb;
+use crate::random::RNG;
+
+/// Light from a source so far away that it reaches the whole scene from the same direction,
+/// like the sun. A source with an angular size is also seen by paths escaping the scene towards
+/// it, as a disc of uniform radiance.
+pub struct DirectionalLight {
+    direction: Vec3,
+    irradiance: Color,
+    cos_max: f64,
+}
+
+impl DirectionalLight {
+    /// `direction` points towards the light, and `irradiance` is the power received per unit of
+    /// area by a surface facing it. A source with a non-zero `angular_diameter` (in degrees,
+    /// about 0.53 for the sun) casts soft shadows.
+    pub fn new(direction: Vec3, irradiance: Color, angular_diameter: f64) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.normalize(),
+            irradiance,
+            cos_max: (angular_diameter / 2.0).to_radians().cos(),
+        }
+    }
+
+    /// Solid angle subtended by the source.
+    fn solid_angle(&self) -> f64 {
+        2.0 * PI * (1.0 - self.cos_max)
+    }
+
+    /// Whether `direction` points towards the source, when it has an angular size.
+    fn covers(&self, direction: &Vec3) -> bool {
+        self.cos_max < 1.0 && direction.normalize().dot(self.direction) >= self.cos_max
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &Point3, rng: &mut RNG) -> Option<LightSample> {
+        // Uniform direction in the cone subtended by the source
+        let cos_theta = 1.0 - rng.random_double() * (1.0 - self.cos_max);
+        let sin_theta = f64::max(0.0, 1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * rng.random_double();
+        let direction = Onb::from_w(&self.direction).to_world(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+            normal: None,
+            pdf: (self.cos_max < 1.0).then(|| 1.0 / self.solid_angle()),
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+
+    fn pdf(&self, _p: &Point3, direction: &Vec3) -> f64 {
+        if self.covers(direction) {
+            1.0 / self.solid_angle()
+        } else {
+            0.0
+        }
+    }
+
+    fn escaped(&self, direction: &Vec3) -> Color {
+        if self.covers(direction) {
+            self.irradiance / self.solid_angle()
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+}
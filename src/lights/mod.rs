@@ -0,0 +1,106 @@
+use crate::base::*;
+use crate::lights::bounds::LightBounds;
+use crate::random::RNG;
+use crate::ray::Ray;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths, Wavelengths};
+
+pub mod bounds;
+pub mod bvh;
+pub mod directional;
+pub mod point;
+pub mod rect;
+pub mod sphere;
+pub mod spot;
+
+/// Light arriving at a point from a sampled direction.
+pub struct LightSample {
+    /// Unit vector pointing towards the light.
+    pub direction: Vec3,
+    /// Distance to the light along `direction`, infinite for lights far away like the sun.
+    pub distance: f64,
+    /// Incident light, already divided by the probability of sampling this direction.
+    pub radiance: Color,
+    /// Normal of the light at the sampled point, or `None` for punctual lights and lights at
+    /// infinity.
+    pub normal: Option<Vec3>,
+    /// Density (per solid angle) with which `direction` was sampled, or `None` for lights which
+    /// paths can't hit, and are only reached with shadow rays.
+    pub pdf: Option<f64>,
+}
+
+/// Ray of light leaving a light, to start paths from it.
+pub struct EmissionSample {
+    /// Point on the light, with a unit direction.
+    pub ray: Ray,
+    /// Radiance (or intensity, for punctual lights) emitted along the ray.
+    pub radiance: Color,
+    /// Normal of the surface at the origin, or `None` for punctual lights.
+    pub normal: Option<Vec3>,
+    /// Density (per unit of area) of the origin, 1 for punctual lights.
+    pub pdf_position: f64,
+    /// Density (per solid angle) of the direction.
+    pub pdf_direction: f64,
+    /// Distance at which the light fades out, for lights with a range.
+    pub range: Option<f64>,
+}
+
+/// Light sampled at each scattering event with a shadow ray. Punctual lights, such as point
+/// lights or a sun without an angular size, can't be hit by paths and are only lit this way.
+/// Emitting surfaces can also be lights (see `SphereLight`), and are then reached both ways, as
+/// are lights at infinity with an angular size by paths escaping the scene.
+pub trait Light: Sync + Send {
+    /// Samples the light arriving at `p`, or `None` if `p` isn't lit.
+    fn sample(&self, p: &Point3, rng: &mut RNG) -> Option<LightSample>;
+    /// Bounds on the emitted light, or `None` for lights at infinity which reach every point.
+    fn bounds(&self) -> Option<LightBounds>;
+
+    /// Density (per solid angle) with which `sample` picks `direction` from `p`.
+    fn pdf(&self, _p: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// Radiance reaching a path which escapes the scene along `direction`, for lights at
+    /// infinity which paths can hit (see `DirectionalLight`).
+    fn escaped(&self, _direction: &Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// Samples light leaving the light, or `None` for lights at infinity, which paths can't
+    /// start from.
+    fn sample_emission(&self, _rng: &mut RNG) -> Option<EmissionSample> {
+        None
+    }
+
+    /// Densities with which `sample_emission` picks the point `p` of the light (per unit of
+    /// area) and `direction` (per solid angle).
+    fn pdf_emission(&self, _p: &Point3, _direction: &Vec3) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+
+    /// Radiance of a `sample` taken from `p`, at the wavelengths carried by a path when rendering
+    /// in spectral mode. Lights made of a material emit its spectrum (see
+    /// `Material::emitted_spectrum`), and others upsample their color.
+    fn sample_spectrum(
+        &self,
+        _p: &Point3,
+        sample: &LightSample,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        lambdas.illuminant(&sample.radiance)
+    }
+
+    /// Radiance of an `emission` sample at the wavelengths carried by a path, as for
+    /// `sample_spectrum`.
+    fn emission_spectrum(
+        &self,
+        emission: &EmissionSample,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        lambdas.illuminant(&emission.radiance)
+    }
+}
+
+/// Average of the color channels, as a scalar power for the light BVH.
+fn power(color: &Color) -> f64 {
+    (color.x + color.y + color.z) / 3.0
+}
@@ -0,0 +1,99 @@
+use std::f64::consts::PI;
+
+use cgmath::InnerSpace;
+
+use crate::aabb::AABB;
+use crate::base::*;
+use crate::lights::bounds::{DirectionCone, LightBounds};
+use crate::lights::{power, EmissionSample, Light, LightSample};
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/// Light emitted uniformly in all directions from a single point, like a bare bulb.
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+    range: Option<f64>,
+}
+
+impl PointLight {
+    /// `intensity` is the power emitted per unit of solid angle, which falls off with the square
+    /// of the distance.
+    pub fn new(position: Point3, intensity: Color) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+            range: None,
+        }
+    }
+
+    /// Smoothly fades the light out to nothing at distance `range`, so that it only lights its
+    /// surroundings.
+    pub fn with_range(self, range: f64) -> PointLight {
+        PointLight {
+            range: Some(range),
+            ..self
+        }
+    }
+}
+
+/// Falloff of a light at `distance`, following the inverse square law and then windowed to reach
+/// 0 at `range`.
+pub(crate) fn falloff(distance: f64, range: Option<f64>) -> f64 {
+    window(distance, range) / (distance * distance)
+}
+
+/// Window smoothly going from 1 at the light to 0 at `range`.
+pub(crate) fn window(distance: f64, range: Option<f64>) -> f64 {
+    match range {
+        Some(range) => {
+            let ratio = distance / range;
+            f64::max(0.0, 1.0 - ratio.powi(4)).powi(2)
+        }
+        None => 1.0,
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &Point3, _rng: &mut RNG) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance = to_light.magnitude();
+        let falloff = falloff(distance, self.range);
+        if distance <= 0.0 || falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity * falloff,
+            normal: None,
+            pdf: None,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds {
+            aabb: AABB::new(self.position, self.position),
+            phi: 4.0 * PI * power(&self.intensity),
+            normals: DirectionCone::entire_sphere(),
+            cos_theta_e: 0.0,
+            two_sided: false,
+        })
+    }
+
+    fn sample_emission(&self, rng: &mut RNG) -> Option<EmissionSample> {
+        Some(EmissionSample {
+            ray: Ray::new(self.position, rng.random_unit_vector()),
+            radiance: self.intensity,
+            normal: None,
+            pdf_position: 1.0,
+            pdf_direction: 1.0 / (4.0 * PI),
+            range: self.range,
+        })
+    }
+
+    fn pdf_emission(&self, _p: &Point3, _direction: &Vec3) -> (f64, f64) {
+        (1.0, 1.0 / (4.0 * PI))
+    }
+}
@@ -0,0 +1,221 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use cgmath::{dot, InnerSpace};
+
+use crate::aabb::AABB;
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::hit::rectangle::XZRect;
+use crate::hit::Hittable;
+use crate::lights::bounds::{DirectionCone, LightBounds};
+use crate::lights::{power, EmissionSample, Light, LightSample};
+use crate::materials::Material;
+use crate::onb::Onb;
+use crate::random::RNG;
+use crate::ray::Ray;
+use crate::spectrum::{Radiance, SampledSpectrum, SampledWavelengths};
+
+/// Emitting horizontal rectangle, such as a ceiling panel, which is sampled with shadow rays as
+/// well as hit by paths. It is added to a scene with `Scene::with_area_light`.
+pub struct RectLight<M: Material> {
+    rect: XZRect<M>,
+    x0: f64,
+    x1: f64,
+    z0: f64,
+    z1: f64,
+    k: f64,
+    material: Arc<M>,
+    flipped: bool,
+}
+
+impl<M: Material> RectLight<M> {
+    /// Rectangle spanning `x0..x1` and `z0..z1` at height `k`, facing upwards. `material` is
+    /// usually a `DiffuseLight`.
+    pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: Arc<M>) -> RectLight<M> {
+        RectLight {
+            rect: XZRect::new(x0, x1, z0, z1, k, material.clone()),
+            x0,
+            x1,
+            z0,
+            z1,
+            k,
+            material,
+            flipped: false,
+        }
+    }
+
+    /// Faces downwards instead, like `FlipFace`, for one-sided lights on a ceiling.
+    pub fn flipped(self) -> RectLight<M> {
+        RectLight {
+            flipped: !self.flipped,
+            ..self
+        }
+    }
+
+    fn area(&self) -> f64 {
+        (self.x1 - self.x0) * (self.z1 - self.z0)
+    }
+
+    fn random_point(&self, rng: &mut RNG) -> Point3 {
+        Point3::new(
+            rng.random_double_range(self.x0..self.x1),
+            self.k,
+            rng.random_double_range(self.z0..self.z1),
+        )
+    }
+
+    /// Calls `emit` with the ray and hit record of the light leaving `point` of the rectangle
+    /// along `direction`.
+    fn emission<R>(
+        &self,
+        point: &Point3,
+        direction: &Vec3,
+        emit: impl FnOnce(&Ray, &HitRecord) -> R,
+    ) -> R {
+        let ray = Ray::new(point + direction, -*direction);
+        let rec = HitRecord::new(
+            1.0,
+            (point.x - self.x0) / (self.x1 - self.x0),
+            (point.z - self.z0) / (self.z1 - self.z0),
+            *point,
+            self.material.as_ref(),
+            &ray,
+            &Vec3::new(0.0, 1.0, 0.0),
+        );
+        let rec = HitRecord {
+            front_face: rec.front_face != self.flipped,
+            ..rec
+        };
+
+        emit(&ray, &rec)
+    }
+
+    /// Light leaving `point` of the rectangle along `direction`.
+    fn emitted(&self, point: &Point3, direction: &Vec3) -> Color {
+        self.emission(point, direction, |ray, rec| self.material.emitted(ray, rec))
+    }
+}
+
+impl<M: Material> Hittable for RectLight<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord<'_>> {
+        self.rect.hit(r, t_min, t_max, rng).map(|rec| HitRecord {
+            front_face: rec.front_face != self.flipped,
+            light: Some(self),
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.rect.bounding_box()
+    }
+}
+
+impl<M: Material> Light for RectLight<M> {
+    fn sample(&self, p: &Point3, rng: &mut RNG) -> Option<LightSample> {
+        // Uniform point on the rectangle
+        let point = self.random_point(rng);
+        let to_light = point - p;
+        let distance = to_light.magnitude();
+        let direction = to_light / distance;
+        let cos_theta = direction.y.abs();
+        if cos_theta < 1e-8 {
+            return None;
+        }
+
+        let pdf = distance * distance / (cos_theta * self.area());
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.emitted(&point, &-direction) / pdf,
+            normal: Some(Vec3::new(0.0, 1.0, 0.0)),
+            pdf: Some(pdf),
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        // Emitted power on both sides, from the radiance at the center
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let center = Point3::new((self.x0 + self.x1) / 2.0, self.k, (self.z0 + self.z1) / 2.0);
+        let radiance = power(&self.emitted(&center, &up)) + power(&self.emitted(&center, &-up));
+
+        Some(LightBounds {
+            aabb: self.rect.bounding_box()?,
+            phi: PI * self.area() * radiance,
+            normals: DirectionCone::new(up, 1.0),
+            cos_theta_e: 0.0,
+            two_sided: true,
+        })
+    }
+
+    fn sample_emission(&self, rng: &mut RNG) -> Option<EmissionSample> {
+        // Uniform point, emitting in a cosine distributed direction on a random side
+        let point = self.random_point(rng);
+        let normal = if rng.random_double() < 0.5 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(0.0, -1.0, 0.0)
+        };
+        let direction = Onb::from_w(&normal).to_world(&rng.random_cosine_direction());
+
+        Some(EmissionSample {
+            ray: Ray::new(point, direction),
+            radiance: self.emitted(&point, &direction),
+            normal: Some(normal),
+            pdf_position: 1.0 / self.area(),
+            pdf_direction: f64::max(0.0, dot(direction, normal)) / (2.0 * PI),
+            range: None,
+        })
+    }
+
+    fn pdf_emission(&self, _p: &Point3, direction: &Vec3) -> (f64, f64) {
+        (
+            1.0 / self.area(),
+            direction.normalize().y.abs() / (2.0 * PI),
+        )
+    }
+
+    fn pdf(&self, p: &Point3, direction: &Vec3) -> f64 {
+        let direction = direction.normalize();
+        let distance = (self.k - p.y) / direction.y;
+        let point = p + direction * distance;
+        if direction.y == 0.0
+            || distance <= 0.0
+            || point.x < self.x0
+            || point.x > self.x1
+            || point.z < self.z0
+            || point.z > self.z1
+        {
+            return 0.0;
+        }
+
+        distance * distance / (direction.y.abs() * self.area())
+    }
+
+    fn sample_spectrum(
+        &self,
+        p: &Point3,
+        sample: &LightSample,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        let point = p + sample.direction * sample.distance;
+        let radiance = self.emission(&point, &-sample.direction, |ray, rec| {
+            self.material.emitted_spectrum(ray, rec, lambdas)
+        });
+
+        match sample.pdf {
+            Some(pdf) => radiance * (1.0 / pdf),
+            None => SampledSpectrum::black(),
+        }
+    }
+
+    fn emission_spectrum(
+        &self,
+        emission: &EmissionSample,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.emission(&emission.ray.origin, &emission.ray.direction, |ray, rec| {
+            self.material.emitted_spectrum(ray, rec, lambdas)
+        })
+    }
+}
@@ -0,0 +1,213 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use cgmath::{dot, InnerSpace};
+
+use crate::aabb::AABB;
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::hit::sphere::{calc_sphere_uv, Sphere};
+use crate::hit::Hittable;
+use crate::lights::bounds::{DirectionCone, LightBounds};
+use crate::lights::{power, EmissionSample, Light, LightSample};
+use crate::materials::Material;
+use crate::onb::Onb;
+use crate::random::RNG;
+use crate::ray::Ray;
+use crate::spectrum::{Radiance, SampledSpectrum, SampledWavelengths};
+
+/// Emitting sphere, such as a lamp, which is sampled with shadow rays as well as hit by paths.
+/// It is added to a scene with `Scene::with_area_light`, which puts it both in the world and in
+/// the lights.
+pub struct SphereLight<M: Material> {
+    sphere: Sphere<M>,
+    center: Point3,
+    radius: f64,
+    material: Arc<M>,
+}
+
+impl<M: Material> SphereLight<M> {
+    /// `material` is usually a `DiffuseLight`.
+    pub fn new(center: Point3, radius: f64, material: Arc<M>) -> SphereLight<M> {
+        SphereLight {
+            sphere: Sphere::new(center, radius, material.clone()),
+            center,
+            radius,
+            material,
+        }
+    }
+
+    /// Calls `emit` with the hit record of `ray` reaching `point` of the sphere, for the light
+    /// going back along it.
+    fn emission<R>(
+        &self,
+        point: &Point3,
+        ray: &Ray,
+        emit: impl FnOnce(&Ray, &HitRecord) -> R,
+    ) -> R {
+        let outward_normal = (point - self.center) / self.radius;
+        let (u, v) = calc_sphere_uv(&outward_normal);
+        let rec = HitRecord::new(
+            1.0,
+            u,
+            v,
+            *point,
+            self.material.as_ref(),
+            ray,
+            &outward_normal,
+        );
+
+        emit(ray, &rec)
+    }
+
+    /// Cosine of the half angle of the cone subtended by the sphere from `p`, or `None` if `p` is
+    /// inside.
+    fn cos_theta_max(&self, p: &Point3) -> Option<f64> {
+        let d2 = (self.center - p).magnitude2();
+        if d2 <= self.radius * self.radius {
+            return None;
+        }
+
+        Some((1.0 - self.radius * self.radius / d2).sqrt())
+    }
+}
+
+impl<M: Material> Hittable for SphereLight<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<HitRecord<'_>> {
+        self.sphere.hit(r, t_min, t_max, rng).map(|rec| HitRecord {
+            light: Some(self),
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.sphere.bounding_box()
+    }
+}
+
+impl<M: Material> Light for SphereLight<M> {
+    fn sample(&self, p: &Point3, rng: &mut RNG) -> Option<LightSample> {
+        // Uniform direction in the cone subtended by the sphere
+        let cos_theta_max = self.cos_theta_max(p)?;
+        let to_center = self.center - p;
+        let distance_to_center = to_center.magnitude();
+        let cos_theta = 1.0 - rng.random_double() * (1.0 - cos_theta_max);
+        let sin_theta = f64::max(0.0, 1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * rng.random_double();
+        let direction = Onb::from_w(&to_center).to_world(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        // Nearest point of the sphere in that direction
+        let distance = distance_to_center * cos_theta
+            - f64::max(
+                0.0,
+                self.radius * self.radius
+                    - distance_to_center * distance_to_center * sin_theta * sin_theta,
+            )
+            .sqrt();
+        let point = p + direction * distance;
+        let radiance = self.emission(&point, &Ray::new(*p, direction), |ray, rec| {
+            self.material.emitted(ray, rec)
+        });
+
+        let pdf = 1.0 / (2.0 * PI * (1.0 - cos_theta_max));
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: radiance / pdf,
+            normal: Some((point - self.center) / self.radius),
+            pdf: Some(pdf),
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        // Emitted power, from the radiance at an arbitrary point
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let point = self.center + normal * self.radius;
+        let ray = Ray::new(point + normal, -normal);
+        let rec = HitRecord::new(1.0, 0.5, 1.0, point, self.material.as_ref(), &ray, &normal);
+        let area = 4.0 * PI * self.radius * self.radius;
+
+        Some(LightBounds {
+            aabb: self.sphere.bounding_box()?,
+            phi: PI * area * power(&self.material.emitted(&ray, &rec)),
+            normals: DirectionCone::entire_sphere(),
+            cos_theta_e: 0.0,
+            two_sided: false,
+        })
+    }
+
+    fn sample_emission(&self, rng: &mut RNG) -> Option<EmissionSample> {
+        // Uniform point on the sphere, emitting in a cosine distributed direction
+        let normal = rng.random_unit_vector();
+        let point = self.center + normal * self.radius;
+        let direction = Onb::from_w(&normal).to_world(&rng.random_cosine_direction());
+        let cos_theta = dot(direction, normal);
+
+        let radiance = self.emission(
+            &point,
+            &Ray::new(point + direction, -direction),
+            |ray, rec| self.material.emitted(ray, rec),
+        );
+
+        Some(EmissionSample {
+            ray: Ray::new(point, direction),
+            radiance,
+            normal: Some(normal),
+            pdf_position: 1.0 / (4.0 * PI * self.radius * self.radius),
+            pdf_direction: f64::max(0.0, cos_theta) / PI,
+            range: None,
+        })
+    }
+
+    fn pdf_emission(&self, p: &Point3, direction: &Vec3) -> (f64, f64) {
+        let normal = (p - self.center).normalize();
+        let cos_theta = dot(direction.normalize(), normal);
+
+        (
+            1.0 / (4.0 * PI * self.radius * self.radius),
+            f64::max(0.0, cos_theta) / PI,
+        )
+    }
+
+    fn pdf(&self, p: &Point3, _direction: &Vec3) -> f64 {
+        match self.cos_theta_max(p) {
+            Some(cos_theta_max) => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+            None => 0.0,
+        }
+    }
+
+    fn sample_spectrum(
+        &self,
+        p: &Point3,
+        sample: &LightSample,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        let point = p + sample.direction * sample.distance;
+        let radiance = self.emission(&point, &Ray::new(*p, sample.direction), |ray, rec| {
+            self.material.emitted_spectrum(ray, rec, lambdas)
+        });
+
+        match sample.pdf {
+            Some(pdf) => radiance * (1.0 / pdf),
+            None => SampledSpectrum::black(),
+        }
+    }
+
+    fn emission_spectrum(
+        &self,
+        emission: &EmissionSample,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        let point = emission.ray.origin;
+        let direction = emission.ray.direction;
+        self.emission(
+            &point,
+            &Ray::new(point + direction, -direction),
+            |ray, rec| self.material.emitted_spectrum(ray, rec, lambdas),
+        )
+    }
+}
@@ -0,0 +1,133 @@
+use std::f64::consts::PI;
+
+use cgmath::{dot, InnerSpace};
+
+use crate::aabb::AABB;
+use crate::base::*;
+use crate::lights::bounds::{DirectionCone, LightBounds};
+use crate::lights::point::falloff;
+use crate::lights::{power, EmissionSample, Light, LightSample};
+use crate::onb::Onb;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/// Point light only emitting inside a cone, like a stage light or a torch.
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    cos_outer: f64,
+    cos_inner: f64,
+    range: Option<f64>,
+}
+
+impl SpotLight {
+    /// Light at `position` pointing at `target`. `angle` is the half angle of the cone (in
+    /// degrees), and `blend` in `[0, 1]` the fraction of it over which the light fades out
+    /// towards the edge.
+    pub fn new(
+        position: Point3,
+        target: Point3,
+        intensity: Color,
+        angle: f64,
+        blend: f64,
+    ) -> SpotLight {
+        let inner_angle = angle * (1.0 - blend.clamp(0.0, 1.0));
+
+        SpotLight {
+            position,
+            direction: (target - position).normalize(),
+            intensity,
+            cos_outer: angle.to_radians().cos(),
+            cos_inner: inner_angle.to_radians().cos(),
+            range: None,
+        }
+    }
+
+    /// Smoothly fades the light out to nothing at distance `range`.
+    pub fn with_range(self, range: f64) -> SpotLight {
+        SpotLight {
+            range: Some(range),
+            ..self
+        }
+    }
+
+    /// Attenuation for light leaving in a direction making an angle of cosine `cos_theta` with
+    /// the axis of the cone.
+    fn cone(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+
+        let t = ((cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &Point3, _rng: &mut RNG) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance = to_light.magnitude();
+        if distance <= 0.0 {
+            return None;
+        }
+
+        let direction = to_light / distance;
+        let attenuation =
+            self.cone(dot(-direction, self.direction)) * falloff(distance, self.range);
+        if attenuation <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.intensity * attenuation,
+            normal: None,
+            pdf: None,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        // The light is bounded by the inner cone, and fades out over the angle to the outer one
+        let theta_inner = self.cos_inner.acos();
+        let theta_outer = self.cos_outer.acos();
+
+        Some(LightBounds {
+            aabb: AABB::new(self.position, self.position),
+            phi: 4.0 * PI * power(&self.intensity),
+            normals: DirectionCone::new(self.direction, self.cos_inner),
+            cos_theta_e: (theta_outer - theta_inner).cos(),
+            two_sided: false,
+        })
+    }
+
+    fn sample_emission(&self, rng: &mut RNG) -> Option<EmissionSample> {
+        // Uniform direction in the outer cone
+        let cos_theta = 1.0 - rng.random_double() * (1.0 - self.cos_outer);
+        let sin_theta = f64::max(0.0, 1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * rng.random_double();
+        let direction = Onb::from_w(&self.direction).to_world(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        Some(EmissionSample {
+            ray: Ray::new(self.position, direction),
+            radiance: self.intensity * self.cone(cos_theta),
+            normal: None,
+            pdf_position: 1.0,
+            pdf_direction: 1.0 / (2.0 * PI * (1.0 - self.cos_outer)),
+            range: self.range,
+        })
+    }
+
+    fn pdf_emission(&self, _p: &Point3, direction: &Vec3) -> (f64, f64) {
+        if dot(direction.normalize(), self.direction) < self.cos_outer {
+            return (1.0, 0.0);
+        }
+
+        (1.0, 1.0 / (2.0 * PI * (1.0 - self.cos_outer)))
+    }
+}
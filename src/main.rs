@@ -2,51 +2,85 @@
 
 use std::time::Instant;
 
-use clap::Parser;
+use clap::{ErrorKind, IntoApp, Parser};
 use indicatif::{ParallelProgressIterator, ProgressBar};
 use itertools::Itertools;
 use rayon::prelude::*;
 
 use crate::base::*;
 use crate::bvh::BVHNode;
+use crate::camera::Camera;
 use crate::hit::*;
+use crate::integrators::guiding::Guide;
+use crate::integrators::mlt::Metropolis;
+use crate::integrators::path::ray_color;
+use crate::integrators::Film;
+use crate::integrators::{bdpt, mlt, sppm, Integrator, PathLength};
+use crate::lights::bvh::LightBvh;
+use crate::materials::medium::MediumStack;
 use crate::random::RNG;
 use crate::ray::Ray;
-use crate::scenes::Scenes;
+use crate::scenes::{Scene, Scenes};
+use crate::spectrum::{Rgb, SampledWavelengths, Wavelengths};
 
 mod aabb;
 mod base;
 mod camera;
 mod hit;
+mod integrators;
+mod lights;
 mod materials;
+mod onb;
+mod perlin;
 mod random;
 mod ray;
 mod scenes;
+mod sky;
+mod spectrum;
 
-fn ray_color<T: Hittable>(
-    r: &Ray,
+/// Light arriving at the camera along `r` with the chosen integrator, in linear sRGB.
+#[allow(clippy::too_many_arguments)]
+fn trace<T: Hittable, W: Wavelengths>(
+    integrator: Integrator,
+    r: Ray,
     world: &T,
-    depth: i32,
-    background: Background,
+    background: &Background,
+    lights: &LightBvh,
+    camera: &Camera,
+    film: &Film,
+    guide: Option<&Guide>,
+    wavelengths: &W,
+    path_length: PathLength,
     rng: &mut RNG,
 ) -> Color {
-    if depth <= 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
+    let radiance = match integrator {
+        // Metropolis light transport mutates the random numbers of the path tracer
+        Integrator::Path | Integrator::Mlt => ray_color(
+            r,
+            world,
+            background,
+            lights,
+            wavelengths,
+            &MediumStack::from(camera.medium()),
+            guide,
+            path_length,
+            rng,
+        ),
+        Integrator::Bdpt => bdpt::li(
+            r,
+            world,
+            background,
+            lights,
+            camera,
+            film,
+            wavelengths,
+            path_length,
+            rng,
+        ),
+        Integrator::Sppm => unreachable!("photon mapping renders the whole image at once"),
+    };
 
-    match world.hit(r, 0.001, f64::INFINITY, rng) {
-        Some(rec) => {
-            let emitted = rec.material.emitted(rec.u, rec.v, &rec.p);
-            match rec.material.scatter(r, &rec, rng) {
-                Some((color, ray)) => {
-                    emitted
-                        + color.multiply_with(&ray_color(&ray, world, depth - 1, background, rng))
-                }
-                None => emitted,
-            }
-        }
-        None => background(r),
-    }
+    wavelengths.to_rgb(&radiance)
 }
 
 #[derive(Parser)]
@@ -63,39 +97,75 @@ struct Args {
 
     #[clap(default_value_t = 200, short, long)]
     samples_per_pixel: u32,
-}
 
-fn main() {
-    let args = Args::parse();
+    /// Trace paths carrying a set of wavelengths instead of RGB colors
+    #[clap(long)]
+    spectral: bool,
 
-    // Scene
-    let (cam, world, background) = args.scene.get_scene();
+    #[clap(arg_enum, default_value = "path", long)]
+    integrator: Integrator,
 
-    // Image
-    let aspect_ratio = cam.get_aspect_ratio();
-    let image_width = args.image_width;
-    let image_height = ((image_width as f64) / aspect_ratio) as u32;
-    let samples_per_pixel = args.samples_per_pixel;
-    let max_depth = 50;
+    /// Maximum number of bounces of a path
+    #[clap(default_value_t = 100, long)]
+    max_depth: u32,
+
+    /// Number of bounces before paths can be terminated by Russian roulette
+    #[clap(default_value_t = 3, long)]
+    min_depth: u32,
+
+    /// Throughput below which paths are randomly terminated by Russian roulette (0 to disable)
+    #[clap(default_value_t = 1.0, long)]
+    roulette_threshold: f64,
+
+    /// Number of photons traced at each iteration (sample) of photon mapping [default: one per
+    /// pixel]
+    #[clap(long)]
+    photons: Option<u64>,
 
-    // Progress Bar
-    let bar = ProgressBar::new((image_height * image_width) as u64);
+    /// Number of independent Markov chains of Metropolis light transport
+    #[clap(default_value_t = 1000, long)]
+    chains: u64,
+
+    /// Number of paths traced to start the chains of Metropolis light transport
+    #[clap(default_value_t = 100000, long)]
+    bootstrap_samples: u64,
+
+    /// Learn where light comes from during training passes before rendering, and sample these
+    /// directions along with the materials (path integrator only)
+    #[clap(long)]
+    guiding: bool,
+
+    /// Number of training passes of path guiding, each with twice the samples per pixel of the
+    /// previous one
+    #[clap(default_value_t = 7, long)]
+    training_passes: u32,
+}
+
+/// Progress bar over `len` steps.
+fn progress_bar(len: u64) -> ProgressBar {
+    let bar = ProgressBar::new(len);
     bar.set_style(
         indicatif::ProgressStyle::default_bar()
             .template("[{elapsed}] {bar:40.cyan/blue} {pos:>7}/{len:7} eta:{eta}")
             .progress_chars("=> "),
     );
-    bar.set_draw_delta((image_height * image_width / 1000) as u64);
+    bar
+}
 
-    println!("Starting to build BVH tree...");
-    let world = BVHNode::new_from_hittable_list(world);
-    println!("Done building the tree");
+/// Sum over `samples_per_pixel` samples of the light reaching each pixel of a `width` by
+/// `height` image, top row first, where `sample` gives the light reaching an image position.
+fn render_pixels(
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    sample: &(dyn Fn(f64, f64, &mut RNG) -> Color + Sync),
+) -> Vec<Color> {
+    let bar = progress_bar((width * height) as u64);
+    bar.set_draw_delta((width * height / 1000) as u64);
 
-    println!("Starting to render...");
-    let start = Instant::now();
-    let pixels: Vec<Color> = (0..(image_height - 1))
+    (0..height)
         .rev()
-        .cartesian_product(0..image_width)
+        .cartesian_product(0..width)
         .collect::<Vec<_>>()
         .into_par_iter()
         .progress_with(bar)
@@ -104,14 +174,165 @@ fn main() {
 
             (0..samples_per_pixel)
                 .map(|_| {
-                    let u = ((i as f64) + rng.random_double()) / (image_width - 1) as f64;
-                    let v = ((j as f64) + rng.random_double()) / (image_height - 1) as f64;
-
-                    let r = cam.get_ray(u, v, &mut rng);
-                    ray_color(&r, &world, max_depth, background, &mut rng)
+                    let u = ((i as f64) + rng.random_double()) / (width - 1) as f64;
+                    let v = ((j as f64) + rng.random_double()) / height as f64;
+                    sample(u, v, &mut rng)
                 })
                 .sum()
         })
+        .collect()
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.spectral && matches!(args.integrator, Integrator::Sppm) {
+        Args::into_app()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "photon mapping only supports RGB rendering, without --spectral",
+            )
+            .exit();
+    }
+    if args.guiding && !matches!(args.integrator, Integrator::Path) {
+        Args::into_app()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "path guiding only applies to the path tracer (--integrator path)",
+            )
+            .exit();
+    }
+
+    // Scene
+    let Scene {
+        camera: cam,
+        world,
+        background,
+        lights,
+    } = args.scene.get_scene();
+    let lights = LightBvh::new(lights);
+
+    // Image
+    let aspect_ratio = cam.get_aspect_ratio();
+    let image_width = args.image_width;
+    let image_height = ((image_width as f64) / aspect_ratio) as u32;
+    let samples_per_pixel = args.samples_per_pixel;
+    let path_length = PathLength {
+        max_depth: args.max_depth,
+        min_depth: args.min_depth,
+        roulette_threshold: args.roulette_threshold,
+    };
+
+    println!("Starting to build BVH tree...");
+    let world = BVHNode::new_from_hittable_list(world);
+    println!("Done building the tree");
+
+    if args.spectral {
+        println!("Fitting RGB to spectrum table...");
+        spectrum::prepare();
+    }
+
+    let film = Film::new(image_width, image_height - 1);
+
+    println!("Starting to render...");
+    let start = Instant::now();
+    // Light reaching a position of the image, along a path traced by the chosen integrator
+    let sample = |u: f64, v: f64, guide: Option<&Guide>, rng: &mut RNG| {
+        let r = cam.get_ray(u, v, rng);
+        if args.spectral {
+            let lambdas = SampledWavelengths::sample_visible(rng.random_double());
+            let r = Ray::new_with_wavelength(r.origin, r.direction, lambdas.hero());
+            trace(
+                args.integrator,
+                r,
+                &world,
+                &*background,
+                &lights,
+                &cam,
+                &film,
+                guide,
+                &lambdas,
+                path_length,
+                rng,
+            )
+        } else {
+            trace(
+                args.integrator,
+                r,
+                &world,
+                &*background,
+                &lights,
+                &cam,
+                &film,
+                guide,
+                &Rgb,
+                path_length,
+                rng,
+            )
+        }
+    };
+
+    // Path guiding learns from the samples of training passes, which are then discarded
+    let guide = if args.guiding {
+        let aabb = world
+            .bounding_box()
+            .expect("path guiding needs a bounded scene");
+        let mut guide = Guide::new(aabb);
+        for pass in 0..args.training_passes {
+            let samples = 1 << pass;
+            println!(
+                "Learning the light, pass {}/{} with {} samples per pixel...",
+                pass + 1,
+                args.training_passes,
+                samples
+            );
+            render_pixels(image_width, image_height - 1, samples, &|u, v, rng| {
+                sample(u, v, Some(&guide), rng)
+            });
+            guide.end_pass(samples);
+        }
+        guide.end_training();
+        Some(guide)
+    } else {
+        None
+    };
+    let sample = |u: f64, v: f64, rng: &mut RNG| sample(u, v, guide.as_ref(), rng);
+
+    let pixel_count = (image_width * (image_height - 1)) as u64;
+    let pixels: Vec<Color> = match args.integrator {
+        Integrator::Sppm => {
+            let bar = progress_bar(samples_per_pixel as u64);
+            sppm::render(
+                &world,
+                &*background,
+                &lights,
+                &cam,
+                image_width,
+                image_height - 1,
+                samples_per_pixel,
+                args.photons.unwrap_or(pixel_count),
+                path_length,
+                &bar,
+            )
+        }
+        Integrator::Mlt => {
+            // Paths land anywhere on the image, and are all splatted
+            let settings = Metropolis {
+                chains: args.chains,
+                bootstrap_samples: args.bootstrap_samples,
+                mutations: pixel_count * samples_per_pixel as u64,
+            };
+            mlt::render(&film, settings, &sample, &progress_bar(args.chains));
+            vec![Color::new(0.0, 0.0, 0.0); pixel_count as usize]
+        }
+        _ => render_pixels(image_width, image_height - 1, samples_per_pixel, &sample),
+    };
+
+    // Light traced from the lights to the camera by BDPT, or by Metropolis light transport,
+    // lands on any pixel
+    let pixels: Vec<Color> = pixels
+        .iter()
+        .zip(film.into_splats())
+        .map(|(pixel, splat)| pixel + splat)
         .collect();
 
     write_color(
@@ -0,0 +1,97 @@
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::materials::medium::Medium;
+use crate::materials::textures::Texture;
+use crate::materials::Material;
+use crate::random::RNG;
+use crate::ray::Ray;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+
+/// Cuts holes in a material, for foliage or fences modelled as flat shapes. `alpha` gives the
+/// opacity of the surface, read from the first channel of the texture.
+pub struct AlphaMask<M: Material, T: Texture> {
+    material: M,
+    alpha: T,
+    threshold: Option<f64>,
+}
+
+impl<M: Material, T: Texture> AlphaMask<M, T> {
+    /// Partially opaque regions let through a fraction of the rays, chosen at random.
+    pub fn new(material: M, alpha: T) -> Self {
+        AlphaMask {
+            material,
+            alpha,
+            threshold: None,
+        }
+    }
+
+    /// Regions with an opacity below `threshold` are fully cut out, and the rest is fully
+    /// opaque.
+    pub fn new_thresholded(material: M, alpha: T, threshold: f64) -> Self {
+        AlphaMask {
+            material,
+            alpha,
+            threshold: Some(threshold),
+        }
+    }
+}
+
+impl<M: Material, T: Texture> Material for AlphaMask<M, T> {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
+        self.material.scatter(ray, rec, rng)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        self.material.eval(rec, wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f64 {
+        self.material.pdf(rec, wo, wi)
+    }
+
+    fn attenuation_spectrum(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        scattered: &Ray,
+        attenuation: &Color,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.material
+            .attenuation_spectrum(ray, rec, scattered, attenuation, lambdas)
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        self.material.emitted(ray, rec)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.material.emitted_spectrum(ray, rec, lambdas)
+    }
+
+    fn is_opaque(&self, u: f64, v: f64, p: &Point3, rng: &mut RNG) -> bool {
+        let alpha = self.alpha.value(u, v, p).x;
+
+        match self.threshold {
+            Some(threshold) => alpha >= threshold,
+            None => alpha >= 1.0 || (alpha > 0.0 && rng.random_double() < alpha),
+        }
+    }
+
+    fn interior_medium(&self) -> Option<&dyn Medium> {
+        self.material.interior_medium()
+    }
+
+    fn exterior_medium(&self) -> Option<&dyn Medium> {
+        self.material.exterior_medium()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.material.is_dispersive()
+    }
+}
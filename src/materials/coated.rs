@@ -0,0 +1,470 @@
+use std::f64::consts::PI;
+
+use cgmath::{dot, InnerSpace};
+
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::integrators::power_heuristic;
+use crate::materials::microfacet::{
+    eval_dielectric, fresnel_dielectric, pdf_dielectric, refract, roughness_to_alpha,
+    sample_dielectric, sample_dielectric_lobe, TrowbridgeReitz,
+};
+use crate::materials::Material;
+use crate::onb::Onb;
+use crate::random::{random_at, seed_from, RNG};
+use crate::ray::Ray;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+
+/// Maximum number of times a path can bounce between the coat and the base before being
+/// terminated.
+const MAX_BOUNCES: u32 = 32;
+
+/// Dielectric layer, such as varnish or the clear coat of car paint, on top of another material.
+///
+/// Light going through the coat is traced stochastically: it bounces between the coat interface
+/// and the base material until it escapes, so any material can be used as the base. `eval` is
+/// estimated the same way, by a random walk in the layer (Guo et al. 2018), while `pdf` only
+/// approximates the density of the walks with a single scattering on the base.
+///
+/// A smooth coat either reflects light or lets it through. As for `MixMaterial`, the choice is
+/// made once per hit, so that light sampling doesn't have to handle the discrete reflection.
+pub struct Coated<M: Material> {
+    base: M,
+    ir: f64,
+    distribution: TrowbridgeReitz,
+    thickness: f64,
+    absorption: Color,
+}
+
+impl<M: Material> Coated<M> {
+    /// Clear coat with a smooth (`roughness` of 0) or rough interface.
+    pub fn new(base: M, ir: f64, roughness: f64) -> Coated<M> {
+        Coated::new_absorbing(base, ir, roughness, 0.0, Color::new(0.0, 0.0, 0.0))
+    }
+
+    /// Tinted coat, where `absorption` is the absorption coefficient of the layer per unit of
+    /// length, for each color channel.
+    pub fn new_absorbing(
+        base: M,
+        ir: f64,
+        roughness: f64,
+        thickness: f64,
+        absorption: Color,
+    ) -> Coated<M> {
+        let alpha = roughness_to_alpha(roughness);
+
+        Coated {
+            base,
+            ir,
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+            thickness,
+            absorption,
+        }
+    }
+
+    /// Transmittance of the layer for a path crossing it in direction `w`, in the local frame.
+    fn transmittance(&self, w: &Vec3) -> Color {
+        let distance = self.thickness / w.z.abs();
+
+        Color::new(
+            (-self.absorption.x * distance).exp(),
+            (-self.absorption.y * distance).exp(),
+            (-self.absorption.z * distance).exp(),
+        )
+    }
+
+    /// Whether a smooth coat reflects the light leaving towards `wo` at a hit, instead of letting
+    /// it through.
+    fn reflects(&self, rec: &HitRecord, wo: &Vec3) -> bool {
+        self.distribution.effectively_smooth()
+            && random_at(&rec.p, self as *const Self as usize) < fresnel_dielectric(wo.z, self.ir)
+    }
+
+    /// Direction in the layer of the light leaving the coat towards `wo`, with the BSDF times
+    /// the cosine over the pdf. At hits where a smooth coat lets light through, it is only
+    /// refracted, so the weight is divided by the probability of the choice.
+    fn enter(&self, wo: &Vec3, rng: &mut RNG) -> Option<(Vec3, f64)> {
+        let (w, weight) = self.sample_lobe(wo, self.ir, true, rng)?;
+        if self.distribution.effectively_smooth() {
+            return Some((w, weight / (1.0 - fresnel_dielectric(wo.z, self.ir))));
+        }
+
+        Some((w, weight))
+    }
+
+    fn sample_lobe(
+        &self,
+        wo: &Vec3,
+        eta: f64,
+        transmit: bool,
+        rng: &mut RNG,
+    ) -> Option<(Vec3, f64)> {
+        sample_dielectric_lobe(
+            &self.distribution,
+            wo,
+            eta,
+            transmit,
+            [rng.random_double(), rng.random_double()],
+        )
+    }
+
+    /// One random walk estimating the light reflected towards `wo` by the layer from `wi` (or
+    /// transmitted through the base if `wi` is below the surface), both in the local frame.
+    ///
+    /// Light leaving the layer towards `wi` is reached from each bounce on the base in two ways:
+    /// by refracting `wi` through the coat, or by scattering on the base. They are weighted with
+    /// multiple importance sampling, as only one of them works for smooth coats or bases.
+    fn walk(&self, rec: &HitRecord, onb: &Onb, wo: &Vec3, wi: &Vec3, rng: &mut RNG) -> Color {
+        let mut f = Color::new(0.0, 0.0, 0.0);
+        let smooth = self.distribution.effectively_smooth();
+        let eta2 = self.ir * self.ir;
+
+        let (mut w, weight) = match self.enter(wo, rng) {
+            Some(sample) => sample,
+            None => return f,
+        };
+        let mut throughput = Color::new(weight, weight, weight);
+
+        // Direction reaching the base from which light is refracted towards wi, with the BSDF
+        // of the coat times the cosine of wi, over the pdf of the direction. Transmission is
+        // reciprocal up to the squared ratio of the indices of refraction.
+        let to_wi = if wi.z <= 0.0 {
+            None
+        } else if smooth {
+            refract(wi, &Vec3::new(0.0, 0.0, 1.0), self.ir).map(|w_in| {
+                (
+                    -w_in,
+                    (1.0 - fresnel_dielectric(wi.z, self.ir)) * wi.z / w_in.z.abs(),
+                )
+            })
+        } else {
+            self.sample_interface(wi, self.ir, rng)
+                .filter(|(w_in, _)| w_in.z < 0.0)
+                .and_then(|(w_in, _)| {
+                    let pdf = pdf_dielectric(&self.distribution, wi, &w_in, self.ir);
+                    let f = eval_dielectric(&self.distribution, wi, &w_in, self.ir);
+                    (pdf > 0.0).then(|| (-w_in, eta2 * f * wi.z / (w_in.z.abs() * pdf)))
+                })
+        };
+
+        for _ in 0..MAX_BOUNCES {
+            throughput = throughput.multiply_with(&self.transmittance(&w));
+            let base_wo = onb.to_world(&-w);
+
+            if wi.z < 0.0 {
+                // Transmission through the base
+                f += throughput.multiply_with(&self.base.eval(rec, &base_wo, &onb.to_world(wi)));
+            } else if let Some((w_up, coat)) = to_wi {
+                let base_wi = onb.to_world(&w_up);
+                let base = self.base.eval(rec, &base_wo, &base_wi);
+                if base != Color::new(0.0, 0.0, 0.0) {
+                    let weight = if smooth {
+                        1.0
+                    } else {
+                        let coat_pdf = pdf_dielectric(&self.distribution, wi, &-w_up, self.ir);
+                        power_heuristic(coat_pdf, self.base.pdf(rec, &base_wo, &base_wi))
+                    };
+                    f += throughput
+                        .multiply_with(&base)
+                        .multiply_with(&self.transmittance(&w_up))
+                        * (coat * weight);
+                }
+            }
+
+            let inner_ray = Ray::new(rec.p, onb.to_world(&w));
+            let (attenuation, scattered) = match self.base.scatter(&inner_ray, rec, rng) {
+                Some(scattering) => scattering,
+                None => break,
+            };
+            let base_wi = scattered.direction.normalize();
+            let w_up = onb.to_local(&base_wi);
+            if w_up.z <= 0.0 {
+                break;
+            }
+            throughput = throughput
+                .multiply_with(&attenuation)
+                .multiply_with(&self.transmittance(&w_up));
+
+            if wi.z > 0.0 && !smooth {
+                let coat =
+                    eval_dielectric(&self.distribution, wi, &-w_up, self.ir) * eta2 * wi.z / w_up.z;
+                if coat > 0.0 {
+                    let base_pdf = self.base.pdf(rec, &base_wo, &base_wi);
+                    let weight = if base_pdf > 0.0 {
+                        let coat_pdf = pdf_dielectric(&self.distribution, wi, &-w_up, self.ir);
+                        power_heuristic(base_pdf, coat_pdf)
+                    } else {
+                        1.0
+                    };
+                    f += throughput * (coat * weight);
+                }
+            }
+
+            // Reflection on the coat, seen from inside the layer
+            let (w_flipped, weight) =
+                match self.sample_lobe(&flip(&-w_up), 1.0 / self.ir, false, rng) {
+                    Some(sample) => sample,
+                    None => break,
+                };
+            throughput *= weight;
+            w = flip(&w_flipped);
+        }
+
+        f
+    }
+
+    fn sample_interface(&self, wo: &Vec3, eta: f64, rng: &mut RNG) -> Option<(Vec3, f64)> {
+        sample_dielectric(
+            &self.distribution,
+            wo,
+            eta,
+            [
+                rng.random_double(),
+                rng.random_double(),
+                rng.random_double(),
+            ],
+        )
+    }
+}
+
+/// Mirrors a direction across the interface, to handle the coat from below in the same frame as
+/// from above.
+fn flip(w: &Vec3) -> Vec3 {
+    Vec3::new(w.x, w.y, -w.z)
+}
+
+impl<M: Material> Material for Coated<M> {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
+        let onb = Onb::from_w(&rec.normal);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        if self.reflects(rec, &wo) {
+            return Some((
+                Color::new(1.0, 1.0, 1.0),
+                Ray::new(rec.p, onb.to_world(&Vec3::new(-wo.x, -wo.y, wo.z))),
+            ));
+        }
+
+        // Reflection on a rough coat
+        let (mut w, weight) = if self.distribution.effectively_smooth() {
+            self.enter(&wo, rng)?
+        } else {
+            self.sample_interface(&wo, self.ir, rng)?
+        };
+        if w.z > 0.0 {
+            return Some((
+                Color::new(weight, weight, weight),
+                Ray::new(rec.p, onb.to_world(&w)),
+            ));
+        }
+
+        let mut throughput = Color::new(weight, weight, weight);
+        let mut wavelength = ray.wavelength;
+
+        for _ in 0..MAX_BOUNCES {
+            throughput = throughput.multiply_with(&self.transmittance(&w));
+
+            // Scattering on the base, which sees the path as coming straight from the coat
+            let inner_ray = Ray {
+                wavelength,
+                ..Ray::new(rec.p, onb.to_world(&w))
+            };
+            let (attenuation, scattered) = self.base.scatter(&inner_ray, rec, rng)?;
+            throughput = throughput.multiply_with(&attenuation);
+            wavelength = scattered.wavelength.or(wavelength);
+
+            let wi = onb.to_local(&scattered.direction.normalize());
+            if wi.z <= 0.0 {
+                // Transmitted through the base
+                return Some((throughput, scattered));
+            }
+            throughput = throughput.multiply_with(&self.transmittance(&wi));
+
+            // Coat interface, seen from inside the layer
+            let (w_flipped, weight) = self.sample_interface(&flip(&-wi), 1.0 / self.ir, rng)?;
+            throughput *= weight;
+            w = flip(&w_flipped);
+
+            if w.z > 0.0 {
+                return Some((
+                    throughput,
+                    Ray {
+                        wavelength,
+                        ..Ray::new(rec.p, onb.to_world(&w))
+                    },
+                ));
+            }
+        }
+
+        None
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        // Light leaving through the far side (of a transmissive base) sees the coat from there,
+        // as `scatter` would
+        if dot(*wo, rec.normal) < 0.0 {
+            return self.eval(&rec.flipped(), wo, wi);
+        }
+
+        let onb = Onb::from_w(&rec.normal);
+        let wo = onb.to_local(wo);
+        let wi = onb.to_local(wi);
+        if wo.z <= 0.0 || wi.z == 0.0 || self.reflects(rec, &wo) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let coat = if wi.z > 0.0 {
+            eval_dielectric(&self.distribution, &wo, &wi, self.ir)
+        } else {
+            0.0
+        };
+
+        // The walk only depends on the directions, so that every evaluation gives the same value
+        let mut rng = RNG::from_seed(seed_from(
+            &[
+                rec.p.x, rec.p.y, rec.p.z, wo.x, wo.y, wo.z, wi.x, wi.y, wi.z,
+            ],
+            self as *const Self as usize,
+        ));
+
+        Color::new(coat, coat, coat) + self.walk(rec, &onb, &wo, &wi, &mut rng)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f64 {
+        if dot(*wo, rec.normal) < 0.0 {
+            return self.pdf(&rec.flipped(), wo, wi);
+        }
+
+        let onb = Onb::from_w(&rec.normal);
+        let wo = onb.to_local(wo);
+        let wi_local = onb.to_local(wi);
+        if wo.z <= 0.0 || wi_local.z == 0.0 || self.reflects(rec, &wo) {
+            return 0.0;
+        }
+
+        // Paths going through the coat are approximated by a single scattering on the base,
+        // between the directions refracted by the macroscopic surface
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let base_wo = match refract(&wo, &normal, self.ir) {
+            Some(w) => onb.to_world(&-w),
+            None => return 0.0,
+        };
+        let layer = if wi_local.z < 0.0 {
+            self.base.pdf(rec, &base_wo, wi)
+        } else {
+            match refract(&wi_local, &normal, self.ir) {
+                // Jacobian of the refraction of the direction
+                Some(w) => {
+                    self.base.pdf(rec, &base_wo, &onb.to_world(&-w)) * wi_local.z
+                        / (self.ir * self.ir * w.z.abs())
+                }
+                None => 0.0,
+            }
+        };
+
+        if self.distribution.effectively_smooth() {
+            return layer;
+        }
+
+        // Rough coats scatter around the reflection as well, which the base may not cover
+        let coat = if wi_local.z > 0.0 {
+            pdf_dielectric(&self.distribution, &wo, &wi_local, self.ir)
+        } else {
+            0.0
+        };
+        let layer = if wi_local.z > 0.0 {
+            0.9 * layer + 0.1 * wi_local.z / PI
+        } else {
+            layer
+        };
+
+        coat + (1.0 - fresnel_dielectric(wo.z, self.ir)) * layer
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(ray, rec)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.base.emitted_spectrum(ray, rec, lambdas)
+    }
+
+    fn is_opaque(&self, u: f64, v: f64, p: &Point3, rng: &mut RNG) -> bool {
+        self.base.is_opaque(u, v, p, rng)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::materials::lambertian::Lambertian;
+
+    #[test]
+    fn eval_integrates_to_the_scattered_light() {
+        // Rough enough for the reflection on the coat to be integrated accurately
+        for roughness in [0.0, 0.6] {
+            let base = Lambertian::from_color(Color::new(0.5, 0.5, 0.5));
+            let material =
+                Coated::new_absorbing(base, 1.5, roughness, 0.5, Color::new(0.2, 0.2, 0.2));
+            for theta in [0.2f64, 0.8, 1.3] {
+                let wo = Vec3::new(theta.sin(), 0.0, theta.cos());
+                let ray = Ray::new(wo, -wo);
+                let normal = Vec3::new(0.0, 0.0, 1.0);
+                // Light reflected by a smooth coat can't be evaluated, so the hit is picked
+                // where the coat lets light through
+                let rec = (0..)
+                    .map(|k| {
+                        let p = Point3::new(k as f64, 0.0, 0.0);
+                        HitRecord::new(1.0, 0.0, 0.0, p, &material, &ray, &normal)
+                    })
+                    .find(|rec| !material.reflects(rec, &wo))
+                    .unwrap();
+
+                let mut rng = RNG::from_seed(1);
+                let n = 200000;
+                let scattered = (0..n)
+                    .filter_map(|_| material.scatter(&ray, &rec, &mut rng))
+                    .map(|(attenuation, _)| attenuation.x)
+                    .sum::<f64>()
+                    / n as f64;
+
+                let (n_theta, n_phi) = (400, 200);
+                let evaluated = (0..n_theta)
+                    .flat_map(|i| (0..n_phi).map(move |j| (i, j)))
+                    .map(|(i, j)| {
+                        let cos_theta = (i as f64 + 0.5) / n_theta as f64;
+                        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                        let phi = 2.0 * PI * (j as f64 + 0.5) / n_phi as f64;
+                        let wi = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                        material.eval(&rec, &wo, &wi).x
+                    })
+                    .sum::<f64>()
+                    * 2.0
+                    * PI
+                    / (n_theta * n_phi) as f64;
+
+                assert!(scattered > 0.0);
+                assert!(
+                    (evaluated - scattered).abs() < 0.01 * scattered,
+                    "{} {}",
+                    evaluated,
+                    scattered
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use cgmath::{dot, InnerSpace};
+
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::materials::microfacet::{fresnel_conductor, roughness_to_alpha, TrowbridgeReitz};
+use crate::materials::thin_film::ThinFilm;
+use crate::materials::Material;
+use crate::onb::Onb;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/// Rough metal described by a complex index of refraction `eta + i k` and a GGX distribution of
+/// microfacets. Unlike `Metal`, the reflectance follows the full conductor Fresnel equations and
+/// the roughness keeps the lobe energy-consistent at grazing angles.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+    film: Option<ThinFilm>,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Conductor {
+        Conductor::new_anisotropic(eta, k, roughness, roughness)
+    }
+
+    /// `roughness_u` and `roughness_v` control the spread of the lobe along the direction in
+    /// which the `u` texture coordinate of the surface grows, and across it.
+    pub fn new_anisotropic(eta: Color, k: Color, roughness_u: f64, roughness_v: f64) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(
+                roughness_to_alpha(roughness_u),
+                roughness_to_alpha(roughness_v),
+            ),
+            film: None,
+        }
+    }
+
+    /// Coats the metal with a thin film, like the oxide layer of anodised metals.
+    pub fn with_thin_film(self, film: ThinFilm) -> Conductor {
+        Conductor {
+            film: Some(film),
+            ..self
+        }
+    }
+
+    fn fresnel(&self, rec: &HitRecord, cos_theta: f64, wavelength: Option<f64>) -> Color {
+        match &self.film {
+            Some(film) => film.reflectance(
+                rec,
+                cos_theta,
+                1.0,
+                |lambda| (channel_at(&self.eta, lambda), channel_at(&self.k, lambda)),
+                wavelength,
+            ),
+            None => fresnel_conductor(cos_theta, &self.eta, &self.k),
+        }
+    }
+
+    // Complex IOR presets, given for the R, G and B channels (roughly 650, 550 and 450 nm).
+
+    pub fn gold(roughness: f64) -> Conductor {
+        Conductor::new(
+            Color::new(0.143119, 0.374957, 1.44248),
+            Color::new(3.98316, 2.38572, 1.60322),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Conductor {
+        Conductor::new(
+            Color::new(0.200438, 0.924033, 1.10221),
+            Color::new(3.91295, 2.45285, 2.14219),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Conductor {
+        Conductor::new(
+            Color::new(1.65746, 0.880369, 0.521229),
+            Color::new(9.22387, 6.26952, 4.837),
+            roughness,
+        )
+    }
+
+    pub fn silver(roughness: f64) -> Conductor {
+        Conductor::new(
+            Color::new(0.155265, 0.116723, 0.138342),
+            Color::new(4.82835, 3.12225, 2.14696),
+            roughness,
+        )
+    }
+}
+
+/// Interpolates a property given for the R, G and B channels at the given wavelength.
+fn channel_at(rgb: &Color, lambda: f64) -> f64 {
+    if lambda >= 550.0 {
+        let t = ((lambda - 550.0) / 100.0).min(1.0);
+        (1.0 - t) * rgb.y + t * rgb.x
+    } else {
+        let t = ((550.0 - lambda) / 100.0).min(1.0);
+        (1.0 - t) * rgb.y + t * rgb.z
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
+        let onb = Onb::from_w_and_u(&rec.normal, &rec.tangent);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        if self.distribution.effectively_smooth() {
+            let wi = Vec3::new(-wo.x, -wo.y, wo.z);
+            return Some((
+                self.fresnel(rec, wo.z, ray.wavelength),
+                Ray::new(rec.p, onb.to_world(&wi)),
+            ));
+        }
+
+        let wm = self
+            .distribution
+            .sample_wm(&wo, rng.random_double(), rng.random_double());
+        let wi = (-wo).reflect(&wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        // With visible normal sampling, f * cos / pdf reduces to F * G2 / G1
+        let fresnel = self.fresnel(rec, dot(wo, wm), ray.wavelength);
+        let attenuation = fresnel * (self.distribution.g(&wo, &wi) / self.distribution.g1(&wo));
+
+        Some((attenuation, Ray::new(rec.p, onb.to_world(&wi))))
+    }
+
+    /// With a thin film, light samples see the reflectance of the film averaged over each color
+    /// channel, even in spectral mode.
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if self.distribution.effectively_smooth() {
+            return black;
+        }
+
+        let onb = Onb::from_w_and_u(&rec.normal, &rec.tangent);
+        let wo = onb.to_local(wo);
+        let wi = onb.to_local(wi);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return black;
+        }
+
+        let wm = (wo + wi).normalize();
+        let fresnel = self.fresnel(rec, dot(wo, wm), None);
+
+        fresnel * (self.distribution.d(&wm) * self.distribution.g(&wo, &wi) / (4.0 * wo.z))
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f64 {
+        if self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+
+        let onb = Onb::from_w_and_u(&rec.normal, &rec.tangent);
+        let wo = onb.to_local(wo);
+        let wi = onb.to_local(wi);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let wm = (wo + wi).normalize();
+        self.distribution.d_visible(&wo, &wm) / (4.0 * dot(wo, wm))
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.film.is_some()
+    }
+}
@@ -0,0 +1,156 @@
+use std::fs;
+use std::ops::{Add, Mul};
+
+use crate::aabb::AABB;
+use crate::base::*;
+use crate::materials::textures::Texture;
+use crate::perlin::Perlin;
+use crate::random::RNG;
+
+/// Density of a medium varying in space, such as smoke or clouds.
+pub trait Density: Sync + Send {
+    fn density(&self, p: &Point3) -> f64;
+    /// Upper bound of the density over the whole medium.
+    fn max_density(&self) -> f64;
+}
+
+// Grid
+
+/// Values stored on a dense 3D grid of voxels spanning `bounds`, and interpolated linearly
+/// between voxel centers. A grid of scalars can be used as a density, and a grid of colors as a
+/// texture (e.g. for the albedo or emission of a medium).
+pub struct Grid<V> {
+    bounds: AABB,
+    resolution: [usize; 3],
+    values: Vec<V>,
+}
+
+impl<V: Copy + Add<Output = V> + Mul<f64, Output = V>> Grid<V> {
+    /// `values` are ordered with x varying fastest, then y, then z.
+    pub fn new(bounds: AABB, resolution: [usize; 3], values: Vec<V>) -> Grid<V> {
+        assert_eq!(values.len(), resolution.iter().product::<usize>());
+
+        Grid {
+            bounds,
+            resolution,
+            values,
+        }
+    }
+
+    /// Fills the grid with a function evaluated at the center of each voxel.
+    pub fn from_fn(bounds: AABB, resolution: [usize; 3], f: impl Fn(Point3) -> V) -> Grid<V> {
+        let size = bounds.max() - bounds.min();
+        let mut values = Vec::with_capacity(resolution.iter().product());
+        for k in 0..resolution[2] {
+            for j in 0..resolution[1] {
+                for i in 0..resolution[0] {
+                    values.push(f(bounds.min()
+                        + Vec3::new(
+                            size.x * (i as f64 + 0.5) / resolution[0] as f64,
+                            size.y * (j as f64 + 0.5) / resolution[1] as f64,
+                            size.z * (k as f64 + 0.5) / resolution[2] as f64,
+                        )));
+                }
+            }
+        }
+
+        Grid::new(bounds, resolution, values)
+    }
+
+    fn at(&self, i: usize, j: usize, k: usize) -> V {
+        self.values[(k * self.resolution[1] + j) * self.resolution[0] + i]
+    }
+
+    /// Trilinear interpolation of the voxels around `p`, or `None` outside of the grid.
+    fn lookup(&self, p: &Point3) -> Option<V> {
+        let (min, max) = (self.bounds.min(), self.bounds.max());
+        let mut indices = [(0, 0, 0.0); 3];
+        for axis in 0..3 {
+            if p[axis] < min[axis] || p[axis] > max[axis] {
+                return None;
+            }
+
+            let n = self.resolution[axis];
+            let x = (p[axis] - min[axis]) / (max[axis] - min[axis]) * n as f64 - 0.5;
+            let x = x.clamp(0.0, (n - 1) as f64);
+            let i = x.floor() as usize;
+            indices[axis] = (i, usize::min(i + 1, n - 1), x - i as f64);
+        }
+
+        let [(x0, x1, fx), (y0, y1, fy), (z0, z1, fz)] = indices;
+        let lerp = |a: V, b: V, t: f64| a * (1.0 - t) + b * t;
+        let plane = |k: usize| {
+            lerp(
+                lerp(self.at(x0, y0, k), self.at(x1, y0, k), fx),
+                lerp(self.at(x0, y1, k), self.at(x1, y1, k), fx),
+                fy,
+            )
+        };
+
+        Some(lerp(plane(z0), plane(z1), fz))
+    }
+}
+
+impl Grid<f64> {
+    /// Loads a raw volume file of little-endian 32-bit floats, ordered like in `new`, panicking
+    /// if it can't be read.
+    pub fn open_raw(path: &str, bounds: AABB, resolution: [usize; 3]) -> Grid<f64> {
+        let bytes = fs::read(path).unwrap();
+        let values = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+            .collect();
+
+        Grid::new(bounds, resolution, values)
+    }
+}
+
+impl Density for Grid<f64> {
+    fn density(&self, p: &Point3) -> f64 {
+        self.lookup(p).unwrap_or(0.0)
+    }
+
+    fn max_density(&self) -> f64 {
+        self.values.iter().copied().fold(0.0, f64::max)
+    }
+}
+
+impl Texture for Grid<Color> {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        self.lookup(p).unwrap_or(Color::new(0.0, 0.0, 0.0))
+    }
+}
+
+// NoiseDensity
+
+/// Procedural density made of Perlin turbulence, with puffs of about `1 / frequency` in size.
+/// Turbulence below `threshold` is left empty, which breaks the medium into separate wisps.
+pub struct NoiseDensity {
+    noise: Perlin,
+    frequency: f64,
+    octaves: u32,
+    threshold: f64,
+}
+
+impl NoiseDensity {
+    pub fn new(frequency: f64, octaves: u32, threshold: f64, rng: &mut RNG) -> NoiseDensity {
+        NoiseDensity {
+            noise: Perlin::new(rng),
+            frequency,
+            octaves,
+            threshold: threshold.clamp(0.0, 0.99),
+        }
+    }
+}
+
+impl Density for NoiseDensity {
+    fn density(&self, p: &Point3) -> f64 {
+        let turbulence = self.noise.turbulence(&(p * self.frequency), self.octaves);
+
+        ((turbulence - self.threshold) / (1.0 - self.threshold)).clamp(0.0, 1.0)
+    }
+
+    fn max_density(&self) -> f64 {
+        1.0
+    }
+}
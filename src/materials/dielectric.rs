@@ -3,47 +3,274 @@ use cgmath::{dot, InnerSpace};
 
 use crate::base::*;
 use crate::hit::hit_record::HitRecord;
+use crate::materials::ior::Ior;
+use crate::materials::thin_film::ThinFilm;
 use crate::materials::Material;
 use crate::random::RNG;
 use crate::ray::Ray;
+use crate::spectrum::{
+    sample_wavelength, Radiance, SampledSpectrum, SampledWavelengths, Wavelengths,
+};
 
 pub struct Dielectric {
-    ir: f64,
+    ior: Ior,
+    absorption: Color,
+    film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Dielectric {
-        Dielectric { ir }
+        Dielectric::new_dispersive(Ior::Constant(ir))
+    }
+
+    /// Creates a dielectric whose index of refraction depends on the wavelength. Paths hitting it
+    /// are restricted to a single wavelength, which separates white light into its colors.
+    pub fn new_dispersive(ior: Ior) -> Dielectric {
+        Dielectric {
+            ior,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            film: None,
+        }
+    }
+
+    /// Creates a tinted dielectric, where `transmittance` is the fraction of light left after
+    /// travelling `distance` inside the material (Beer-Lambert law).
+    pub fn new_absorbing(ir: f64, transmittance: Color, distance: f64) -> Dielectric {
+        let absorption = |t: f64| -f64::max(t, 1e-6).ln() / distance;
+
+        Dielectric {
+            ior: Ior::Constant(ir),
+            absorption: Color::new(
+                absorption(transmittance.x),
+                absorption(transmittance.y),
+                absorption(transmittance.z),
+            ),
+            film: None,
+        }
+    }
+
+    /// Coats the surface with a thin film, like a soap bubble.
+    pub fn with_thin_film(self, film: ThinFilm) -> Dielectric {
+        Dielectric {
+            film: Some(film),
+            ..self
+        }
+    }
+}
+
+impl Dielectric {
+    /// Cosine of the angle of incidence of `ray`, and the ratio of the indices of refraction on
+    /// either side of the surface for the given index `ir` of the material.
+    fn incidence(ray: &Ray, rec: &HitRecord, ir: f64) -> (f64, f64) {
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
+        let cos_theta = f64::min(dot(-ray.direction.normalize(), rec.normal), 1.0);
+
+        (cos_theta, refraction_ratio)
+    }
+
+    /// Reflectance of the film at the given wavelength, or averaged over each color channel.
+    fn film_reflectance(
+        &self,
+        film: &ThinFilm,
+        rec: &HitRecord,
+        cos_theta: f64,
+        ir: f64,
+        wavelength: Option<f64>,
+    ) -> Color {
+        let (outside, base) = if rec.front_face {
+            (1.0, Some(&self.ior))
+        } else {
+            (ir, None)
+        };
+
+        film.reflectance(
+            rec,
+            cos_theta,
+            outside,
+            |lambda| (base.map_or(1.0, |ior| ior.at(lambda)), 0.0),
+            wavelength,
+        )
+    }
+
+    /// Fraction of the light left at `rec`. A ray hitting the back face travelled inside the
+    /// material since its last bounce.
+    fn transmittance(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+
+        let distance = rec.t * ray.direction.magnitude();
+        Color::new(
+            (-self.absorption.x * distance).exp(),
+            (-self.absorption.y * distance).exp(),
+            (-self.absorption.z * distance).exp(),
+        )
     }
 }
 
 impl Material for Dielectric {
     fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
+        // Dispersive materials pick a wavelength for the path if it does not have one yet, and
+        // weight the path by the color of that wavelength
+        let (ir, wavelength, weight) = match (self.ior.is_dispersive(), ray.wavelength) {
+            (false, _) => (self.ior.nominal(), None, Color::new(1.0, 1.0, 1.0)),
+            (true, Some(lambda)) => (self.ior.at(lambda), Some(lambda), Color::new(1.0, 1.0, 1.0)),
+            (true, None) => {
+                let (lambda, weight) = sample_wavelength(rng);
+                (self.ior.at(lambda), Some(lambda), weight)
+            }
         };
 
-        let unit_direction = ray.direction.normalize();
-        let cos_theta = f64::min(dot(-unit_direction, rec.normal), 1.0);
+        let (cos_theta, refraction_ratio) = Dielectric::incidence(ray, rec, ir);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
+        let unit_direction = ray.direction.normalize();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction =
-            if cannot_refract || reflectance(cos_theta, refraction_ratio) > rng.random_double() {
-                unit_direction.reflect(&rec.normal)
+        let (direction, weight) = if cannot_refract {
+            (unit_direction.reflect(&rec.normal), weight)
+        } else if let Some(film) = &self.film {
+            // The film reflects each color differently: pick a direction according to the
+            // average reflectance, and weight the path by the color of that choice
+            let reflectance =
+                self.film_reflectance(film, rec, cos_theta, ir, wavelength.or(ray.wavelength));
+            let probability = reflection_probability(&reflectance);
+
+            if rng.random_double() < probability {
+                (
+                    unit_direction.reflect(&rec.normal),
+                    weight.multiply_with(&(reflectance / probability)),
+                )
             } else {
-                unit_direction.refract(&rec.normal, refraction_ratio)
-            };
+                let transmittance = Color::new(1.0, 1.0, 1.0) - reflectance;
+                (
+                    unit_direction.refract(&rec.normal, refraction_ratio),
+                    weight.multiply_with(&(transmittance / (1.0 - probability))),
+                )
+            }
+        } else if reflectance(cos_theta, refraction_ratio) > rng.random_double() {
+            (unit_direction.reflect(&rec.normal), weight)
+        } else {
+            (
+                unit_direction.refract(&rec.normal, refraction_ratio),
+                weight,
+            )
+        };
+
+        let attenuation = weight.multiply_with(&self.transmittance(ray, rec));
+
+        let scattered = match wavelength {
+            Some(lambda) => Ray::new_with_wavelength(rec.p, direction, lambda),
+            None => Ray::new(rec.p, direction),
+        };
+
+        Some((attenuation, scattered))
+    }
+
+    /// The film weights each wavelength by its own reflectance, against the probability with
+    /// which `scatter` reflected or refracted the path.
+    fn attenuation_spectrum(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        scattered: &Ray,
+        attenuation: &Color,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        // Dispersive materials restrict the path to the wavelength `scatter` evaluated
+        let film = match &self.film {
+            Some(film) if !self.ior.is_dispersive() => film,
+            _ => return lambdas.albedo(attenuation),
+        };
+
+        let ir = self.ior.nominal();
+        let (cos_theta, refraction_ratio) = Dielectric::incidence(ray, rec, ir);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        if refraction_ratio * sin_theta > 1.0 {
+            return lambdas.albedo(attenuation);
+        }
+
+        let probability = reflection_probability(&self.film_reflectance(
+            film,
+            rec,
+            cos_theta,
+            ir,
+            ray.wavelength,
+        ));
+        let reflected = dot(scattered.direction, rec.normal) > 0.0;
+        let weight = lambdas.sample(|lambda| {
+            let r = self
+                .film_reflectance(film, rec, cos_theta, ir, Some(lambda))
+                .x;
+            if reflected {
+                r / probability
+            } else {
+                (1.0 - r) / (1.0 - probability)
+            }
+        });
+
+        weight.modulate(&lambdas.albedo(&self.transmittance(ray, rec)))
+    }
 
-        Some((Color::new(1.0, 1.0, 1.0), Ray::new(rec.p, direction)))
+    fn is_dispersive(&self) -> bool {
+        self.ior.is_dispersive()
     }
 }
 
+/// Probability of reflecting off a film with the given reflectance.
+fn reflection_probability(reflectance: &Color) -> f64 {
+    ((reflectance.x + reflectance.y + reflectance.z) / 3.0).clamp(1e-3, 1.0 - 1e-3)
+}
+
 fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
     let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     let r0 = r0 * r0;
 
     r0 + (1.0 - r0) * (1.0 - cosine).pow(5)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::N_WAVELENGTHS;
+
+    #[test]
+    fn thin_films_weight_each_wavelength_without_losing_energy() {
+        let glass = Dielectric::new(1.5).with_thin_film(ThinFilm::new(400.0, 1.4));
+        let lambdas = SampledWavelengths::sample_visible(0.3);
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let direction = Vec3::new(0.4, 0.1, -1.0).normalize();
+        let ray = Ray::new_with_wavelength(-direction, direction, lambdas.hero());
+        let rec = HitRecord::new(
+            1.0,
+            0.0,
+            0.0,
+            Point3::new(0.0, 0.0, 0.0),
+            &glass,
+            &ray,
+            &normal,
+        );
+
+        let (cos_theta, refraction_ratio) = Dielectric::incidence(&ray, &rec, 1.5);
+        let reflectance = glass.film_reflectance(
+            glass.film.as_ref().unwrap(),
+            &rec,
+            cos_theta,
+            1.5,
+            ray.wavelength,
+        );
+        let probability = reflection_probability(&reflectance);
+        let spectrum = |direction: Vec3| {
+            let scattered = Ray::new(rec.p, direction);
+            glass.attenuation_spectrum(&ray, &rec, &scattered, &reflectance, &lambdas)
+        };
+        let reflected = spectrum(direction.reflect(&normal));
+        let refracted = spectrum(direction.refract(&normal, refraction_ratio));
+
+        assert!(!glass.is_dispersive());
+        assert!((1..N_WAVELENGTHS).any(|i| reflected.get(i) != reflected.get(0)));
+        for i in 0..N_WAVELENGTHS {
+            let expected = probability * reflected.get(i) + (1.0 - probability) * refracted.get(i);
+            assert!((expected - 1.0).abs() < 1e-9);
+        }
+    }
+}
@@ -1,17 +1,45 @@
-use crate::base::{Color, Point3};
+use crate::base::Color;
 use crate::hit::hit_record::HitRecord;
 use crate::materials::textures::{SolidColor, Texture};
 use crate::materials::Material;
 use crate::random::RNG;
 use crate::ray::Ray;
+use crate::spectrum::illuminants::Spectrum;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths, Wavelengths};
 
 pub struct DiffuseLight<T: Texture> {
     emit: T,
+    spectrum: Option<Spectrum>,
+    strength: f64,
+    two_sided: bool,
 }
 
 impl<T: Texture> DiffuseLight<T> {
+    /// Two-sided light whose emitted color is given by a texture, such as a screen showing an
+    /// image.
     pub fn new(emit: T) -> Self {
-        DiffuseLight { emit }
+        DiffuseLight {
+            emit,
+            spectrum: None,
+            strength: 1.0,
+            two_sided: true,
+        }
+    }
+
+    /// Multiplies the emitted radiance.
+    pub fn with_strength(self, strength: f64) -> Self {
+        DiffuseLight {
+            strength: self.strength * strength,
+            ..self
+        }
+    }
+
+    /// Only emits on the front face of the surface, in the direction of its outward normal.
+    pub fn one_sided(self) -> Self {
+        DiffuseLight {
+            two_sided: false,
+            ..self
+        }
     }
 }
 
@@ -19,6 +47,28 @@ impl DiffuseLight<SolidColor> {
     pub fn from_color(color: Color) -> DiffuseLight<SolidColor> {
         DiffuseLight::<SolidColor>::new(SolidColor::new(color))
     }
+
+    /// Light with the given emission spectrum (e.g. a black body or a standard illuminant), where
+    /// `strength` is its luminance.
+    pub fn from_spectrum(spectrum: Spectrum, strength: f64) -> DiffuseLight<SolidColor> {
+        DiffuseLight {
+            emit: SolidColor::new(spectrum.to_rgb()),
+            spectrum: Some(spectrum),
+            strength,
+            two_sided: true,
+        }
+    }
+
+    /// Incandescent light, emitting like a black body at the given temperature (in Kelvin).
+    pub fn from_temperature(temperature: f64, strength: f64) -> DiffuseLight<SolidColor> {
+        DiffuseLight::from_spectrum(Spectrum::blackbody(temperature), strength)
+    }
+}
+
+impl<T: Texture> DiffuseLight<T> {
+    fn emits_towards(&self, rec: &HitRecord) -> bool {
+        self.two_sided || rec.front_face
+    }
 }
 
 impl<T: Texture> Material for DiffuseLight<T> {
@@ -26,7 +76,25 @@ impl<T: Texture> Material for DiffuseLight<T> {
         None
     }
 
-    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
-        self.emit.value(u, v, p)
+    fn emitted(&self, _ray: &Ray, rec: &HitRecord) -> Color {
+        if !self.emits_towards(rec) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        self.strength * self.emit.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        match &self.spectrum {
+            Some(spectrum) if self.emits_towards(rec) => {
+                spectrum.scaled(self.strength).sample(lambdas)
+            }
+            _ => lambdas.illuminant(&self.emitted(ray, rec)),
+        }
     }
 }
@@ -0,0 +1,74 @@
+/// Index of refraction of a dielectric, possibly varying with the wavelength of light.
+#[derive(Copy, Clone)]
+pub enum Ior {
+    Constant(f64),
+    /// `n = a + b / λ²`, with λ in micrometers.
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    /// `n² = 1 + Σ b_i λ² / (λ² - c_i)`, with λ in micrometers.
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+/// Wavelength (in nm) of the Fraunhofer d line, at which glasses are usually characterized.
+const D_LINE: f64 = 587.6;
+
+impl Ior {
+    pub fn bk7() -> Ior {
+        Ior::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    pub fn dense_flint() -> Ior {
+        Ior::Sellmeier {
+            b: [1.73759695, 0.313747346, 1.89878101],
+            c: [0.013188707, 0.0623068142, 155.23629],
+        }
+    }
+
+    pub fn fused_silica() -> Ior {
+        Ior::Sellmeier {
+            b: [0.6961663, 0.4079426, 0.8974794],
+            c: [0.00467914826, 0.0135120631, 97.9340025],
+        }
+    }
+
+    pub fn diamond() -> Ior {
+        Ior::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030625, 0.011236, 0.0],
+        }
+    }
+
+    /// Index of refraction at the given wavelength (in nm).
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let l = wavelength / 1000.0;
+        let l2 = l * l;
+
+        match *self {
+            Ior::Constant(ir) => ir,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => (1.0
+                + b.iter()
+                    .zip(c.iter())
+                    .map(|(b, c)| b * l2 / (l2 - c))
+                    .sum::<f64>())
+            .sqrt(),
+        }
+    }
+
+    /// Index of refraction used when the wavelength is unknown.
+    pub fn nominal(&self) -> f64 {
+        self.at(D_LINE)
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
@@ -1,3 +1,7 @@
+use std::f64::consts::PI;
+
+use cgmath::dot;
+
 use crate::base::*;
 use crate::hit::hit_record::HitRecord;
 use crate::materials::textures::{SolidColor, Texture};
@@ -37,4 +41,14 @@ impl<T: Texture> Material for Lambertian<T> {
             ))
         }
     }
+
+    fn eval(&self, rec: &HitRecord, _wo: &Vec3, wi: &Vec3) -> Color {
+        let cos_theta = f64::max(0.0, dot(rec.normal, *wi));
+        self.albedo.value(rec.u, rec.v, &rec.p) * (cos_theta / PI)
+    }
+
+    fn pdf(&self, rec: &HitRecord, _wo: &Vec3, wi: &Vec3) -> f64 {
+        // normal + random unit vector is distributed with a cosine density
+        f64::max(0.0, dot(rec.normal, *wi)) / PI
+    }
 }
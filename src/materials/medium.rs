@@ -0,0 +1,240 @@
+use cgmath::InnerSpace;
+
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::materials::density::Density;
+use crate::materials::phase::{Isotropic, PhaseFunction};
+use crate::materials::textures::{SolidColor, Texture};
+use crate::materials::volumetric::Volumetric;
+use crate::materials::Material;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/// Participating medium filling a region of space, such as fog, smoke or murky water. Media are
+/// either bounded by a `Hittable` (see `ConstantMedium`), or entered by paths crossing a surface
+/// (see `MediumBoundary`).
+pub trait Medium: Sync + Send {
+    /// Samples where a path travelling along `r` first interacts with the medium between `t_min`
+    /// and `t_max`, or `None` if it goes through.
+    fn sample_interaction(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<f64>;
+    /// Fraction of the light going through the medium between `t_min` and `t_max` along the ray.
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> f64;
+    /// Material scattering (and possibly emitting) light at the interactions.
+    fn material(&self) -> &dyn Material;
+
+    /// Record of an interaction at `t` along the ray.
+    fn interaction(&self, r: &Ray, t: f64) -> HitRecord<'_> {
+        HitRecord {
+            t,
+            p: r.at(t),
+            front_face: true,
+            u: 0.0,                           // arbitrary
+            v: 0.0,                           // arbitrary
+            normal: Vec3::new(1.0, 0.0, 0.0), // arbitrary
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            material: self.material(),
+            light: None,
+        }
+    }
+}
+
+// Homogeneous
+
+/// Medium with the same density everywhere.
+pub struct Homogeneous<T: Texture, P: PhaseFunction = Isotropic> {
+    density: f64,
+    phase_function: Volumetric<T, P>,
+}
+
+impl<T: Texture> Homogeneous<T> {
+    pub fn new(density: f64, albedo: T) -> Self {
+        Homogeneous::new_with_phase(density, albedo, Isotropic)
+    }
+}
+
+impl Homogeneous<SolidColor> {
+    pub fn from_color(density: f64, color: Color) -> Self {
+        Homogeneous::new(density, SolidColor::new(color))
+    }
+}
+
+impl<T: Texture, P: PhaseFunction> Homogeneous<T, P> {
+    pub fn new_with_phase(density: f64, albedo: T, phase: P) -> Self {
+        Homogeneous {
+            density,
+            phase_function: Volumetric::new(albedo, phase),
+        }
+    }
+}
+
+impl<T: Texture, P: PhaseFunction> Medium for Homogeneous<T, P> {
+    fn sample_interaction(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<f64> {
+        let distance = -(1.0 - rng.random_double()).ln() / self.density;
+        let t = t_min + distance / r.direction.magnitude();
+
+        if t < t_max {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut RNG) -> f64 {
+        (-self.density * (t_max - t_min) * r.direction.magnitude()).exp()
+    }
+
+    fn material(&self) -> &dyn Material {
+        &self.phase_function
+    }
+}
+
+// Heterogeneous
+
+/// Medium whose density varies in space. Interactions are sampled with delta tracking against
+/// the maximum density, and transmittance is estimated with ratio tracking.
+pub struct Heterogeneous<D: Density, T: Texture, P: PhaseFunction = Isotropic> {
+    density: D,
+    scale: f64,
+    majorant: f64,
+    phase_function: Volumetric<T, P>,
+}
+
+impl<D: Density, T: Texture> Heterogeneous<D, T> {
+    /// The extinction coefficient of the medium is the density at each point multiplied by
+    /// `scale`.
+    pub fn new(density: D, scale: f64, albedo: T) -> Self {
+        Heterogeneous::new_with_phase(density, scale, albedo, Isotropic)
+    }
+}
+
+impl<D: Density, T: Texture, P: PhaseFunction> Heterogeneous<D, T, P> {
+    pub fn new_with_phase(density: D, scale: f64, albedo: T, phase: P) -> Self {
+        let majorant = scale * density.max_density();
+
+        Heterogeneous {
+            density,
+            scale,
+            majorant,
+            phase_function: Volumetric::new(albedo, phase),
+        }
+    }
+
+    /// Emitting medium, such as fire, where `emission` is usually a `Grid` of colors.
+    pub fn with_emission<E: Texture + 'static>(self, emission: E) -> Self {
+        Heterogeneous {
+            phase_function: self.phase_function.with_emission(emission),
+            ..self
+        }
+    }
+
+    /// Samples the distance to the next tentative collision, in units of `t`.
+    fn step(&self, r: &Ray, rng: &mut RNG) -> f64 {
+        -(1.0 - rng.random_double()).ln() / (self.majorant * r.direction.magnitude())
+    }
+}
+
+impl<D: Density, T: Texture, P: PhaseFunction> Medium for Heterogeneous<D, T, P> {
+    fn sample_interaction(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> Option<f64> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+
+        // Delta tracking: the medium is completed with fictitious particles up to the maximum
+        // density, and collisions with them are skipped
+        let mut t = t_min;
+        loop {
+            t += self.step(r, rng);
+            if t >= t_max {
+                return None;
+            }
+
+            if rng.random_double() * self.majorant < self.scale * self.density.density(&r.at(t)) {
+                return Some(t);
+            }
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut RNG) -> f64 {
+        if self.majorant <= 0.0 {
+            return 1.0;
+        }
+
+        let mut t = t_min;
+        let mut transmittance = 1.0;
+        loop {
+            t += self.step(r, rng);
+            if t >= t_max {
+                return transmittance;
+            }
+
+            transmittance *= 1.0 - self.scale * self.density.density(&r.at(t)) / self.majorant;
+        }
+    }
+
+    fn material(&self) -> &dyn Material {
+        &self.phase_function
+    }
+}
+
+// MediumStack
+
+/// Media a path is travelling through, the innermost last. Entering a nested medium (e.g. fog in
+/// a bottle under water) pushes it on the stack, and leaving it gives back the enclosing one.
+#[derive(Clone, Default)]
+pub struct MediumStack<'a> {
+    media: Vec<&'a dyn Medium>,
+}
+
+impl<'a> MediumStack<'a> {
+    pub fn new() -> MediumStack<'a> {
+        MediumStack { media: Vec::new() }
+    }
+
+    /// Medium the path is currently in, or `None` in vacuum.
+    pub fn current(&self) -> Option<&'a dyn Medium> {
+        self.media.last().copied()
+    }
+
+    /// Stack after the path goes through the surface at `rec`, towards the other side.
+    pub fn crossed(&self, rec: &HitRecord<'a>) -> MediumStack<'a> {
+        let mut media = self.media.clone();
+        let interior = rec.material.interior_medium();
+
+        if rec.front_face {
+            if let Some(interior) = interior {
+                media.push(interior);
+            }
+            return MediumStack { media };
+        }
+
+        if let Some(interior) = interior {
+            if let Some(i) = media.iter().rposition(|m| same_medium(*m, interior)) {
+                // Media don't need to be left in the order they were entered
+                media.remove(i);
+            }
+        }
+        if let Some(exterior) = rec.material.exterior_medium() {
+            if !media.last().is_some_and(|m| same_medium(*m, exterior)) {
+                media.push(exterior);
+            }
+        }
+
+        MediumStack { media }
+    }
+}
+
+impl<'a> From<Option<&'a dyn Medium>> for MediumStack<'a> {
+    /// Stack of a path starting in the given medium, e.g. from a camera in fog.
+    fn from(medium: Option<&'a dyn Medium>) -> MediumStack<'a> {
+        MediumStack {
+            media: medium.into_iter().collect(),
+        }
+    }
+}
+
+fn same_medium(a: &dyn Medium, b: &dyn Medium) -> bool {
+    std::ptr::eq(
+        a as *const dyn Medium as *const u8,
+        b as *const dyn Medium as *const u8,
+    )
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::materials::medium::Medium;
+use crate::materials::Material;
+use crate::random::RNG;
+use crate::ray::Ray;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+
+/// Surface separating two media, such as the surface of a lake or a glass of milk. The interior
+/// is on the opposite side of the outward normal.
+///
+/// Paths going through the surface enter the interior medium, and get back to the medium they
+/// were in before when leaving. The exterior medium is only needed when the path can't know it,
+/// e.g. for the surface of water seen from a camera inside it.
+pub struct MediumInterface<M: Material> {
+    material: M,
+    interior: Option<Arc<dyn Medium>>,
+    exterior: Option<Arc<dyn Medium>>,
+}
+
+impl<M: Material> MediumInterface<M> {
+    pub fn new(material: M) -> Self {
+        MediumInterface {
+            material,
+            interior: None,
+            exterior: None,
+        }
+    }
+
+    pub fn with_interior(self, medium: Arc<dyn Medium>) -> Self {
+        MediumInterface {
+            interior: Some(medium),
+            ..self
+        }
+    }
+
+    pub fn with_exterior(self, medium: Arc<dyn Medium>) -> Self {
+        MediumInterface {
+            exterior: Some(medium),
+            ..self
+        }
+    }
+}
+
+impl<M: Material> Material for MediumInterface<M> {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
+        self.material.scatter(ray, rec, rng)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        self.material.eval(rec, wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f64 {
+        self.material.pdf(rec, wo, wi)
+    }
+
+    fn attenuation_spectrum(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        scattered: &Ray,
+        attenuation: &Color,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.material
+            .attenuation_spectrum(ray, rec, scattered, attenuation, lambdas)
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        self.material.emitted(ray, rec)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.material.emitted_spectrum(ray, rec, lambdas)
+    }
+
+    fn is_opaque(&self, u: f64, v: f64, p: &Point3, rng: &mut RNG) -> bool {
+        self.material.is_opaque(u, v, p, rng)
+    }
+
+    fn interior_medium(&self) -> Option<&dyn Medium> {
+        self.interior.as_deref()
+    }
+
+    fn exterior_medium(&self) -> Option<&dyn Medium> {
+        self.exterior.as_deref()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.material.is_dispersive()
+    }
+}
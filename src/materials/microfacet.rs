@@ -0,0 +1,562 @@
+use std::f64::consts::PI;
+
+use cgmath::{dot, InnerSpace};
+
+use crate::base::*;
+
+/*
+Helpers for microfacet based materials. Every direction handled here is expressed in the local
+shading frame (see `Onb`), where the surface normal is the +z axis.
+ */
+
+fn cos2_theta(w: &Vec3) -> f64 {
+    w.z * w.z
+}
+
+fn sin2_theta(w: &Vec3) -> f64 {
+    f64::max(0.0, 1.0 - cos2_theta(w))
+}
+
+fn tan2_theta(w: &Vec3) -> f64 {
+    sin2_theta(w) / cos2_theta(w)
+}
+
+fn cos_phi(w: &Vec3) -> f64 {
+    let sin_theta = sin2_theta(w).sqrt();
+    if sin_theta == 0.0 {
+        1.0
+    } else {
+        (w.x / sin_theta).clamp(-1.0, 1.0)
+    }
+}
+
+fn sin_phi(w: &Vec3) -> f64 {
+    let sin_theta = sin2_theta(w).sqrt();
+    if sin_theta == 0.0 {
+        0.0
+    } else {
+        (w.y / sin_theta).clamp(-1.0, 1.0)
+    }
+}
+
+/// Maps a perceptual roughness in `[0, 1]` to the GGX `alpha` parameter.
+pub fn roughness_to_alpha(roughness: f64) -> f64 {
+    roughness.clamp(0.0, 1.0).powi(2)
+}
+
+/// Anisotropic GGX (Trowbridge-Reitz) distribution of microfacet normals.
+#[derive(Copy, Clone)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz {
+            alpha_x: f64::max(alpha_x, 1e-4),
+            alpha_y: f64::max(alpha_y, 1e-4),
+        }
+    }
+
+    /// Below this roughness the lobe is numerically a delta and should be handled as a perfectly
+    /// smooth interface.
+    pub fn effectively_smooth(&self) -> bool {
+        f64::max(self.alpha_x, self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        let tan2 = tan2_theta(wm);
+        if tan2.is_infinite() {
+            return 0.0;
+        }
+
+        let cos4 = cos2_theta(wm) * cos2_theta(wm);
+        if cos4 < 1e-16 {
+            return 0.0;
+        }
+
+        let e =
+            tan2 * ((cos_phi(wm) / self.alpha_x).powi(2) + (sin_phi(wm) / self.alpha_y).powi(2));
+
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos4 * (1.0 + e).powi(2))
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let tan2 = tan2_theta(w);
+        if tan2.is_infinite() {
+            return 0.0;
+        }
+
+        let alpha2 = (cos_phi(w) * self.alpha_x).powi(2) + (sin_phi(w) * self.alpha_y).powi(2);
+
+        ((1.0 + alpha2 * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Smith masking function.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated Smith masking-shadowing function.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Distribution of normals visible from `w`. Normals facing away from `w` are hidden.
+    pub fn d_visible(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        self.g1(w) / w.z.abs() * self.d(wm) * f64::max(0.0, dot(*w, *wm))
+    }
+
+    /// Samples a microfacet normal from the distribution of normals visible from `w`
+    /// (Heitz 2018). `u1` and `u2` are uniform random numbers in `[0, 1)`.
+    pub fn sample_wm(&self, w: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Transform w to the hemispherical configuration
+        let mut wh = Vec3::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).normalize();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+
+        // Orthonormal basis around the visible hemisphere
+        let t1 = if wh.z < 0.99999 {
+            Vec3::new(0.0, 0.0, 1.0).cross(wh).normalize()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(t1);
+
+        // Uniformly distributed point on a disk, warped to the projection of the hemisphere
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let p2 = r * phi.sin();
+        let h = (1.0 - p1 * p1).sqrt();
+        let s = (1.0 + wh.z) / 2.0;
+        let p2 = (1.0 - s) * h + s * p2;
+        let pz = f64::max(0.0, 1.0 - p1 * p1 - p2 * p2).sqrt();
+
+        // Reproject onto the hemisphere and back to the ellipsoid configuration
+        let nh = p1 * t1 + p2 * t2 + pz * wh;
+        Vec3::new(
+            self.alpha_x * nh.x,
+            self.alpha_y * nh.y,
+            f64::max(1e-6, nh.z),
+        )
+        .normalize()
+    }
+}
+
+/// Fresnel reflectance at the boundary between a dielectric with index 1 and a conductor with
+/// complex index of refraction `eta + i k`, evaluated per color channel.
+pub fn fresnel_conductor(cos_theta_i: f64, eta: &Color, k: &Color) -> Color {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let cos2 = cos_theta_i * cos_theta_i;
+    let sin2 = 1.0 - cos2;
+
+    let channel = |eta: f64, k: f64| {
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = f64::max(0.0, 0.5 * (a2_plus_b2 + t0)).sqrt();
+        let t2 = 2.0 * cos_theta_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        0.5 * (rp + rs)
+    };
+
+    Color::new(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    )
+}
+
+/// Unpolarized Fresnel reflectance at a smooth dielectric interface, where `eta` is the index of
+/// refraction of the transmitted side over the index of the incident side.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+/// Refracts `wo` (pointing away from the surface) through the interface with normal `n`, both
+/// on the same side. Returns `None` on total internal reflection.
+pub fn refract(wo: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = dot(*n, *wo);
+    let sin2_theta_i = f64::max(0.0, 1.0 - cos_theta_i * cos_theta_i);
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    Some(-*wo / eta + (cos_theta_i / eta - cos_theta_t) * n)
+}
+
+/// Samples the reflection or refraction of `wo` at a rough dielectric interface with relative IOR
+/// `eta`, choosing between them with the Fresnel equations. Returns the incident direction along
+/// with the BSDF times the cosine over the pdf. `u` holds three uniform random numbers.
+pub fn sample_dielectric(
+    distribution: &TrowbridgeReitz,
+    wo: &Vec3,
+    eta: f64,
+    u: [f64; 3],
+) -> Option<(Vec3, f64)> {
+    let wm = sample_dielectric_normal(distribution, wo, u[0], u[1]);
+    let reflectance = fresnel_dielectric(dot(*wo, wm), eta);
+    let transmit = u[2] >= reflectance;
+    let (wi, weight) = scatter_on_normal(distribution, wo, &wm, eta, transmit)?;
+
+    // The Fresnel factor of the lobe cancels out with the probability of picking it
+    if transmit {
+        Some((wi, weight / (1.0 - reflectance)))
+    } else {
+        Some((wi, weight / reflectance))
+    }
+}
+
+/// Like `sample_dielectric`, but always reflects `wo` (or refracts it if `transmit`), so the
+/// returned weight keeps the Fresnel factor of the lobe. `u` holds two uniform random numbers.
+pub fn sample_dielectric_lobe(
+    distribution: &TrowbridgeReitz,
+    wo: &Vec3,
+    eta: f64,
+    transmit: bool,
+    u: [f64; 2],
+) -> Option<(Vec3, f64)> {
+    let wm = sample_dielectric_normal(distribution, wo, u[0], u[1]);
+    scatter_on_normal(distribution, wo, &wm, eta, transmit)
+}
+
+fn sample_dielectric_normal(distribution: &TrowbridgeReitz, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+    if distribution.effectively_smooth() {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        distribution.sample_wm(wo, u1, u2)
+    }
+}
+
+/// Reflects or refracts `wo` on the microfacet `wm`, sampled from the visible normals. Returns
+/// the incident direction with the BSDF times the cosine over the pdf of `wm`.
+fn scatter_on_normal(
+    distribution: &TrowbridgeReitz,
+    wo: &Vec3,
+    wm: &Vec3,
+    eta: f64,
+    transmit: bool,
+) -> Option<(Vec3, f64)> {
+    let reflectance = fresnel_dielectric(dot(*wo, *wm), eta);
+    let (wi, weight) = if transmit {
+        let wi = refract(wo, wm, eta)?;
+        if wi.z >= 0.0 {
+            return None;
+        }
+        // Radiance is compressed into a smaller solid angle when entering a denser medium
+        (wi, (1.0 - reflectance) / (eta * eta))
+    } else {
+        let wi = (-*wo).reflect(wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        (wi, reflectance)
+    };
+
+    // With visible normal sampling, f * cos / pdf reduces to the Fresnel factor times G2 / G1
+    if distribution.effectively_smooth() {
+        Some((wi, weight))
+    } else {
+        Some((wi, weight * distribution.g(wo, &wi) / distribution.g1(wo)))
+    }
+}
+
+/// Generalized half vector between `wo` and `wi` refracted through an interface with relative
+/// IOR `eta`, along with the squared denominator of the Jacobian of the refraction.
+pub fn refraction_half_vector(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let mut wm = (wo + wi * eta).normalize();
+    if wm.z < 0.0 {
+        wm = -wm;
+    }
+    if dot(*wi, wm) >= 0.0 || dot(*wo, wm) <= 0.0 {
+        return None;
+    }
+
+    let denom = (dot(*wi, wm) + dot(*wo, wm) / eta).powi(2);
+    Some((wm, denom))
+}
+
+/// BSDF times the cosine of `wi` of the interface sampled by `sample_dielectric`, for `wo` above
+/// it. Smooth interfaces only scatter in discrete directions and give 0.
+pub fn eval_dielectric(distribution: &TrowbridgeReitz, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+    if wo.z <= 0.0 || wi.z == 0.0 || distribution.effectively_smooth() {
+        return 0.0;
+    }
+
+    if wi.z > 0.0 {
+        let wm = (wo + wi).normalize();
+        return fresnel_dielectric(dot(*wo, wm), eta)
+            * distribution.d(&wm)
+            * distribution.g(wo, wi)
+            / (4.0 * wo.z);
+    }
+
+    match refraction_half_vector(wo, wi, eta) {
+        Some((wm, denom)) => {
+            let transmittance = 1.0 - fresnel_dielectric(dot(*wo, wm), eta);
+            // Radiance is compressed into a smaller solid angle when entering a denser medium
+            distribution.d(&wm)
+                * transmittance
+                * distribution.g(wo, wi)
+                * (dot(*wi, wm) * dot(*wo, wm) / (wo.z * denom)).abs()
+                / (eta * eta)
+        }
+        None => 0.0,
+    }
+}
+
+/// Density with which `sample_dielectric` picks `wi`, for `wo` above the interface.
+pub fn pdf_dielectric(distribution: &TrowbridgeReitz, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+    if wo.z <= 0.0 || wi.z == 0.0 || distribution.effectively_smooth() {
+        return 0.0;
+    }
+
+    if wi.z > 0.0 {
+        let wm = (wo + wi).normalize();
+        return fresnel_dielectric(dot(*wo, wm), eta) * distribution.d_visible(wo, &wm)
+            / (4.0 * dot(*wo, wm));
+    }
+
+    match refraction_half_vector(wo, wi, eta) {
+        Some((wm, denom)) => {
+            let transmittance = 1.0 - fresnel_dielectric(dot(*wo, wm), eta);
+            transmittance * distribution.d_visible(wo, &wm) * dot(*wi, wm).abs() / denom
+        }
+        None => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Integrates `f` over the hemisphere around +z with the midpoint rule, in `cos(theta)` and
+    /// `phi` so each cell has the same solid angle.
+    fn integrate_hemisphere(f: impl Fn(&Vec3) -> f64) -> f64 {
+        let (n_theta, n_phi) = (1000, 400);
+        let cell = 2.0 * PI / (n_theta * n_phi) as f64;
+
+        (0..n_theta)
+            .flat_map(|i| (0..n_phi).map(move |j| (i, j)))
+            .map(|(i, j)| {
+                let cos_theta = (i as f64 + 0.5) / n_theta as f64;
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let phi = 2.0 * PI * (j as f64 + 0.5) / n_phi as f64;
+                f(&Vec3::new(
+                    sin_theta * phi.cos(),
+                    sin_theta * phi.sin(),
+                    cos_theta,
+                ))
+            })
+            .sum::<f64>()
+            * cell
+    }
+
+    fn distributions() -> [TrowbridgeReitz; 3] {
+        [
+            TrowbridgeReitz::new(0.3, 0.3),
+            TrowbridgeReitz::new(0.6, 0.6),
+            TrowbridgeReitz::new(0.25, 0.7),
+        ]
+    }
+
+    #[test]
+    fn projected_normals_cover_the_surface() {
+        for distribution in distributions() {
+            let integral = integrate_hemisphere(|wm| distribution.d(wm) * wm.z);
+            assert!((integral - 1.0).abs() < 1e-3, "{}", integral);
+        }
+    }
+
+    #[test]
+    fn visible_normals_pdf_integrates_to_one() {
+        for distribution in distributions() {
+            for w in [
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.5, 0.2, 0.8).normalize(),
+                Vec3::new(-0.3, 0.8, 0.3).normalize(),
+            ] {
+                let integral = integrate_hemisphere(|wm| distribution.d_visible(&w, wm));
+                assert!((integral - 1.0).abs() < 1e-3, "{}", integral);
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_normals_follow_the_visible_normals_pdf() {
+        // The average of the sampled normals matches the mean of the pdf
+        let distribution = TrowbridgeReitz::new(0.25, 0.7);
+        let w = Vec3::new(0.5, 0.2, 0.8).normalize();
+        let expected = Vec3::new(
+            integrate_hemisphere(|wm| wm.x * distribution.d_visible(&w, wm)),
+            integrate_hemisphere(|wm| wm.y * distribution.d_visible(&w, wm)),
+            integrate_hemisphere(|wm| wm.z * distribution.d_visible(&w, wm)),
+        );
+
+        let n = 500;
+        let mean = (0..n)
+            .flat_map(|i| (0..n).map(move |j| (i, j)))
+            .map(|(i, j)| {
+                let u1 = (i as f64 + 0.5) / n as f64;
+                let u2 = (j as f64 + 0.5) / n as f64;
+                distribution.sample_wm(&w, u1, u2)
+            })
+            .sum::<Vec3>()
+            / (n * n) as f64;
+
+        assert!((mean - expected).magnitude() < 1e-3, "{:?}", mean);
+    }
+
+    fn directions() -> impl Iterator<Item = Vec3> {
+        (0..10).map(|i| {
+            let theta = i as f64 / 10.0 * PI / 2.0;
+            Vec3::new(theta.sin() * 0.6, theta.sin() * 0.8, theta.cos())
+        })
+    }
+
+    #[test]
+    fn refraction_follows_snell_law_both_ways() {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        for eta in [1.33, 1.5, 2.4] {
+            for wo in directions() {
+                let wi = refract(&wo, &n, eta).unwrap();
+                let sin_o = (1.0 - wo.z * wo.z).sqrt();
+                let sin_i = (1.0 - wi.z * wi.z).sqrt();
+                assert!(wi.z < 0.0);
+                assert!((wi.magnitude() - 1.0).abs() < 1e-12);
+                assert!((sin_o - eta * sin_i).abs() < 1e-12);
+
+                let back = refract(&wi, &-n, 1.0 / eta).unwrap();
+                assert!((back - wo).magnitude() < 1e-12, "{:?} {:?}", wo, back);
+            }
+        }
+    }
+
+    #[test]
+    fn fresnel_is_reciprocal() {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        for eta in [1.33, 1.5, 2.4] {
+            for wo in directions() {
+                let wi = refract(&wo, &n, eta).unwrap();
+                let outside = fresnel_dielectric(wo.z, eta);
+                let inside = fresnel_dielectric(wi.z, eta);
+                assert!((0.0..=1.0).contains(&outside));
+                assert!((outside - inside).abs() < 1e-12);
+            }
+
+            let normal = ((eta - 1.0) / (eta + 1.0)).powi(2);
+            assert!((fresnel_dielectric(1.0, eta) - normal).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn total_internal_reflection() {
+        let eta: f64 = 1.5;
+        let critical = (1.0 / eta).asin();
+        let theta = critical + 0.01;
+        let wo = Vec3::new(theta.sin(), 0.0, -theta.cos());
+
+        assert!(refract(&wo, &Vec3::new(0.0, 0.0, -1.0), 1.0 / eta).is_none());
+        assert_eq!(fresnel_dielectric(wo.z, eta), 1.0);
+    }
+
+    #[test]
+    fn sampled_dielectric_weights_match_eval_over_pdf() {
+        for distribution in distributions() {
+            for eta in [1.5, 1.0 / 1.5] {
+                for wo in directions() {
+                    for i in 0..100 {
+                        let u = [(i % 10) as f64 / 10.0 + 0.05, (i / 10) as f64 / 10.0 + 0.05];
+                        for u3 in [0.01, 0.99] {
+                            let (wi, weight) = match sample_dielectric(
+                                &distribution,
+                                &wo,
+                                eta,
+                                [u[0], u[1], u3],
+                            ) {
+                                Some(sample) => sample,
+                                None => continue,
+                            };
+                            let pdf = pdf_dielectric(&distribution, &wo, &wi, eta);
+                            let f = eval_dielectric(&distribution, &wo, &wi, eta);
+                            assert!(pdf > 0.0);
+                            assert!(
+                                (f / pdf - weight).abs() < 1e-6 * weight.max(1.0),
+                                "{} {}",
+                                f / pdf,
+                                weight
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dielectric_pdf_integrates_to_the_sampled_fraction() {
+        // Samples leaving on the wrong side of the interface are discarded, so the pdf doesn't
+        // quite integrate to one. The lobes are kept wide for the integration to be accurate.
+        let distribution = TrowbridgeReitz::new(0.6, 0.6);
+        for eta in [1.5, 1.0 / 1.5] {
+            for wo in [
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.5, 0.2, 0.8).normalize(),
+            ] {
+                let reflected =
+                    integrate_hemisphere(|wi| pdf_dielectric(&distribution, &wo, wi, eta));
+                let transmitted = integrate_hemisphere(|wi| {
+                    pdf_dielectric(&distribution, &wo, &Vec3::new(wi.x, wi.y, -wi.z), eta)
+                });
+
+                // Averaged over the choice of the lobe
+                let n = 300;
+                let sampled = (0..n * n)
+                    .map(|i| {
+                        let u1 = (i % n) as f64 / n as f64 + 0.5 / n as f64;
+                        let u2 = (i / n) as f64 / n as f64 + 0.5 / n as f64;
+                        let wm = sample_dielectric_normal(&distribution, &wo, u1, u2);
+                        let reflectance = fresnel_dielectric(dot(wo, wm), eta);
+                        let kept = |transmit| {
+                            scatter_on_normal(&distribution, &wo, &wm, eta, transmit).is_some()
+                        };
+                        reflectance * kept(false) as u8 as f64
+                            + (1.0 - reflectance) * kept(true) as u8 as f64
+                    })
+                    .sum::<f64>()
+                    / (n * n) as f64;
+
+                let total = reflected + transmitted;
+                assert!((total - sampled).abs() < 2e-3, "{} {}", total, sampled);
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::materials::textures::Texture;
+use crate::materials::Material;
+use crate::random::{random_at, RNG};
+use crate::ray::Ray;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+
+/// Blend of two materials, such as rust patches on a metal. At each hit, `mask` gives the weight
+/// of the second material: the first channel of the texture, in `[0, 1]`.
+///
+/// Each hit scatters light with one of the two materials, picked at random according to the
+/// weight. The pick only depends on the hit point, so that lights are sampled and directions are
+/// weighted with the same material that scatters the path. Emission is either interpolated, or
+/// added on top of the first material's emission, which suits glowing decals.
+pub struct MixMaterial<A: Material, B: Material, T: Texture> {
+    first: A,
+    second: B,
+    mask: T,
+    additive_emission: bool,
+}
+
+impl<A: Material, B: Material, T: Texture> MixMaterial<A, B, T> {
+    pub fn new(first: A, second: B, mask: T) -> Self {
+        MixMaterial {
+            first,
+            second,
+            mask,
+            additive_emission: false,
+        }
+    }
+
+    /// Mix where the emission of `second`, weighted by the mask, is added to the full emission
+    /// of `first`.
+    pub fn new_additive(first: A, second: B, mask: T) -> Self {
+        MixMaterial {
+            first,
+            second,
+            mask,
+            additive_emission: true,
+        }
+    }
+
+    fn weight(&self, u: f64, v: f64, p: &Point3) -> f64 {
+        self.mask.value(u, v, p).x.clamp(0.0, 1.0)
+    }
+
+    /// Whether the second material is the one used at a hit.
+    fn picks_second(&self, rec: &HitRecord) -> bool {
+        // Salted with the address of the mix, so that nested mixes pick independently
+        random_at(&rec.p, self as *const Self as usize) < self.weight(rec.u, rec.v, &rec.p)
+    }
+
+    fn first_emission_weight(&self, weight: f64) -> f64 {
+        if self.additive_emission {
+            1.0
+        } else {
+            1.0 - weight
+        }
+    }
+}
+
+impl<A: Material, B: Material, T: Texture> Material for MixMaterial<A, B, T> {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
+        if self.picks_second(rec) {
+            self.second.scatter(ray, rec, rng)
+        } else {
+            self.first.scatter(ray, rec, rng)
+        }
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        if self.picks_second(rec) {
+            self.second.eval(rec, wo, wi)
+        } else {
+            self.first.eval(rec, wo, wi)
+        }
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f64 {
+        if self.picks_second(rec) {
+            self.second.pdf(rec, wo, wi)
+        } else {
+            self.first.pdf(rec, wo, wi)
+        }
+    }
+
+    fn attenuation_spectrum(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        scattered: &Ray,
+        attenuation: &Color,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        if self.picks_second(rec) {
+            self.second
+                .attenuation_spectrum(ray, rec, scattered, attenuation, lambdas)
+        } else {
+            self.first
+                .attenuation_spectrum(ray, rec, scattered, attenuation, lambdas)
+        }
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        let weight = self.weight(rec.u, rec.v, &rec.p);
+        self.first_emission_weight(weight) * self.first.emitted(ray, rec)
+            + weight * self.second.emitted(ray, rec)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        let weight = self.weight(rec.u, rec.v, &rec.p);
+        self.first.emitted_spectrum(ray, rec, lambdas) * self.first_emission_weight(weight)
+            + self.second.emitted_spectrum(ray, rec, lambdas) * weight
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.first.is_dispersive() || self.second.is_dispersive()
+    }
+}
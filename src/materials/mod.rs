@@ -1,18 +1,151 @@
 use crate::base::*;
 use crate::hit::hit_record::HitRecord;
+use crate::materials::medium::Medium;
 use crate::random::RNG;
 use crate::ray::Ray;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths, Wavelengths};
 
+pub mod alpha_mask;
+pub mod coated;
+pub mod conductor;
+pub mod density;
 pub mod dielectric;
 pub mod diffuse_light;
-pub mod isotropic;
+pub mod ior;
 pub mod lambertian;
+pub mod medium;
+pub mod medium_interface;
 pub mod metal;
+pub mod microfacet;
+pub mod mix;
+pub mod oren_nayar;
+pub mod phase;
+pub mod principled;
+pub mod rough_dielectric;
 pub mod textures;
+pub mod thin_film;
+pub mod volumetric;
 
 pub trait Material: Sync + Send {
     fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)>;
-    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+    /// BSDF times the cosine of the incident angle, for the outgoing direction `wo` and the
+    /// incident direction `wi` (both unit vectors pointing away from the surface). Materials that
+    /// only scatter in discrete directions keep the default, and can't be used for light sampling.
+    /// Transmissive materials may get `wo` on the far side of the surface (e.g. for the reverse
+    /// densities of bidirectional path tracing), and see the hit from there (`HitRecord::flipped`).
+    fn eval(&self, _rec: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> Color {
         Color::new(0.0, 0.0, 0.0)
     }
+    /// Density (per solid angle) with which `scatter` picks `wi` when leaving towards `wo`. It
+    /// may only approximate it (see `Coated`), as long as it is positive wherever `scatter`
+    /// picks directions with a density.
+    fn pdf(&self, _rec: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> f64 {
+        0.0
+    }
+    /// Radiance emitted at `rec` towards the origin of `ray`.
+    fn emitted(&self, _ray: &Ray, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+    /// Weight of a path scattered by `scatter` at the wavelengths it carries, when rendering in
+    /// spectral mode. `scattered` and `attenuation` are the direction and weight `scatter`
+    /// returned, the latter possibly reweighted by path guiding.
+    fn attenuation_spectrum(
+        &self,
+        _ray: &Ray,
+        _rec: &HitRecord,
+        _scattered: &Ray,
+        attenuation: &Color,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        lambdas.albedo(attenuation)
+    }
+    /// Emitted radiance at the wavelengths carried by a path, when rendering in spectral mode.
+    fn emitted_spectrum(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        lambdas.illuminant(&self.emitted(ray, rec))
+    }
+    /// Whether the surface is present at a point. Rays go through cut-out regions as if nothing
+    /// was hit.
+    fn is_opaque(&self, _u: f64, _v: f64, _p: &Point3, _rng: &mut RNG) -> bool {
+        true
+    }
+    /// Medium filling the inside of the surface, which paths enter when going through it.
+    fn interior_medium(&self) -> Option<&dyn Medium> {
+        None
+    }
+    /// Medium on the outer side of the surface, if it must be set when leaving the interior.
+    fn exterior_medium(&self) -> Option<&dyn Medium> {
+        None
+    }
+    /// Whether the surface only separates two media, and lets paths through unchanged.
+    fn is_interface(&self) -> bool {
+        false
+    }
+    /// Whether the scattered direction depends on the wavelength of the path.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+    /// Whether light is scattered inside a medium rather than at a surface, so the normal of the
+    /// interactions is meaningless.
+    fn is_volumetric(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+    use crate::materials::coated::Coated;
+    use crate::materials::principled::Principled;
+    use crate::materials::rough_dielectric::RoughDielectric;
+
+    #[test]
+    fn refracted_directions_have_a_density_both_ways() {
+        let materials: [Box<dyn Material>; 3] = [
+            Box::new(RoughDielectric::new(1.5, 0.4)),
+            Box::new(
+                Principled::from_color(Color::new(0.9, 0.9, 0.9))
+                    .with_transmission(1.0)
+                    .with_roughness(0.4),
+            ),
+            Box::new(Coated::new(RoughDielectric::new(1.3, 0.4), 1.5, 0.2)),
+        ];
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let wo = Vec3::new(0.4, 0.1, 1.0).normalize();
+        let ray = Ray::new(wo, -wo);
+
+        for material in materials.iter() {
+            for front_face in [true, false] {
+                let rec = HitRecord::new(
+                    1.0,
+                    0.0,
+                    0.0,
+                    Point3::new(0.0, 0.0, 0.0),
+                    material.as_ref(),
+                    &ray,
+                    &normal,
+                )
+                .with_tangent(&Vec3::new(1.0, 0.0, 0.0));
+                // Paths may enter or leave the material
+                let rec = HitRecord { front_face, ..rec };
+
+                let mut rng = RNG::from_seed(1);
+                let wi = (0..1000)
+                    .filter_map(|_| material.scatter(&ray, &rec, &mut rng))
+                    .map(|(_, scattered)| scattered.direction.normalize())
+                    .find(|wi| wi.z < 0.0)
+                    .unwrap();
+
+                assert!(material.pdf(&rec, &wo, &wi) > 0.0);
+                assert!(material.pdf(&rec, &wi, &wo) > 0.0);
+                assert!(material.eval(&rec, &wi, &wo) != Color::new(0.0, 0.0, 0.0));
+            }
+        }
+    }
 }
@@ -0,0 +1,128 @@
+use std::f64::consts::PI;
+
+use cgmath::{dot, InnerSpace};
+
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::materials::textures::{SolidColor, Texture};
+use crate::materials::Material;
+use crate::onb::Onb;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/*
+Rough diffuse reflection, using Fujii's formulation of the qualitative Oren-Nayar model. The
+energy compensated variant adds the multiple scattering term from Portsmouth et al., "EON: A
+practical energy-preserving rough diffuse BRDF" (2024), so that a white surface reflects all the
+incoming light whatever its roughness.
+ */
+
+const CONSTANT1: f64 = 0.5 - 2.0 / (3.0 * PI);
+const CONSTANT2: f64 = 2.0 / 3.0 - 28.0 / (15.0 * PI);
+
+/// Diffuse material for rough surfaces such as clay, concrete or the moon, which look flatter and
+/// brighter towards the light than a `Lambertian`. A roughness of 0 matches `Lambertian`.
+pub struct OrenNayar<T: Texture, R: Texture> {
+    albedo: T,
+    roughness: R,
+    energy_compensated: bool,
+}
+
+impl<T: Texture, R: Texture> OrenNayar<T, R> {
+    /// `roughness` is read from the first channel of the texture, in `[0, 1]`.
+    pub fn new(albedo: T, roughness: R) -> Self {
+        OrenNayar {
+            albedo,
+            roughness,
+            energy_compensated: false,
+        }
+    }
+
+    /// Variant which keeps the energy lost by interreflections between the surface's facets.
+    pub fn new_energy_compensated(albedo: T, roughness: R) -> Self {
+        OrenNayar {
+            albedo,
+            roughness,
+            energy_compensated: true,
+        }
+    }
+}
+
+impl OrenNayar<SolidColor, f64> {
+    pub fn from_color(color: Color, roughness: f64) -> Self {
+        OrenNayar::new(SolidColor::new(color), roughness)
+    }
+}
+
+/// Directional albedo of the single scattering term for a white surface.
+fn albedo_single_scattering(cos_theta: f64, roughness: f64) -> f64 {
+    let mu = cos_theta.clamp(1e-7, 1.0);
+    let a = 1.0 / (1.0 + CONSTANT1 * roughness);
+    let b = roughness * a;
+    let s = (1.0 - mu * mu).sqrt();
+    let g = s * (mu.acos() - s * mu) + 2.0 / 3.0 * ((s / mu) * (1.0 - s * s * s) - s);
+
+    a + b / PI * g
+}
+
+impl<T: Texture, R: Texture> OrenNayar<T, R> {
+    /// BSDF times the cosine, in the local shading frame.
+    fn eval_local(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let albedo = self.albedo.value(rec.u, rec.v, &rec.p);
+        let roughness = self.roughness.value(rec.u, rec.v, &rec.p).x.clamp(0.0, 1.0);
+
+        let s = dot(*wi, *wo) - wi.z * wo.z;
+        let s_over_t = if s > 0.0 { s / f64::max(wi.z, wo.z) } else { s };
+        let a = 1.0 / (1.0 + CONSTANT1 * roughness);
+        let mut f = albedo * (a * (1.0 + roughness * s_over_t) / PI);
+
+        if self.energy_compensated {
+            let eps = 1e-7;
+            let e_avg = a * (1.0 + CONSTANT2 * roughness);
+            let e_o = albedo_single_scattering(wo.z, roughness);
+            let e_i = albedo_single_scattering(wi.z, roughness);
+
+            // Albedo of the multiple scattering lobe, accounting for the color saturating with
+            // each bounce
+            let albedo_ms = Color::new(
+                albedo.x * albedo.x * e_avg / (1.0 - albedo.x * (1.0 - e_avg)),
+                albedo.y * albedo.y * e_avg / (1.0 - albedo.y * (1.0 - e_avg)),
+                albedo.z * albedo.z * e_avg / (1.0 - albedo.z * (1.0 - e_avg)),
+            );
+
+            f += albedo_ms
+                * (f64::max(eps, 1.0 - e_o) * f64::max(eps, 1.0 - e_i)
+                    / (PI * f64::max(eps, 1.0 - e_avg)));
+        }
+
+        f * wi.z
+    }
+}
+
+impl<T: Texture, R: Texture> Material for OrenNayar<T, R> {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
+        let onb = Onb::from_w(&rec.normal);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        let wi = rng.random_cosine_direction();
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return None;
+        }
+
+        let attenuation = self.eval_local(rec, &wo, &wi) * (PI / wi.z);
+
+        Some((attenuation, Ray::new(rec.p, onb.to_world(&wi))))
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        let onb = Onb::from_w(&rec.normal);
+        self.eval_local(rec, &onb.to_local(wo), &onb.to_local(wi))
+    }
+
+    fn pdf(&self, rec: &HitRecord, _wo: &Vec3, wi: &Vec3) -> f64 {
+        f64::max(0.0, dot(rec.normal, *wi)) / PI
+    }
+}
@@ -0,0 +1,156 @@
+use std::f64::consts::PI;
+
+use cgmath::InnerSpace;
+
+use crate::base::*;
+use crate::onb::Onb;
+use crate::random::RNG;
+
+/*
+Phase functions describe how light is scattered inside a participating medium, as the density of
+scattered directions relative to the direction the light was travelling in. They integrate to 1
+over the sphere, and are only parameterized by the cosine of the scattering angle.
+ */
+
+pub trait PhaseFunction: Sync + Send {
+    /// Density of light travelling along `direction` being scattered to `scattered`.
+    fn eval(&self, direction: &Vec3, scattered: &Vec3) -> f64;
+    /// Samples a scattered direction, distributed exactly with the density given by `eval`.
+    fn sample(&self, direction: &Vec3, rng: &mut RNG) -> Vec3;
+    /// Density (per solid angle) with which `sample` picks `scattered`.
+    fn pdf(&self, direction: &Vec3, scattered: &Vec3) -> f64 {
+        self.eval(direction, scattered)
+    }
+}
+
+fn cos_angle(direction: &Vec3, scattered: &Vec3) -> f64 {
+    direction.normalize().dot(scattered.normalize())
+}
+
+/// Builds a direction making an angle of cosine `cos_theta` with `direction`, at a random azimuth.
+fn around(direction: &Vec3, cos_theta: f64, rng: &mut RNG) -> Vec3 {
+    let sin_theta = f64::max(0.0, 1.0 - cos_theta * cos_theta).sqrt();
+    let phi = 2.0 * PI * rng.random_double();
+
+    Onb::from_w(direction).to_world(&Vec3::new(
+        sin_theta * phi.cos(),
+        sin_theta * phi.sin(),
+        cos_theta,
+    ))
+}
+
+/// Scatters light uniformly in all directions.
+#[derive(Clone, Copy)]
+pub struct Isotropic;
+
+impl PhaseFunction for Isotropic {
+    fn eval(&self, _direction: &Vec3, _scattered: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn sample(&self, direction: &Vec3, rng: &mut RNG) -> Vec3 {
+        around(direction, 1.0 - 2.0 * rng.random_double(), rng)
+    }
+}
+
+/// Henyey-Greenstein phase function, where the asymmetry parameter `g` in `(-1, 1)` is the
+/// average cosine of the scattering angle. Positive values scatter forward, as in fog or clouds
+/// which glow around lights behind them, and negative values scatter backward.
+#[derive(Clone, Copy)]
+pub struct HenyeyGreenstein {
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein {
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    fn eval_cos(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+}
+
+impl PhaseFunction for HenyeyGreenstein {
+    fn eval(&self, direction: &Vec3, scattered: &Vec3) -> f64 {
+        self.eval_cos(cos_angle(direction, scattered))
+    }
+
+    fn sample(&self, direction: &Vec3, rng: &mut RNG) -> Vec3 {
+        let g = self.g;
+        let u = rng.random_double();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s = (1.0 - g * g) / (1.0 + g - 2.0 * g * u);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+
+        around(direction, cos_theta.clamp(-1.0, 1.0), rng)
+    }
+}
+
+/// Blend of a forward and a backward Henyey-Greenstein lobe, which fits media such as clouds
+/// and smoke with both a strong forward peak and some back scattering better than a single lobe.
+#[derive(Clone, Copy)]
+pub struct DoubleHenyeyGreenstein {
+    forward: HenyeyGreenstein,
+    backward: HenyeyGreenstein,
+    forward_weight: f64,
+}
+
+impl DoubleHenyeyGreenstein {
+    /// `forward_weight` in `[0, 1]` is the share of light scattered by the lobe of asymmetry
+    /// `g_forward`, the rest going to the lobe of asymmetry `g_backward`.
+    pub fn new(g_forward: f64, g_backward: f64, forward_weight: f64) -> DoubleHenyeyGreenstein {
+        DoubleHenyeyGreenstein {
+            forward: HenyeyGreenstein::new(g_forward),
+            backward: HenyeyGreenstein::new(g_backward),
+            forward_weight: forward_weight.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl PhaseFunction for DoubleHenyeyGreenstein {
+    fn eval(&self, direction: &Vec3, scattered: &Vec3) -> f64 {
+        let cos_theta = cos_angle(direction, scattered);
+
+        self.forward_weight * self.forward.eval_cos(cos_theta)
+            + (1.0 - self.forward_weight) * self.backward.eval_cos(cos_theta)
+    }
+
+    fn sample(&self, direction: &Vec3, rng: &mut RNG) -> Vec3 {
+        if rng.random_double() < self.forward_weight {
+            self.forward.sample(direction, rng)
+        } else {
+            self.backward.sample(direction, rng)
+        }
+    }
+}
+
+/// Scattering by particles much smaller than the wavelength, such as the molecules of the air.
+/// Light is scattered as much forward as backward, and less to the sides.
+#[derive(Clone, Copy)]
+pub struct Rayleigh;
+
+impl PhaseFunction for Rayleigh {
+    fn eval(&self, direction: &Vec3, scattered: &Vec3) -> f64 {
+        let cos_theta = cos_angle(direction, scattered);
+
+        3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta)
+    }
+
+    fn sample(&self, direction: &Vec3, rng: &mut RNG) -> Vec3 {
+        // Inverts the CDF, which needs solving mu^3 + 3 mu = q with Cardano's formula
+        let q = 8.0 * rng.random_double() - 4.0;
+        let a = (0.5 * q + (0.25 * q * q + 1.0).sqrt()).cbrt();
+        let cos_theta = a - 1.0 / a;
+
+        around(direction, cos_theta.clamp(-1.0, 1.0), rng)
+    }
+}
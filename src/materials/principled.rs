@@ -0,0 +1,442 @@
+use std::f64::consts::PI;
+
+use cgmath::{dot, InnerSpace};
+
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::materials::microfacet::{
+    fresnel_dielectric, refract, refraction_half_vector, roughness_to_alpha, TrowbridgeReitz,
+};
+use crate::materials::textures::{SolidColor, Texture};
+use crate::materials::Material;
+use crate::onb::Onb;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/*
+Principled BSDF following Burley, "Physically Based Shading at Disney" (2012) and "Extending the
+Disney BRDF to a BSDF with Integrated Subsurface Scattering" (2015). It blends four lobes:
+  - a diffuse lobe with retro-reflection and sheen, for dielectrics
+  - an anisotropic GGX specular reflection, tinted by the base color for metals
+  - a GTR1 clearcoat layer with a fixed IOR of 1.5
+  - a rough dielectric transmission lobe, tinted by the base color
+Scalar parameters are read from the first channel of their texture, so plain numbers can be
+used for constant values.
+ */
+
+/// Uber-material covering most opaque and transparent surfaces with a small set of artist
+/// friendly parameters. Every parameter is in `[0, 1]`, except `ior`.
+pub struct Principled {
+    base_color: Box<dyn Texture>,
+    metallic: Box<dyn Texture>,
+    roughness: Box<dyn Texture>,
+    specular: Box<dyn Texture>,
+    specular_tint: Box<dyn Texture>,
+    anisotropic: Box<dyn Texture>,
+    sheen: Box<dyn Texture>,
+    sheen_tint: Box<dyn Texture>,
+    clearcoat: Box<dyn Texture>,
+    clearcoat_gloss: Box<dyn Texture>,
+    transmission: Box<dyn Texture>,
+    ior: Box<dyn Texture>,
+}
+
+impl Principled {
+    /// Rough dielectric with the given base color. Other parameters are set with the `with_*`
+    /// methods.
+    pub fn new<T: Texture + 'static>(base_color: T) -> Principled {
+        Principled {
+            base_color: Box::new(base_color),
+            metallic: Box::new(0.0),
+            roughness: Box::new(0.5),
+            specular: Box::new(0.5),
+            specular_tint: Box::new(0.0),
+            anisotropic: Box::new(0.0),
+            sheen: Box::new(0.0),
+            sheen_tint: Box::new(0.5),
+            clearcoat: Box::new(0.0),
+            clearcoat_gloss: Box::new(1.0),
+            transmission: Box::new(0.0),
+            ior: Box::new(1.5),
+        }
+    }
+
+    pub fn from_color(color: Color) -> Principled {
+        Principled::new(SolidColor::new(color))
+    }
+
+    pub fn with_metallic<T: Texture + 'static>(self, metallic: T) -> Principled {
+        Principled {
+            metallic: Box::new(metallic),
+            ..self
+        }
+    }
+
+    pub fn with_roughness<T: Texture + 'static>(self, roughness: T) -> Principled {
+        Principled {
+            roughness: Box::new(roughness),
+            ..self
+        }
+    }
+
+    /// Strength of the specular reflection of dielectrics, where 0.5 matches an IOR of 1.5.
+    pub fn with_specular<T: Texture + 'static>(self, specular: T) -> Principled {
+        Principled {
+            specular: Box::new(specular),
+            ..self
+        }
+    }
+
+    /// Tints the specular reflection of dielectrics towards the base color.
+    pub fn with_specular_tint<T: Texture + 'static>(self, specular_tint: T) -> Principled {
+        Principled {
+            specular_tint: Box::new(specular_tint),
+            ..self
+        }
+    }
+
+    /// Stretches the specular highlight along the direction in which the `u` texture coordinate
+    /// grows.
+    pub fn with_anisotropic<T: Texture + 'static>(self, anisotropic: T) -> Principled {
+        Principled {
+            anisotropic: Box::new(anisotropic),
+            ..self
+        }
+    }
+
+    /// Extra grazing reflection, as seen on cloth.
+    pub fn with_sheen<T: Texture + 'static>(self, sheen: T) -> Principled {
+        Principled {
+            sheen: Box::new(sheen),
+            ..self
+        }
+    }
+
+    pub fn with_sheen_tint<T: Texture + 'static>(self, sheen_tint: T) -> Principled {
+        Principled {
+            sheen_tint: Box::new(sheen_tint),
+            ..self
+        }
+    }
+
+    /// Strength of a thin glossy layer on top of the surface, like varnish or car paint.
+    pub fn with_clearcoat<T: Texture + 'static>(self, clearcoat: T) -> Principled {
+        Principled {
+            clearcoat: Box::new(clearcoat),
+            ..self
+        }
+    }
+
+    pub fn with_clearcoat_gloss<T: Texture + 'static>(self, clearcoat_gloss: T) -> Principled {
+        Principled {
+            clearcoat_gloss: Box::new(clearcoat_gloss),
+            ..self
+        }
+    }
+
+    /// Fraction of the dielectric part of the surface that refracts light instead of diffusing
+    /// it.
+    pub fn with_transmission<T: Texture + 'static>(self, transmission: T) -> Principled {
+        Principled {
+            transmission: Box::new(transmission),
+            ..self
+        }
+    }
+
+    pub fn with_ior<T: Texture + 'static>(self, ior: T) -> Principled {
+        Principled {
+            ior: Box::new(ior),
+            ..self
+        }
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> Lobes {
+        let color = |texture: &dyn Texture| texture.value(rec.u, rec.v, &rec.p);
+        let scalar = |texture: &dyn Texture| color(texture).x.clamp(0.0, 1.0);
+
+        let base_color = color(self.base_color.as_ref());
+        let metallic = scalar(self.metallic.as_ref());
+        let roughness = scalar(self.roughness.as_ref());
+        let transmission = scalar(self.transmission.as_ref());
+        let ior = f64::max(color(self.ior.as_ref()).x, 1.0 + 1e-4);
+
+        let luminance = dot(base_color, Color::new(0.3, 0.6, 0.1));
+        let tint = if luminance > 0.0 {
+            base_color / luminance
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        };
+        let white = Color::new(1.0, 1.0, 1.0);
+        let lerp = |t: f64, a: Color, b: Color| (1.0 - t) * a + t * b;
+
+        let specular_f0 = lerp(
+            metallic,
+            0.08 * scalar(self.specular.as_ref())
+                * lerp(scalar(self.specular_tint.as_ref()), white, tint),
+            base_color,
+        );
+
+        let aspect = (1.0 - 0.9 * scalar(self.anisotropic.as_ref())).sqrt();
+        let alpha = f64::max(1e-3, roughness_to_alpha(roughness));
+        let specular = TrowbridgeReitz::new(alpha / aspect, alpha * aspect);
+
+        let clearcoat_alpha = 0.1 + (0.001 - 0.1) * scalar(self.clearcoat_gloss.as_ref());
+
+        let diffuse_weight = (1.0 - metallic) * (1.0 - transmission);
+        let specular_weight = 1.0 - (1.0 - metallic) * transmission;
+        let clearcoat_weight = 0.25 * scalar(self.clearcoat.as_ref());
+        let transmission_weight = (1.0 - metallic) * transmission;
+
+        // Lobes are picked proportionally to their weight
+        let total = diffuse_weight + specular_weight + clearcoat_weight + transmission_weight;
+
+        Lobes {
+            base_color,
+            roughness,
+            sheen: scalar(self.sheen.as_ref())
+                * lerp(scalar(self.sheen_tint.as_ref()), white, tint),
+            specular_f0,
+            specular,
+            clearcoat_alpha,
+            eta: if rec.front_face { ior } else { 1.0 / ior },
+            diffuse_weight,
+            specular_weight,
+            clearcoat_weight,
+            transmission_weight,
+            probabilities: [
+                diffuse_weight / total,
+                specular_weight / total,
+                clearcoat_weight / total,
+                transmission_weight / total,
+            ],
+        }
+    }
+}
+
+/// Parameters of the lobes at a given hit point.
+struct Lobes {
+    base_color: Color,
+    roughness: f64,
+    sheen: Color,
+    specular_f0: Color,
+    specular: TrowbridgeReitz,
+    clearcoat_alpha: f64,
+    // Relative IOR of the far side of the surface over the side the ray comes from
+    eta: f64,
+    diffuse_weight: f64,
+    specular_weight: f64,
+    clearcoat_weight: f64,
+    transmission_weight: f64,
+    // Probabilities of sampling the diffuse, specular, clearcoat and transmission lobes
+    probabilities: [f64; 4],
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+/// GTR1 distribution used by the clearcoat, which has longer tails than GGX.
+fn gtr1(cos_theta_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_theta_h * cos_theta_h))
+}
+
+fn sample_gtr1(alpha: f64, u1: f64, u2: f64) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos_theta = ((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2)).sqrt();
+    let sin_theta = f64::max(0.0, 1.0 - cos_theta * cos_theta).sqrt();
+    let phi = 2.0 * PI * u2;
+
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+impl Lobes {
+    /// BSDF times the cosine, in the local shading frame where `wo.z > 0`.
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        let cos_o = wo.z;
+        let cos_i = wi.z;
+        if cos_o <= 0.0 || cos_i == 0.0 {
+            return black;
+        }
+
+        if cos_i < 0.0 {
+            return self.eval_refraction(wo, wi);
+        }
+
+        let wm = (wo + wi).normalize();
+        let cos_d = dot(*wi, wm);
+        let mut f = black;
+
+        if self.diffuse_weight > 0.0 {
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let fl = 1.0 + (fd90 - 1.0) * schlick_weight(cos_i);
+            let fv = 1.0 + (fd90 - 1.0) * schlick_weight(cos_o);
+            let diffuse = self.base_color * (fl * fv / PI);
+            let sheen = self.sheen * schlick_weight(cos_d);
+
+            f += self.diffuse_weight * (diffuse + sheen);
+        }
+
+        if self.specular_weight > 0.0 {
+            let fresnel = self.specular_f0
+                + (Color::new(1.0, 1.0, 1.0) - self.specular_f0) * schlick_weight(cos_d);
+            let specular = self.specular.d(&wm) * self.specular.g(wo, wi) / (4.0 * cos_i * cos_o);
+
+            f += fresnel * (self.specular_weight * specular);
+        }
+
+        if self.clearcoat_weight > 0.0 {
+            let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+            let g = TrowbridgeReitz::new(0.25, 0.25);
+            let clearcoat = gtr1(wm.z, self.clearcoat_alpha) * fresnel * g.g1(wo) * g.g1(wi)
+                / (4.0 * cos_i * cos_o);
+
+            f += Color::new(1.0, 1.0, 1.0) * (self.clearcoat_weight * clearcoat);
+        }
+
+        if self.transmission_weight > 0.0 {
+            let reflection = fresnel_dielectric(dot(*wo, wm), self.eta)
+                * self.specular.d(&wm)
+                * self.specular.g(wo, wi)
+                / (4.0 * cos_i * cos_o);
+
+            f += Color::new(1.0, 1.0, 1.0) * (self.transmission_weight * reflection);
+        }
+
+        f * cos_i
+    }
+
+    fn eval_refraction(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if self.transmission_weight <= 0.0 {
+            return black;
+        }
+
+        let (wm, denom) = match refraction_half_vector(wo, wi, self.eta) {
+            Some(h) => h,
+            None => return black,
+        };
+
+        let transmittance = 1.0 - fresnel_dielectric(dot(*wo, wm), self.eta);
+        // Radiance is compressed into a smaller solid angle when entering a denser medium
+        let ft = self.specular.d(&wm)
+            * transmittance
+            * self.specular.g(wo, wi)
+            * (dot(*wi, wm) * dot(*wo, wm) / (wi.z * wo.z * denom)).abs()
+            / (self.eta * self.eta);
+
+        self.base_color * (self.transmission_weight * ft * wi.z.abs())
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let [p_diffuse, p_specular, p_clearcoat, p_transmission] = self.probabilities;
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+
+        if wi.z < 0.0 {
+            if p_transmission <= 0.0 {
+                return 0.0;
+            }
+            return match refraction_half_vector(wo, wi, self.eta) {
+                Some((wm, denom)) => {
+                    let transmittance = 1.0 - fresnel_dielectric(dot(*wo, wm), self.eta);
+                    p_transmission * self.specular.d_visible(wo, &wm) * dot(*wi, wm).abs() / denom
+                        * transmittance
+                }
+                None => 0.0,
+            };
+        }
+
+        let wm = (wo + wi).normalize();
+        let reflection = self.specular.d_visible(wo, &wm) / (4.0 * dot(*wo, wm));
+
+        let mut pdf = p_diffuse * wi.z / PI + p_specular * reflection;
+        if p_clearcoat > 0.0 {
+            pdf += p_clearcoat * gtr1(wm.z, self.clearcoat_alpha) * wm.z / (4.0 * dot(*wo, wm));
+        }
+        if p_transmission > 0.0 {
+            pdf += p_transmission * reflection * fresnel_dielectric(dot(*wo, wm), self.eta);
+        }
+
+        pdf
+    }
+
+    fn sample(&self, wo: &Vec3, rng: &mut RNG) -> Option<Vec3> {
+        let [p_diffuse, p_specular, p_clearcoat, _] = self.probabilities;
+        let u = rng.random_double();
+
+        let wi = if u < p_diffuse {
+            rng.random_cosine_direction()
+        } else if u < p_diffuse + p_specular {
+            let wm = self
+                .specular
+                .sample_wm(wo, rng.random_double(), rng.random_double());
+            (-*wo).reflect(&wm)
+        } else if u < p_diffuse + p_specular + p_clearcoat {
+            let wm = sample_gtr1(
+                self.clearcoat_alpha,
+                rng.random_double(),
+                rng.random_double(),
+            );
+            (-*wo).reflect(&wm)
+        } else {
+            let wm = self
+                .specular
+                .sample_wm(wo, rng.random_double(), rng.random_double());
+            if rng.random_double() < fresnel_dielectric(dot(*wo, wm), self.eta) {
+                (-*wo).reflect(&wm)
+            } else {
+                refract(wo, &wm, self.eta)?
+            }
+        };
+
+        Some(wi)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
+        let lobes = self.lobes(rec);
+
+        let onb = Onb::from_w_and_u(&rec.normal, &rec.tangent);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let wi = lobes.sample(&wo, rng)?;
+
+        // The sampled lobe is weighted against the density of all the lobes, so the choice of
+        // lobe doesn't have to match their actual contribution
+        let pdf = lobes.pdf(&wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((
+            lobes.eval(&wo, &wi) / pdf,
+            Ray::new(rec.p, onb.to_world(&wi)),
+        ))
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        // Light leaving through the far side sees the surface from there, as `scatter` would
+        if dot(*wo, rec.normal) < 0.0 {
+            return self.eval(&rec.flipped(), wo, wi);
+        }
+
+        let onb = Onb::from_w_and_u(&rec.normal, &rec.tangent);
+        self.lobes(rec).eval(&onb.to_local(wo), &onb.to_local(wi))
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f64 {
+        if dot(*wo, rec.normal) < 0.0 {
+            return self.pdf(&rec.flipped(), wo, wi);
+        }
+
+        let onb = Onb::from_w_and_u(&rec.normal, &rec.tangent);
+        self.lobes(rec).pdf(&onb.to_local(wo), &onb.to_local(wi))
+    }
+}
@@ -0,0 +1,103 @@
+use cgmath::{dot, InnerSpace};
+
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::materials::microfacet::{
+    eval_dielectric, pdf_dielectric, roughness_to_alpha, sample_dielectric, TrowbridgeReitz,
+};
+use crate::materials::Material;
+use crate::onb::Onb;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/// Dielectric interface with a GGX distribution of microfacets (Walter et al. 2007), used for
+/// frosted glass and rough plastics. Reflection and refraction are chosen using the exact
+/// dielectric Fresnel equations evaluated on the sampled microfacet.
+pub struct RoughDielectric {
+    ir: f64,
+    distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectric {
+    pub fn new(ir: f64, roughness: f64) -> RoughDielectric {
+        RoughDielectric::new_anisotropic(ir, roughness, roughness)
+    }
+
+    /// Roughness along the direction in which the `u` texture coordinate grows, and across it.
+    pub fn new_anisotropic(ir: f64, roughness_u: f64, roughness_v: f64) -> RoughDielectric {
+        RoughDielectric {
+            ir,
+            distribution: TrowbridgeReitz::new(
+                roughness_to_alpha(roughness_u),
+                roughness_to_alpha(roughness_v),
+            ),
+        }
+    }
+
+    /// Relative IOR of the far side of the interface over the side the ray comes from.
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.ir
+        } else {
+            1.0 / self.ir
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
+        let eta = self.eta(rec);
+        let onb = Onb::from_w_and_u(&rec.normal, &rec.tangent);
+        let wo = onb.to_local(&-ray.direction.normalize());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let (wi, attenuation) = sample_dielectric(
+            &self.distribution,
+            &wo,
+            eta,
+            [
+                rng.random_double(),
+                rng.random_double(),
+                rng.random_double(),
+            ],
+        )?;
+
+        Some((
+            Color::new(attenuation, attenuation, attenuation),
+            Ray::new(rec.p, onb.to_world(&wi)),
+        ))
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        // Light leaving through the far side sees the interface from there, as `scatter` would
+        if dot(*wo, rec.normal) < 0.0 {
+            return self.eval(&rec.flipped(), wo, wi);
+        }
+
+        let onb = Onb::from_w_and_u(&rec.normal, &rec.tangent);
+        let f = eval_dielectric(
+            &self.distribution,
+            &onb.to_local(wo),
+            &onb.to_local(wi),
+            self.eta(rec),
+        );
+
+        Color::new(f, f, f)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f64 {
+        if dot(*wo, rec.normal) < 0.0 {
+            return self.pdf(&rec.flipped(), wo, wi);
+        }
+
+        let onb = Onb::from_w_and_u(&rec.normal, &rec.tangent);
+        pdf_dielectric(
+            &self.distribution,
+            &onb.to_local(wo),
+            &onb.to_local(wi),
+            self.eta(rec),
+        )
+    }
+}
@@ -1,3 +1,8 @@
+use std::fs::File;
+use std::path::Path;
+
+use png::{ColorType, Decoder, DecodingError, Transformations};
+
 use crate::base::*;
 
 pub trait Texture: Sync + Send {
@@ -19,3 +24,87 @@ impl Texture for SolidColor {
         self.color_value
     }
 }
+
+/// 3D checker pattern alternating between two textures, with squares of size `scale`.
+pub struct Checker<A: Texture, B: Texture> {
+    odd: A,
+    even: B,
+    scale: f64,
+}
+
+impl<A: Texture, B: Texture> Checker<A, B> {
+    pub fn new(odd: A, even: B, scale: f64) -> Checker<A, B> {
+        Checker { odd, even, scale }
+    }
+}
+
+impl<A: Texture, B: Texture> Texture for Checker<A, B> {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let cell = (p / self.scale).map(|x| x.floor() as i64);
+        if (cell.x + cell.y + cell.z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// A constant gray, mostly used for scalar material parameters.
+impl Texture for f64 {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::new(*self, *self, *self)
+    }
+}
+
+/// Texture mapped from a PNG image, using the `(u, v)` coordinates of the surface.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    /// Loads the image at `path`, or fails if it can't be opened or decoded. Colors are
+    /// converted to linear space with the same gamma of 2 used when writing renders.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ImageTexture, DecodingError> {
+        let file = File::open(path)?;
+        let mut decoder = Decoder::new(file);
+        decoder.set_transformations(Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        let channels = info.color_type.samples();
+        let to_linear = |c: u8| (c as f64 / 255.0).powi(2);
+        let pixels = buffer[..info.buffer_size()]
+            .chunks_exact(channels)
+            .map(|pixel| match info.color_type {
+                ColorType::Grayscale | ColorType::GrayscaleAlpha => {
+                    let gray = to_linear(pixel[0]);
+                    Color::new(gray, gray, gray)
+                }
+                _ => Color::new(
+                    to_linear(pixel[0]),
+                    to_linear(pixel[1]),
+                    to_linear(pixel[2]),
+                ),
+            })
+            .collect();
+
+        Ok(ImageTexture {
+            width: info.width as usize,
+            height: info.height as usize,
+            pixels,
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        // Images are stored from the top row down, while v goes up
+        let i = ((u.clamp(0.0, 1.0) * self.width as f64) as usize).min(self.width - 1);
+        let j = (((1.0 - v.clamp(0.0, 1.0)) * self.height as f64) as usize).min(self.height - 1);
+
+        self.pixels[j * self.width + i]
+    }
+}
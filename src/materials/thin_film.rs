@@ -0,0 +1,191 @@
+use std::f64::consts::PI;
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::materials::textures::Texture;
+use crate::spectrum::reflectance_to_rgb;
+
+/*
+Thin film interference, from the Airy summation of the waves reflected back and forth inside the
+film (see Born and Wolf, "Principles of Optics", 1.6). The film sits between the medium the light
+comes from and the base material, which may be a conductor.
+ */
+
+#[derive(Copy, Clone)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    fn real(re: f64) -> Complex {
+        Complex::new(re, 0.0)
+    }
+
+    fn norm2(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// Principal square root.
+    fn sqrt(self) -> Complex {
+        let n = self.norm2().sqrt();
+        let re = ((n + self.re) / 2.0).max(0.0).sqrt();
+        let im = ((n - self.re) / 2.0).max(0.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    /// `exp(i z)`
+    fn exp_i(self) -> Complex {
+        let scale = (-self.im).exp();
+        Complex::new(scale * self.re.cos(), scale * self.re.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let d = rhs.norm2();
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / d,
+            (self.im * rhs.re - self.re * rhs.im) / d,
+        )
+    }
+}
+
+/// Cosine of the angle of a wave refracted into a medium of index `n`, given the invariant
+/// `n sin(theta)` of the incident wave.
+fn refracted_cos(n: Complex, n_sin: f64) -> Complex {
+    let s = Complex::real(n_sin) / n;
+    (Complex::real(1.0) - s * s).sqrt()
+}
+
+/// Amplitude reflection coefficients for s and p polarizations at an interface.
+fn fresnel_amplitudes(
+    n1: Complex,
+    cos1: Complex,
+    n2: Complex,
+    cos2: Complex,
+) -> (Complex, Complex) {
+    let rs = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+    let rp = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+    (rs, rp)
+}
+
+/// Reflectance of a film of index `film` and `thickness` (in nm) over a base of complex index
+/// `base`, for light of the given wavelength (in nm) coming from a medium of index `outside`.
+fn thin_film_reflectance(
+    cos_theta: f64,
+    outside: f64,
+    film: f64,
+    thickness: f64,
+    base: Complex,
+    wavelength: f64,
+) -> f64 {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    let n_sin = outside * (1.0 - cos_theta * cos_theta).sqrt();
+
+    let n1 = Complex::real(outside);
+    let n2 = Complex::real(film);
+    let cos1 = Complex::real(cos_theta);
+    let cos2 = refracted_cos(n2, n_sin);
+    let cos3 = refracted_cos(base, n_sin);
+
+    let (r12s, r12p) = fresnel_amplitudes(n1, cos1, n2, cos2);
+    let (r23s, r23p) = fresnel_amplitudes(n2, cos2, base, cos3);
+
+    // Phase difference accumulated by a round trip inside the film
+    let phase = (Complex::real(4.0 * PI * film * thickness / wavelength) * cos2).exp_i();
+
+    let airy = |r12: Complex, r23: Complex| {
+        ((r12 + r23 * phase) / (Complex::real(1.0) + r12 * r23 * phase)).norm2()
+    };
+
+    ((airy(r12s, r23s) + airy(r12p, r23p)) / 2.0).clamp(0.0, 1.0)
+}
+
+/// Thin transparent layer on top of a surface, such as a soap film, oil or the oxide of anodised
+/// metals. Interference between the reflections on its two sides makes the reflectance vary with
+/// the wavelength and the viewing angle.
+pub struct ThinFilm {
+    thickness: Box<dyn Texture>,
+    ior: Box<dyn Texture>,
+}
+
+impl ThinFilm {
+    /// `thickness` is in nanometers. Both parameters are read from the first channel of their
+    /// texture.
+    pub fn new<T: Texture + 'static, I: Texture + 'static>(thickness: T, ior: I) -> ThinFilm {
+        ThinFilm {
+            thickness: Box::new(thickness),
+            ior: Box::new(ior),
+        }
+    }
+
+    /// Reflectance of the film on top of a base whose complex index `n + i k` at a given
+    /// wavelength is returned by `base`. Evaluated at the path's wavelength if it has one, and
+    /// averaged over each color channel otherwise.
+    pub fn reflectance(
+        &self,
+        rec: &HitRecord,
+        cos_theta: f64,
+        outside: f64,
+        base: impl Fn(f64) -> (f64, f64),
+        wavelength: Option<f64>,
+    ) -> Color {
+        let thickness = self.thickness.value(rec.u, rec.v, &rec.p).x.max(0.0);
+        let film = self.ior.value(rec.u, rec.v, &rec.p).x.max(1.0);
+
+        let reflectance = |lambda: f64| {
+            let (n, k) = base(lambda);
+            thin_film_reflectance(
+                cos_theta,
+                outside,
+                film,
+                thickness,
+                Complex::new(n, k),
+                lambda,
+            )
+        };
+
+        match wavelength {
+            Some(lambda) => {
+                let r = reflectance(lambda);
+                Color::new(r, r, r)
+            }
+            None => reflectance_to_rgb(reflectance),
+        }
+    }
+}
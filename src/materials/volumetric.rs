@@ -0,0 +1,75 @@
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::materials::phase::PhaseFunction;
+use crate::materials::textures::{SolidColor, Texture};
+use crate::materials::Material;
+use crate::random::RNG;
+use crate::ray::Ray;
+
+/// Scattering inside a participating medium, such as fog or smoke. `albedo` is the fraction of
+/// light scattered (rather than absorbed) at each interaction, and the phase function gives the
+/// distribution of scattered directions.
+pub struct Volumetric<T: Texture, P: PhaseFunction> {
+    albedo: T,
+    phase: P,
+    emission: Option<Box<dyn Texture>>,
+}
+
+impl<T: Texture, P: PhaseFunction> Volumetric<T, P> {
+    pub fn new(albedo: T, phase: P) -> Self {
+        Volumetric {
+            albedo,
+            phase,
+            emission: None,
+        }
+    }
+
+    /// Glowing medium, such as fire. Like in the radiative transfer equation, the emitted
+    /// radiance is scaled by the absorption of the medium, so only the fraction of the medium
+    /// which doesn't scatter light emits.
+    pub fn with_emission<E: Texture + 'static>(self, emission: E) -> Self {
+        Volumetric {
+            emission: Some(Box::new(emission)),
+            ..self
+        }
+    }
+}
+
+impl<P: PhaseFunction> Volumetric<SolidColor, P> {
+    pub fn from_color(color: Color, phase: P) -> Self {
+        Volumetric::new(SolidColor::new(color), phase)
+    }
+}
+
+impl<T: Texture, P: PhaseFunction> Material for Volumetric<T, P> {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut RNG) -> Option<(Color, Ray)> {
+        Some((
+            self.albedo.value(rec.u, rec.v, &rec.p),
+            Ray::new(rec.p, self.phase.sample(&ray.direction, rng)),
+        ))
+    }
+
+    // There is no cosine term in media, the phase function is used as is
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.phase.eval(&-*wo, wi)
+    }
+
+    fn pdf(&self, _rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f64 {
+        self.phase.pdf(&-*wo, wi)
+    }
+
+    fn emitted(&self, _ray: &Ray, rec: &HitRecord) -> Color {
+        match &self.emission {
+            Some(emission) => {
+                let absorption =
+                    Color::new(1.0, 1.0, 1.0) - self.albedo.value(rec.u, rec.v, &rec.p);
+                absorption.multiply_with(&emission.value(rec.u, rec.v, &rec.p))
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn is_volumetric(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,49 @@
+use cgmath::InnerSpace;
+
+use crate::base::*;
+
+/// Orthonormal basis used to move directions in and out of a local shading frame, where `w` is
+/// the surface normal and `u`/`v` span the tangent plane.
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(n: &Vec3) -> Onb {
+        let w = n.normalize();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).normalize();
+        let u = w.cross(v);
+
+        Onb { u, v, w }
+    }
+
+    /// Basis with `u` along the tangent `t`, projected onto the plane orthogonal to `n`, so the
+    /// frame follows the parameterization of the surface. Falls back to an arbitrary `u` when
+    /// the tangent is missing or parallel to the normal.
+    pub fn from_w_and_u(n: &Vec3, t: &Vec3) -> Onb {
+        let w = n.normalize();
+        let u = t - w * t.dot(w);
+        if u.magnitude2() <= 1e-12 * t.magnitude2() {
+            return Onb::from_w(n);
+        }
+        let u = u.normalize();
+        let v = w.cross(u);
+
+        Onb { u, v, w }
+    }
+
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+
+    pub fn to_world(&self, a: &Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+}
@@ -0,0 +1,83 @@
+use cgmath::{dot, InnerSpace};
+
+use crate::base::*;
+use crate::random::RNG;
+
+const POINT_COUNT: usize = 256;
+
+/// Perlin gradient noise, used for procedural textures and densities.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(rng: &mut RNG) -> Perlin {
+        Perlin {
+            gradients: (0..POINT_COUNT)
+                .map(|_| rng.random_vector_range(-1.0..1.0).normalize())
+                .collect(),
+            perm_x: generate_perm(rng),
+            perm_y: generate_perm(rng),
+            perm_z: generate_perm(rng),
+        }
+    }
+
+    /// Smooth noise in `[-1, 1]`, varying over distances of about 1.
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let floor = p.map(|x| x.floor());
+        let frac = p - floor;
+        let (i, j, k) = (floor.x as i64, floor.y as i64, floor.z as i64);
+
+        // Hermite smoothing of the interpolation weights
+        let smooth = frac.map(|x| x * x * (3.0 - 2.0 * x));
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize]];
+                    let (di, dj, dk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(frac.x - di, frac.y - dj, frac.z - dk);
+
+                    accum += (di * smooth.x + (1.0 - di) * (1.0 - smooth.x))
+                        * (dj * smooth.y + (1.0 - dj) * (1.0 - smooth.y))
+                        * (dk * smooth.z + (1.0 - dk) * (1.0 - smooth.z))
+                        * dot(gradient, weight);
+                }
+            }
+        }
+
+        accum
+    }
+
+    /// Sum of `depth` octaves of noise, each with twice the frequency and half the amplitude of
+    /// the previous one.
+    pub fn turbulence(&self, p: &Point3, depth: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(&p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+
+        accum.abs()
+    }
+}
+
+fn generate_perm(rng: &mut RNG) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        let target = rng.random_int(0..(i as i32 + 1)) as usize;
+        p.swap(i, target);
+    }
+
+    p
+}
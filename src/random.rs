@@ -1,31 +1,174 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::ops::Range;
 
 use cgmath::{dot, InnerSpace};
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
 
-use crate::base::Vec3;
+use crate::base::{Point3, Vec3};
 
+/// Source of the random numbers used to render the image. It is either a plain pseudo-random
+/// generator, or a point in primary sample space which Metropolis light transport mutates (see
+/// `new_primary`).
 pub struct RNG {
     rng: SmallRng,
+    primary: Option<PrimarySamples>,
+}
+
+/// Random number of a path in primary sample space, with its value before the current mutation.
+struct PrimarySample {
+    value: f64,
+    last_modified: u64,
+    backup: f64,
+    backup_modified: u64,
+}
+
+/// Vector of the random numbers drawn by a path, which is mutated lazily: each number is only
+/// updated when it is next drawn, with all the mutations it missed since it was last drawn.
+struct PrimarySamples {
+    samples: Vec<PrimarySample>,
+    /// Index of the next number drawn by the path.
+    index: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+    sigma: f64,
+    large_step_probability: f64,
+}
+
+impl PrimarySamples {
+    fn next(&mut self, rng: &mut SmallRng) -> f64 {
+        // Numbers the path didn't draw so far are uniform
+        if self.index == self.samples.len() {
+            let value = rng.gen();
+            self.samples.push(PrimarySample {
+                value,
+                last_modified: self.iteration,
+                backup: value,
+                backup_modified: self.iteration,
+            });
+            self.index += 1;
+            return value;
+        }
+
+        let sample = &mut self.samples[self.index];
+        self.index += 1;
+
+        // Numbers not drawn since the last large step are replaced by it
+        if sample.last_modified < self.last_large_step {
+            sample.value = rng.gen();
+            sample.last_modified = self.last_large_step;
+        }
+
+        sample.backup = sample.value;
+        sample.backup_modified = sample.last_modified;
+        if self.large_step {
+            sample.value = rng.gen();
+        } else {
+            // Small steps since the last modification add up to one normal perturbation
+            let steps = (self.iteration - sample.last_modified) as f64;
+            let normal = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt()
+                * (2.0 * std::f64::consts::PI * rng.gen::<f64>()).cos();
+            let value = (sample.value + normal * self.sigma * steps.sqrt()).rem_euclid(1.0);
+            sample.value = if value < 1.0 { value } else { 0.0 };
+        }
+        sample.last_modified = self.iteration;
+
+        sample.value
+    }
 }
 
 impl RNG {
     pub fn new() -> RNG {
         RNG {
             rng: SmallRng::from_entropy(),
+            primary: None,
+        }
+    }
+
+    /// Plain generator whose numbers only depend on `seed`.
+    pub fn from_seed(seed: u64) -> RNG {
+        RNG {
+            rng: SmallRng::seed_from_u64(seed),
+            primary: None,
+        }
+    }
+
+    /// Point in primary sample space, whose numbers are drawn uniformly from `seed` until it is
+    /// mutated. Mutations are either small steps, moving each number by a normal perturbation of
+    /// standard deviation `sigma`, or with probability `large_step_probability` large steps,
+    /// drawing every number again.
+    pub fn new_primary(seed: u64, sigma: f64, large_step_probability: f64) -> RNG {
+        RNG {
+            rng: SmallRng::seed_from_u64(seed),
+            primary: Some(PrimarySamples {
+                samples: Vec::new(),
+                index: 0,
+                iteration: 0,
+                large_step: true,
+                last_large_step: 0,
+                sigma,
+                large_step_probability,
+            }),
+        }
+    }
+
+    /// Draws the following mutations from entropy, so that chains starting from the same seed
+    /// of `new_primary` differ.
+    pub fn reseed(&mut self) {
+        self.rng = SmallRng::from_entropy();
+    }
+
+    /// Mutates the point in primary sample space, so that the numbers drawn next are those of a
+    /// proposed path. It then has to be accepted or rejected.
+    pub fn mutate(&mut self) {
+        if let Some(primary) = &mut self.primary {
+            primary.iteration += 1;
+            primary.large_step = self.rng.gen::<f64>() < primary.large_step_probability;
+            primary.index = 0;
+        }
+    }
+
+    /// Keeps the mutated point.
+    pub fn accept(&mut self) {
+        if let Some(primary) = &mut self.primary {
+            if primary.large_step {
+                primary.last_large_step = primary.iteration;
+            }
+        }
+    }
+
+    /// Restores the point as it was before the mutation.
+    pub fn reject(&mut self) {
+        if let Some(primary) = &mut self.primary {
+            for sample in &mut primary.samples {
+                if sample.last_modified == primary.iteration {
+                    sample.value = sample.backup;
+                    sample.last_modified = sample.backup_modified;
+                }
+            }
+            primary.iteration -= 1;
+        }
+    }
+
+    /// Uniform number in [0, 1), which all the other random values are built from.
+    fn next(&mut self) -> f64 {
+        match &mut self.primary {
+            Some(primary) => primary.next(&mut self.rng),
+            None => self.rng.gen(),
         }
     }
 
     pub fn random_vector(&mut self) -> Vec3 {
-        Vec3::new(self.rng.gen(), self.rng.gen(), self.rng.gen())
+        Vec3::new(self.next(), self.next(), self.next())
     }
 
     pub fn random_vector_range(&mut self, r: Range<f64>) -> Vec3 {
         Vec3::new(
-            self.rng.gen_range(r.clone()),
-            self.rng.gen_range(r.clone()),
-            self.rng.gen_range(r.clone()),
+            self.random_double_range(r.clone()),
+            self.random_double_range(r.clone()),
+            self.random_double_range(r),
         )
     }
 
@@ -54,24 +197,38 @@ impl RNG {
         }
     }
 
+    /// Random direction around +z, distributed with a density proportional to its z component.
+    pub fn random_cosine_direction(&mut self) -> Vec3 {
+        let r1 = self.next();
+        let r2 = self.next();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+
+        Vec3::new(
+            phi.cos() * r2.sqrt(),
+            phi.sin() * r2.sqrt(),
+            (1.0 - r2).sqrt(),
+        )
+    }
+
     pub fn random_int(&mut self, a: Range<i32>) -> i32 {
-        self.rng.gen_range(a)
+        let i = a.start + ((a.end - a.start) as f64 * self.next()) as i32;
+        i.min(a.end - 1)
     }
 
     pub fn random_double(&mut self) -> f64 {
-        self.rng.gen()
+        self.next()
     }
 
     pub fn random_double_range(&mut self, a: Range<f64>) -> f64 {
-        self.rng.gen_range(a)
+        a.start + (a.end - a.start) * self.next()
     }
 
     pub fn random_in_unit_disk(&mut self) -> Vec3 {
         loop {
             let p = Vec3::new(
-                self.rng.gen_range(-1.0..1.0),
-                self.rng.gen_range(-1.0..1.0),
-                self.rng.gen_range(-1.0..1.0),
+                self.random_double_range(-1.0..1.0),
+                self.random_double_range(-1.0..1.0),
+                self.random_double_range(-1.0..1.0),
             );
             if p.magnitude2() >= 1.0 {
                 continue;
@@ -80,3 +237,21 @@ impl RNG {
         }
     }
 }
+
+/// Hash of the bits of some numbers and of a salt, to seed randomness which must be the same
+/// every time the same numbers are given, such as the choices made when shading a point.
+pub fn seed_from(values: &[f64], salt: usize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for value in values {
+        value.to_bits().hash(&mut hasher);
+    }
+    salt.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+/// Uniform number in `[0, 1)` derived from a point, the same every time it is hit. Different
+/// salts, such as the addresses of the materials making a choice, give independent numbers.
+pub fn random_at(p: &Point3, salt: usize) -> f64 {
+    (seed_from(&[p.x, p.y, p.z], salt) >> 11) as f64 / (1u64 << 53) as f64
+}
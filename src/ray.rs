@@ -3,11 +3,25 @@ use crate::base::*;
 pub struct Ray {
     pub origin: Point3,
     pub direction: Point3,
+    /// Wavelength (in nm) the path carrying this ray is restricted to, once a wavelength
+    /// dependent material has been hit.
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
     pub(crate) fn new(origin: Point3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            wavelength: None,
+        }
+    }
+    pub(crate) fn new_with_wavelength(origin: Point3, direction: Vec3, wavelength: f64) -> Ray {
+        Ray {
+            origin,
+            direction,
+            wavelength: Some(wavelength),
+        }
     }
     pub(crate) fn at(&self, t: f64) -> Vec3 {
         self.origin + t * self.direction
@@ -3,23 +3,82 @@ use std::sync::Arc;
 use cgmath::InnerSpace;
 use clap::ArgEnum;
 
+use crate::aabb::AABB;
 use crate::base::*;
 use crate::camera::Camera;
 use crate::hit::constant_medium::ConstantMedium;
+use crate::hit::heterogeneous_medium::HeterogeneousMedium;
+use crate::hit::medium_boundary::MediumBoundary;
 use crate::hit::rectangle::{Cuboid, XYRect, XZRect, YZRect};
 use crate::hit::sphere::Sphere;
-use crate::hit::transform::Translate;
-use crate::hit::HittableList;
+use crate::hit::subsurface::Subsurface;
+use crate::hit::transform::{FlipFace, Translate};
+use crate::hit::{Hittable, HittableList};
+use crate::lights::directional::DirectionalLight;
+use crate::lights::point::PointLight;
+use crate::lights::rect::RectLight;
+use crate::lights::sphere::SphereLight;
+use crate::lights::spot::SpotLight;
+use crate::lights::Light;
+use crate::materials::alpha_mask::AlphaMask;
+use crate::materials::coated::Coated;
+use crate::materials::conductor::Conductor;
+use crate::materials::density::{Grid, NoiseDensity};
 use crate::materials::dielectric::Dielectric;
 use crate::materials::diffuse_light::DiffuseLight;
+use crate::materials::ior::Ior;
 use crate::materials::lambertian::Lambertian;
+use crate::materials::medium::{Homogeneous, Medium};
+use crate::materials::medium_interface::MediumInterface;
 use crate::materials::metal::Metal;
-use crate::materials::textures::SolidColor;
+use crate::materials::mix::MixMaterial;
+use crate::materials::oren_nayar::OrenNayar;
+use crate::materials::phase::{HenyeyGreenstein, Rayleigh};
+use crate::materials::principled::Principled;
+use crate::materials::rough_dielectric::RoughDielectric;
+use crate::materials::textures::{Checker, ImageTexture, SolidColor, Texture};
+use crate::materials::thin_film::ThinFilm;
+use crate::perlin::Perlin;
 use crate::random::RNG;
 use crate::ray::Ray;
+use crate::sky::Sky;
+use crate::spectrum::illuminants::{Illuminant, Spectrum};
 use crate::transform::RotateY;
 
-type Scene = (Camera, HittableList, Background);
+pub struct Scene {
+    pub camera: Camera,
+    pub world: HittableList,
+    pub background: Box<Background>,
+    /// Lights sampled with shadow rays.
+    pub lights: Vec<Arc<dyn Light>>,
+}
+
+impl Scene {
+    fn new<B>(camera: Camera, world: HittableList, background: B) -> Scene
+    where
+        B: Fn(&Ray) -> Color + Sync + Send + 'static,
+    {
+        Scene {
+            camera,
+            world,
+            background: Box::new(background),
+            lights: Vec::new(),
+        }
+    }
+
+    fn with_light<L: Light + 'static>(mut self, light: L) -> Scene {
+        self.lights.push(Arc::new(light));
+        self
+    }
+
+    /// Adds an emitting object which paths can hit, and which is also sampled as a light.
+    fn with_area_light<L: Light + Hittable + 'static>(mut self, light: L) -> Scene {
+        let light = Arc::new(light);
+        self.world.add(Box::new(light.clone()));
+        self.lights.push(light);
+        self
+    }
+}
 
 fn blue_sky(r: &Ray) -> Color {
     let unit_direction = r.direction.normalize();
@@ -34,6 +93,26 @@ pub enum Scenes {
     SimpleScene1,
     LightScene,
     CornellBox,
+    MetalScene,
+    GlassScene,
+    SpectralScene,
+    PrincipledScene,
+    RoughDiffuseScene,
+    CoatedScene,
+    MixScene,
+    ThinFilmScene,
+    SubsurfaceScene,
+    AlphaScene,
+    EmissionScene,
+    FogScene,
+    SmokeScene,
+    NestedMediaScene,
+    GodRaysScene,
+    PunctualLightsScene,
+    SunSkyScene,
+    CityAtNightScene,
+    CausticsScene,
+    WindowScene,
 }
 
 impl Scenes {
@@ -43,6 +122,26 @@ impl Scenes {
             Scenes::SimpleScene1 => simple_scene1(),
             Scenes::LightScene => light_scene(),
             Scenes::CornellBox => cornell_box(),
+            Scenes::MetalScene => metal_scene(),
+            Scenes::GlassScene => glass_scene(),
+            Scenes::SpectralScene => spectral_scene(),
+            Scenes::PrincipledScene => principled_scene(),
+            Scenes::RoughDiffuseScene => rough_diffuse_scene(),
+            Scenes::CoatedScene => coated_scene(),
+            Scenes::MixScene => mix_scene(),
+            Scenes::ThinFilmScene => thin_film_scene(),
+            Scenes::SubsurfaceScene => subsurface_scene(),
+            Scenes::AlphaScene => alpha_scene(),
+            Scenes::EmissionScene => emission_scene(),
+            Scenes::FogScene => fog_scene(),
+            Scenes::SmokeScene => smoke_scene(),
+            Scenes::NestedMediaScene => nested_media_scene(),
+            Scenes::GodRaysScene => god_rays_scene(),
+            Scenes::PunctualLightsScene => punctual_lights_scene(),
+            Scenes::SunSkyScene => sun_sky_scene(),
+            Scenes::CityAtNightScene => city_at_night_scene(),
+            Scenes::CausticsScene => caustics_scene(),
+            Scenes::WindowScene => window_scene(),
         }
     }
 }
@@ -135,7 +234,7 @@ fn random_scene1() -> Scene {
         dist_to_focus,
     );
 
-    return (cam, world, blue_sky);
+    return Scene::new(cam, world, blue_sky);
 }
 
 fn simple_scene1() -> Scene {
@@ -181,7 +280,7 @@ fn simple_scene1() -> Scene {
         1.0,
     );
 
-    return (cam, world, blue_sky);
+    return Scene::new(cam, world, blue_sky);
 }
 
 fn light_scene() -> Scene {
@@ -211,7 +310,7 @@ fn light_scene() -> Scene {
         10.0,
     );
 
-    (cam, world, |_| Color::new(0.0, 0.0, 0.0))
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0))
 }
 
 fn cornell_box() -> Scene {
@@ -220,13 +319,10 @@ fn cornell_box() -> Scene {
     let red = Arc::new(Lambertian::from_color(Color::new(0.65, 0.05, 0.05)));
     let white = Arc::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73)));
     let green = Arc::new(Lambertian::from_color(Color::new(0.12, 0.45, 0.15)));
-    let light = Arc::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
+    let light = Arc::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)).one_sided());
 
     world.add(Box::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)));
     world.add(Box::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)));
-    world.add(Box::new(XZRect::new(
-        213.0, 343.0, 227.0, 332.0, 554.0, light,
-    )));
     world.add(Box::new(XZRect::new(
         0.0,
         555.0,
@@ -281,6 +377,1283 @@ fn cornell_box() -> Scene {
         10.0,
     );
 
-    (cam, world, |_| Color::new(0.0, 0.0, 0.0))
-    // (cam, world, blue_sky)
+    // The light only shines downwards, into the box
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0))
+        .with_area_light(RectLight::new(213.0, 343.0, 227.0, 332.0, 554.0, light).flipped())
+    // Scene::new(cam, world, blue_sky)
+}
+
+fn metal_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(-3.3, 1.0, 0.0),
+        1.0,
+        Arc::new(Conductor::gold(0.2)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.1, 1.0, 0.0),
+        1.0,
+        Arc::new(Conductor::copper(0.4)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(1.1, 1.0, 0.0),
+        1.0,
+        Arc::new(Conductor::silver(0.0)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(3.3, 1.0, 0.0),
+        1.0,
+        Arc::new(Conductor::new_anisotropic(
+            Color::new(1.65746, 0.880369, 0.521229),
+            Color::new(9.22387, 6.26952, 4.837),
+            0.1,
+            0.6,
+        )),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 2.5, 10.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, blue_sky)
+}
+
+fn glass_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    // Colored spheres behind the glass, to show how each material blurs what is seen through it
+    for (i, color) in [
+        Color::new(0.8, 0.1, 0.1),
+        Color::new(0.1, 0.8, 0.1),
+        Color::new(0.1, 0.1, 0.8),
+    ]
+    .iter()
+    .enumerate()
+    {
+        world.add(Box::new(Sphere::new(
+            Point3::new(-2.2 + 2.2 * (i as f64), 0.5, -3.0),
+            0.5,
+            Arc::new(Lambertian::from_color(*color)),
+        )));
+    }
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(-2.2, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(RoughDielectric::new(1.5, 0.3)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(2.2, 1.0, 0.0),
+        1.0,
+        Arc::new(RoughDielectric::new(1.5, 0.6)),
+    )));
+
+    // Tinted glass in front of the row
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.1, 0.5, 2.0),
+        0.5,
+        Arc::new(Dielectric::new_absorbing(
+            1.5,
+            Color::new(0.9, 0.4, 0.1),
+            0.5,
+        )),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(1.1, 0.5, 2.0),
+        0.5,
+        Arc::new(Dielectric::new_absorbing(
+            1.33,
+            Color::new(0.3, 0.7, 0.9),
+            0.5,
+        )),
+    )));
+
+    // Dispersive glass, which splits the sky and the spheres behind into colored fringes
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.35, 2.8),
+        0.35,
+        Arc::new(Dielectric::new_dispersive(Ior::dense_flint())),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 2.0, 9.0),
+        &Vec3::new(0.0, 0.8, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, blue_sky)
+}
+
+fn spectral_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73))),
+    )));
+
+    let colors = [
+        Color::new(0.8, 0.1, 0.1),
+        Color::new(0.8, 0.6, 0.1),
+        Color::new(0.1, 0.6, 0.2),
+        Color::new(0.1, 0.3, 0.8),
+    ];
+    for (i, color) in colors.iter().enumerate() {
+        world.add(Box::new(Sphere::new(
+            Point3::new(-3.0 + 2.0 * i as f64, 0.8, 0.0),
+            0.8,
+            Arc::new(Lambertian::from_color(*color)),
+        )));
+    }
+
+    // Lights of equal luminance but different spectra, which only differ when rendered with
+    // --spectral
+    let lights = [
+        Spectrum::blackbody(2700.0),
+        Spectrum::illuminant(Illuminant::D65),
+        Spectrum::illuminant(Illuminant::F11),
+    ];
+    for (i, spectrum) in lights.iter().enumerate() {
+        let x = -3.5 + 2.5 * i as f64;
+        world.add(Box::new(XZRect::new(
+            x,
+            x + 2.0,
+            -1.0,
+            1.0,
+            4.0,
+            Arc::new(DiffuseLight::from_spectrum(*spectrum, 6.0)),
+        )));
+    }
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 3.0, 12.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0))
+}
+
+fn principled_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    let base_color = Color::new(0.8, 0.3, 0.1);
+
+    // Back row: roughness of a metal, then of a dielectric, increasing from left to right
+    for i in 0..5 {
+        let roughness = i as f64 / 4.0;
+        world.add(Box::new(Sphere::new(
+            Point3::new(-4.4 + 2.2 * i as f64, 1.0, -2.2),
+            1.0,
+            Arc::new(
+                Principled::from_color(base_color)
+                    .with_metallic(1.0)
+                    .with_roughness(roughness),
+            ),
+        )));
+        world.add(Box::new(Sphere::new(
+            Point3::new(-4.4 + 2.2 * i as f64, 1.0, 0.0),
+            1.0,
+            Arc::new(Principled::from_color(base_color).with_roughness(roughness)),
+        )));
+    }
+
+    // Front row: the remaining lobes
+    let front = [
+        Principled::from_color(Color::new(0.1, 0.2, 0.6))
+            .with_roughness(0.6)
+            .with_clearcoat(1.0),
+        Principled::from_color(Color::new(0.8, 0.8, 0.8))
+            .with_metallic(1.0)
+            .with_roughness(0.4)
+            .with_anisotropic(1.0),
+        Principled::from_color(Color::new(0.5, 0.1, 0.3))
+            .with_roughness(1.0)
+            .with_sheen(1.0),
+        Principled::from_color(Color::new(1.0, 1.0, 1.0))
+            .with_roughness(0.0)
+            .with_transmission(1.0),
+        Principled::from_color(Color::new(0.7, 0.9, 0.8))
+            .with_roughness(0.3)
+            .with_transmission(1.0)
+            .with_ior(1.33),
+    ];
+    for (i, material) in front.into_iter().enumerate() {
+        world.add(Box::new(Sphere::new(
+            Point3::new(-4.4 + 2.2 * i as f64, 1.0, 2.2),
+            1.0,
+            Arc::new(material),
+        )));
+    }
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 6.0, 14.0),
+        &Vec3::new(0.0, 0.8, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, blue_sky)
+}
+
+fn rough_diffuse_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.3, 0.3, 0.3))),
+    )));
+
+    // Lambertian, then Oren-Nayar and its energy compensated variant at full roughness
+    let albedo = Color::new(0.9, 0.85, 0.8);
+    world.add(Box::new(Sphere::new(
+        Point3::new(-2.2, 1.0, 0.0),
+        1.0,
+        Arc::new(Lambertian::from_color(albedo)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(OrenNayar::from_color(albedo, 1.0)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(2.2, 1.0, 0.0),
+        1.0,
+        Arc::new(OrenNayar::new_energy_compensated(
+            SolidColor::new(albedo),
+            1.0,
+        )),
+    )));
+
+    // Light placed behind the camera, where rough surfaces stand out the most
+    let light = Arc::new(DiffuseLight::from_color(Color::new(8.0, 8.0, 8.0)));
+    world.add(Box::new(XYRect::new(-3.0, 3.0, 1.0, 5.0, 12.0, light)));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 2.0, 10.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0))
+}
+
+fn coated_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    // Ceramic: smooth coat over a white diffuse base
+    world.add(Box::new(Sphere::new(
+        Point3::new(-3.3, 1.0, 0.0),
+        1.0,
+        Arc::new(Coated::new(
+            Lambertian::from_color(Color::new(0.8, 0.8, 0.8)),
+            1.5,
+            0.0,
+        )),
+    )));
+    // Car paint: clear coat over a rough metallic base
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.1, 1.0, 0.0),
+        1.0,
+        Arc::new(Coated::new(
+            Conductor::new(Color::new(0.2, 1.2, 1.5), Color::new(3.0, 1.0, 1.0), 0.5),
+            1.5,
+            0.05,
+        )),
+    )));
+    // Varnished wood: thick tinted coat over a rough diffuse base
+    world.add(Box::new(Sphere::new(
+        Point3::new(1.1, 1.0, 0.0),
+        1.0,
+        Arc::new(Coated::new_absorbing(
+            OrenNayar::from_color(Color::new(0.6, 0.35, 0.2), 0.5),
+            1.5,
+            0.1,
+            0.1,
+            Color::new(1.0, 3.0, 8.0),
+        )),
+    )));
+    // Rough coat, which blurs the reflection of the surroundings
+    world.add(Box::new(Sphere::new(
+        Point3::new(3.3, 1.0, 0.0),
+        1.0,
+        Arc::new(Coated::new(
+            Lambertian::from_color(Color::new(0.1, 0.3, 0.6)),
+            1.5,
+            0.3,
+        )),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 3.0, 12.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, blue_sky)
+}
+
+fn mix_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    // Patches of rust on polished steel
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.2, 1.0, 0.0),
+        1.0,
+        Arc::new(MixMaterial::new(
+            Conductor::aluminium(0.15),
+            OrenNayar::from_color(Color::new(0.45, 0.18, 0.07), 1.0),
+            Checker::new(1.0, 0.0, 0.4),
+        )),
+    )));
+
+    // Glowing decal painted over a diffuse surface
+    world.add(Box::new(Sphere::new(
+        Point3::new(1.2, 1.0, 0.0),
+        1.0,
+        Arc::new(MixMaterial::new_additive(
+            Lambertian::from_color(Color::new(0.2, 0.2, 0.6)),
+            DiffuseLight::from_color(Color::new(4.0, 2.0, 0.5)),
+            Checker::new(0.0, 1.0, 0.5),
+        )),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 2.0, 10.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, blue_sky)
+}
+
+/// Film thickness of a soap bubble, which gets thinner towards the top as the liquid drains.
+struct BubbleThickness;
+
+impl Texture for BubbleThickness {
+    fn value(&self, _u: f64, v: f64, _p: &Point3) -> Color {
+        let thickness = 150.0 + 700.0 * (1.0 - v);
+        Color::new(thickness, thickness, thickness)
+    }
+}
+
+fn thin_film_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.1, 0.1, 0.1))),
+    )));
+
+    // Soap bubble: a film of water with air on both sides
+    world.add(Box::new(Sphere::new(
+        Point3::new(-2.2, 1.2, 0.0),
+        1.0,
+        Arc::new(Dielectric::new(1.0).with_thin_film(ThinFilm::new(BubbleThickness, 1.33))),
+    )));
+    // Glass with an oily film
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::new(1.5).with_thin_film(ThinFilm::new(400.0, 1.4))),
+    )));
+    // Anodised titanium: a layer of titanium dioxide over the metal
+    world.add(Box::new(Sphere::new(
+        Point3::new(2.2, 1.0, 0.0),
+        1.0,
+        Arc::new(
+            Conductor::new(
+                Color::new(2.74, 2.54, 2.16),
+                Color::new(3.81, 3.43, 2.93),
+                0.15,
+            )
+            .with_thin_film(ThinFilm::new(70.0, 2.4)),
+        ),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 2.0, 10.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, blue_sky)
+}
+
+fn subsurface_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    let boundary_material = Arc::new(Lambertian::from_color(Color::new(0.0, 0.0, 0.0)));
+
+    // Skin: red light travels further than blue
+    world.add(Box::new(Subsurface::new(
+        Sphere::new(Point3::new(-2.2, 1.0, 0.0), 1.0, boundary_material.clone()),
+        1.4,
+        Color::new(0.99, 0.9, 0.8),
+        Color::new(0.6, 0.25, 0.15),
+        0.0,
+    )));
+    // Wax
+    world.add(Box::new(Subsurface::new(
+        Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, boundary_material.clone()),
+        1.45,
+        Color::new(0.99, 0.95, 0.8),
+        Color::new(0.3, 0.3, 0.3),
+        0.3,
+    )));
+    // Marble, from its scattering and absorption coefficients
+    world.add(Box::new(Subsurface::new_from_coefficients(
+        Sphere::new(Point3::new(2.2, 1.0, 0.0), 1.0, boundary_material),
+        1.5,
+        Color::new(4.0, 4.5, 5.0),
+        Color::new(0.02, 0.03, 0.05),
+        0.0,
+    )));
+
+    let light = Arc::new(DiffuseLight::from_color(Color::new(10.0, 10.0, 10.0)));
+    world.add(Box::new(XZRect::new(-2.0, 2.0, -3.0, -1.0, 5.0, light)));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 2.0, 10.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, |r| blue_sky(r) * 0.2)
+}
+
+/// Opacity of a wire fence: a grid of thin bars over the unit square.
+struct FenceAlpha;
+
+impl Texture for FenceAlpha {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        let bar = |x: f64| (x * 12.0).fract() < 0.12;
+        if bar(u) || bar(v) {
+            Color::new(1.0, 1.0, 1.0)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+}
+
+fn alpha_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.4, 0.6, 0.3))),
+    )));
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.2, 1.0, -1.0),
+        1.0,
+        Arc::new(Lambertian::from_color(Color::new(0.8, 0.3, 0.1))),
+    )));
+    // Half transparent sphere, letting through half of the rays
+    world.add(Box::new(Sphere::new(
+        Point3::new(1.2, 1.0, -1.0),
+        1.0,
+        Arc::new(AlphaMask::new(
+            Lambertian::from_color(Color::new(0.1, 0.3, 0.8)),
+            0.5,
+        )),
+    )));
+
+    // Fence in front of the spheres
+    world.add(Box::new(XYRect::new(
+        -3.0,
+        3.0,
+        0.0,
+        2.5,
+        1.0,
+        Arc::new(AlphaMask::new_thresholded(
+            Metal::new(Color::new(0.7, 0.7, 0.7), 0.3),
+            FenceAlpha,
+            0.5,
+        )),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 2.0, 10.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, blue_sky)
+}
+
+fn emission_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let floor = Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.5)));
+    world.add(Box::new(XZRect::new(
+        -20.0,
+        20.0,
+        -20.0,
+        20.0,
+        0.0,
+        floor.clone(),
+    )));
+    world.add(Box::new(XYRect::new(-20.0, 20.0, 0.0, 10.0, -3.0, floor)));
+
+    // Television showing a previous render, found from the crate root wherever the renderer
+    // is run from
+    let screen = concat!(env!("CARGO_MANIFEST_DIR"), "/renders/book1.png");
+    let screen = ImageTexture::open(screen)
+        .unwrap_or_else(|err| panic!("could not read the texture {}: {}", screen, err));
+    world.add(Box::new(Cuboid::new(
+        Point3::new(-2.1, 0.4, -2.2),
+        Point3::new(2.1, 2.9, -2.0),
+        Arc::new(Lambertian::from_color(Color::new(0.05, 0.05, 0.05))),
+    )));
+    world.add(Box::new(XYRect::new(
+        -2.0,
+        2.0,
+        0.5,
+        2.8,
+        -1.99,
+        Arc::new(DiffuseLight::new(screen).with_strength(2.0)),
+    )));
+
+    // Warm lamp, emitting like a black body
+    world.add(Box::new(Sphere::new(
+        Point3::new(3.5, 1.5, -1.0),
+        0.3,
+        Arc::new(DiffuseLight::from_temperature(2700.0, 8.0)),
+    )));
+
+    // Ceiling panel only lighting the floor under it
+    world.add(Box::new(FlipFace::new(XZRect::new(
+        -4.5,
+        -3.0,
+        -1.5,
+        0.0,
+        4.0,
+        Arc::new(DiffuseLight::from_color(Color::new(4.0, 4.0, 5.0)).one_sided()),
+    ))));
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.0, 0.4, 1.0),
+        0.4,
+        Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.05)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(1.2, 0.4, 1.2),
+        0.4,
+        Arc::new(Lambertian::from_color(Color::new(0.7, 0.7, 0.7))),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 3.0, 10.0),
+        &Vec3::new(0.0, 1.2, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0))
+}
+
+fn fog_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        Arc::new(Lambertian::from_color(Color::new(0.4, 0.4, 0.4))),
+    )));
+
+    // Street lamps behind the fog, with a halo from forward scattering
+    for x in [-4.0, 0.0, 4.0] {
+        world.add(Box::new(Sphere::new(
+            Point3::new(x, 3.0, -6.0),
+            0.5,
+            Arc::new(DiffuseLight::from_temperature(3000.0, 20.0)),
+        )));
+    }
+
+    world.add(Box::new(ConstantMedium::new_with_phase(
+        Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            30.0,
+            Arc::new(Lambertian::from_color(Color::new(0.0, 0.0, 0.0))),
+        ),
+        0.03,
+        SolidColor::new(Color::new(0.9, 0.9, 0.9)),
+        HenyeyGreenstein::new(0.85),
+    )));
+
+    // Small cloud of air-like particles, scattering blue light to the sides
+    world.add(Box::new(ConstantMedium::new_with_phase(
+        Sphere::new(
+            Point3::new(2.0, 1.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::from_color(Color::new(0.0, 0.0, 0.0))),
+        ),
+        1.0,
+        SolidColor::new(Color::new(0.3, 0.5, 0.9)),
+        Rayleigh,
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 1.5, 10.0),
+        &Vec3::new(0.0, 2.0, -6.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0))
+}
+
+fn smoke_scene() -> Scene {
+    let mut world = HittableList::new();
+    let mut rng = RNG::new();
+
+    world.add(Box::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        Arc::new(Lambertian::from_color(Color::new(0.4, 0.4, 0.4))),
+    )));
+    world.add(Box::new(FlipFace::new(XZRect::new(
+        -4.0,
+        -1.0,
+        -2.0,
+        1.0,
+        6.0,
+        Arc::new(DiffuseLight::from_color(Color::new(6.0, 6.0, 6.0)).one_sided()),
+    ))));
+
+    // Puff of smoke with a procedural density
+    world.add(Box::new(HeterogeneousMedium::new_with_phase(
+        Sphere::new(
+            Point3::new(-2.0, 1.5, 0.0),
+            1.5,
+            Arc::new(Lambertian::from_color(Color::new(0.0, 0.0, 0.0))),
+        ),
+        NoiseDensity::new(1.2, 5, 0.25, &mut rng),
+        15.0,
+        SolidColor::new(Color::new(0.8, 0.8, 0.8)),
+        HenyeyGreenstein::new(0.3),
+    )));
+
+    // Flame, with density and temperature baked into grids
+    let min = Point3::new(1.0, 0.0, -0.8);
+    let max = Point3::new(3.0, 3.0, 0.8);
+    let resolution = [32, 48, 32];
+    let noise = Perlin::new(&mut rng);
+    let flame = move |p: Point3| {
+        let height = p.y / 3.0;
+        let radius = 0.7 * (1.0 - height) * (1.0 + 0.5 * noise.turbulence(&(p * 3.0), 4));
+        let distance = ((p.x - 2.0).powi(2) + p.z.powi(2)).sqrt();
+        f64::max(0.0, 1.0 - distance / radius)
+    };
+    let temperature = |core: f64, height: f64| 1000.0 + 1200.0 * core * (1.0 - height);
+    let emission = Grid::from_fn(AABB::new(min, max), resolution, |p| {
+        let core = flame(p);
+        if core <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let t = temperature(core, p.y / 3.0);
+        Spectrum::blackbody(t).to_rgb() * (t / 1000.0).powi(4)
+    });
+    let density = Grid::from_fn(AABB::new(min, max), resolution, flame);
+
+    world.add(Box::new(
+        HeterogeneousMedium::new(
+            Cuboid::new(
+                min,
+                max,
+                Arc::new(Lambertian::from_color(Color::new(0.0, 0.0, 0.0))),
+            ),
+            density,
+            8.0,
+            SolidColor::new(Color::new(0.1, 0.1, 0.1)),
+        )
+        .with_emission(emission),
+    ));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 2.5, 12.0),
+        &Vec3::new(0.0, 1.5, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0))
+}
+
+fn nested_media_scene() -> Scene {
+    let mut world = HittableList::new();
+    let black = Arc::new(Lambertian::from_color(Color::new(0.0, 0.0, 0.0)));
+
+    world.add(Box::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        Arc::new(Lambertian::from_color(Color::new(0.6, 0.6, 0.6))),
+    )));
+    world.add(Box::new(FlipFace::new(XZRect::new(
+        -3.0,
+        3.0,
+        -2.0,
+        2.0,
+        6.0,
+        Arc::new(DiffuseLight::from_color(Color::new(5.0, 5.0, 5.0)).one_sided()),
+    ))));
+
+    // Tank of murky water, holding a glass bottle filled with fog, which replaces the water
+    world.add(Box::new(MediumBoundary::new(
+        Cuboid::new(
+            Point3::new(-3.5, 0.01, -2.5),
+            Point3::new(0.8, 2.6, 1.5),
+            black.clone(),
+        ),
+        Arc::new(Homogeneous::from_color(0.15, Color::new(0.3, 0.7, 0.8))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.3, 1.1, -0.5),
+        1.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.3, 1.1, -0.5),
+        -0.95,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    world.add(Box::new(MediumBoundary::new(
+        Sphere::new(Point3::new(-1.3, 1.1, -0.5), 0.94, black.clone()),
+        Arc::new(Homogeneous::from_color(3.0, Color::new(0.95, 0.9, 0.85))),
+    )));
+
+    // Hollow shell of smoke, whose boundary is made of two spheres
+    let mut shell = HittableList::new();
+    shell.add(Box::new(Sphere::new(
+        Point3::new(2.3, 1.2, -0.5),
+        1.2,
+        black.clone(),
+    )));
+    shell.add(Box::new(Sphere::new(
+        Point3::new(2.3, 1.2, -0.5),
+        0.8,
+        black,
+    )));
+    world.add(Box::new(ConstantMedium::new_from_color(
+        shell,
+        1.5,
+        Color::new(0.8, 0.4, 0.3),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 3.0, 10.0),
+        &Vec3::new(0.0, 1.1, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        35.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0))
+}
+
+fn sunny_sky(r: &Ray) -> Color {
+    let sun = Vec3::new(-1.0, 2.0, -0.5).normalize();
+    if r.direction.normalize().dot(sun) > 0.995 {
+        Color::new(100.0, 90.0, 75.0)
+    } else {
+        0.3 * blue_sky(r)
+    }
+}
+
+fn god_rays_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.45))),
+    )));
+
+    // Roof with holes, letting beams of sunlight through the hazy air
+    world.add(Box::new(XZRect::new(
+        -6.0,
+        6.0,
+        -6.0,
+        6.0,
+        4.0,
+        Arc::new(AlphaMask::new_thresholded(
+            Lambertian::from_color(Color::new(0.6, 0.4, 0.3)),
+            Checker::new(1.0, 0.0, 1.5),
+            0.5,
+        )),
+    )));
+
+    // Glass of murky water
+    world.add(Box::new(Sphere::new(
+        Point3::new(1.0, 1.0, 0.0),
+        1.0,
+        Arc::new(
+            MediumInterface::new(Dielectric::new(1.33)).with_interior(Arc::new(
+                Homogeneous::from_color(1.5, Color::new(0.6, 0.8, 0.9)),
+            )),
+        ),
+    )));
+
+    // Haze around the scene, which the camera is in
+    let haze: Arc<dyn Medium> = Arc::new(Homogeneous::new_with_phase(
+        0.03,
+        SolidColor::new(Color::new(0.9, 0.9, 0.9)),
+        HenyeyGreenstein::new(0.6),
+    ));
+    world.add(Box::new(MediumBoundary::new(
+        Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            20.0,
+            Arc::new(Lambertian::from_color(Color::new(0.0, 0.0, 0.0))),
+        ),
+        haze.clone(),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 2.0, 12.0),
+        &Vec3::new(0.0, 2.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    )
+    .with_medium(haze);
+
+    Scene::new(cam, world, sunny_sky)
+}
+
+fn punctual_lights_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(-2.5, 1.0, 0.0),
+        1.0,
+        Arc::new(Lambertian::from_color(Color::new(0.8, 0.3, 0.2))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(OrenNayar::from_color(Color::new(0.3, 0.6, 0.3), 0.5)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(2.5, 1.0, 0.0),
+        1.0,
+        Arc::new(Lambertian::from_color(Color::new(0.2, 0.4, 0.8))),
+    )));
+
+    // Light haze, making the cone of the spot light visible
+    let haze: Arc<dyn Medium> = Arc::new(Homogeneous::from_color(0.02, Color::new(0.9, 0.9, 0.9)));
+    world.add(Box::new(MediumBoundary::new(
+        Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            20.0,
+            Arc::new(Lambertian::from_color(Color::new(0.0, 0.0, 0.0))),
+        ),
+        haze.clone(),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 3.0, 10.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    )
+    .with_medium(haze);
+
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0))
+        // Warm bulb between the left spheres, only lighting its surroundings
+        .with_light(
+            PointLight::new(Point3::new(-1.25, 2.5, 1.0), Color::new(8.0, 6.0, 3.0))
+                .with_range(6.0),
+        )
+        // Stage light on the right sphere, with a soft edge
+        .with_light(SpotLight::new(
+            Point3::new(4.0, 6.0, 2.0),
+            Point3::new(2.5, 0.0, 0.0),
+            Color::new(40.0, 40.0, 45.0),
+            20.0,
+            0.3,
+        ))
+        // Dim moonlight with soft shadows
+        .with_light(DirectionalLight::new(
+            Vec3::new(-1.0, 2.0, 1.0),
+            Color::new(0.08, 0.1, 0.15),
+            2.0,
+        ))
+}
+
+/// `random_scene1` in the late afternoon, lit by the sun and the sky.
+fn sun_sky_scene() -> Scene {
+    let Scene { camera, world, .. } = random_scene1();
+
+    // Same albedo as the ground of the scene, so that it continues past the horizon
+    let sky = Sky::new(20.0, 240.0, 3.0).with_ground_albedo(Color::new(0.5, 0.5, 0.5));
+    let sun = sky.sun();
+
+    Scene::new(camera, world, move |r| sky.radiance(&r.direction)).with_light(sun)
+}
+
+/// `random_scene1` at night, where many of the small spheres are lamps of various colors.
+fn city_at_night_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    let mut rng = RNG::new();
+    let mut lamps = Vec::new();
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rng.random_double();
+            let center = Vec3::new(
+                (a as f64) + 0.9 * rng.random_double(),
+                0.2,
+                (b as f64) + 0.9 * rng.random_double(),
+            );
+
+            if (center - Vec3::new(4.0, 0.2, 0.0)).magnitude() <= 0.9 {
+                continue;
+            }
+
+            if choose_mat < 0.4 {
+                // lamp, from sodium orange to cold white
+                let temperature = rng.random_double_range(1800.0..7000.0);
+                lamps.push(SphereLight::new(
+                    center,
+                    0.2,
+                    Arc::new(DiffuseLight::from_temperature(temperature, 4.0)),
+                ));
+            } else if choose_mat < 0.85 {
+                let albedo = rng.random_vector().multiply_with(&rng.random_vector());
+                world.add(Box::new(Sphere::new(
+                    center,
+                    0.2,
+                    Arc::new(Lambertian::from_color(albedo)),
+                )));
+            } else {
+                let albedo = rng.random_vector_range(0.5..1.0);
+                let fuzz = rng.random_double_range(0.0..0.5);
+                world.add(Box::new(Sphere::new(
+                    center,
+                    0.2,
+                    Arc::new(Metal::new(albedo, fuzz)),
+                )));
+            }
+        }
+    }
+
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Lambertian::from_color(Color::new(0.4, 0.2, 0.1))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(4.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(13.0, 2.0, 3.0),
+        &Vec3::new(0.0, 0.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        20.0,
+        3.0 / 2.0,
+        0.1,
+        10.0,
+    );
+
+    lamps.into_iter().fold(
+        Scene::new(cam, world, |r| blue_sky(r) * 0.005),
+        |scene, lamp| scene.with_area_light(lamp),
+    )
+}
+
+/// The spheres of `simple_scene1` lit by a small lamp, casting caustics through the glass that
+/// are best rendered with `--integrator bdpt` or `--integrator sppm`.
+fn caustics_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, -100.5, -1.0),
+        100.0,
+        Arc::new(Lambertian::from_color(Color::new(0.8, 0.8, 0.7))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 0.0, -1.0),
+        0.5,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(-1.0, 0.0, -1.0),
+        0.5,
+        Arc::new(Dielectric::new_absorbing(
+            1.5,
+            Color::new(0.2, 0.5, 0.9),
+            1.0,
+        )),
+    )));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(1.0, 0.0, -1.0),
+        0.5,
+        Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.0)),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(0.0, 1.2, 1.2),
+        &Vec3::new(0.0, -0.2, -0.7),
+        &Vec3::new(0.0, 1.0, 0.0),
+        50.0,
+        3.0 / 2.0,
+        0.02,
+        2.4,
+    );
+
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0)).with_area_light(SphereLight::new(
+        Point3::new(0.3, 1.2, -2.6),
+        0.1,
+        Arc::new(DiffuseLight::from_color(Color::new(150.0, 150.0, 150.0))),
+    ))
+}
+
+/// Room only lit through a small window, by a bright wall outside which isn't sampled as a light.
+/// Paths rarely find the window, which is best rendered with `--guiding`.
+fn window_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let white = Arc::new(Lambertian::from_color(Color::new(0.75, 0.75, 0.75)));
+    let floor = Arc::new(Lambertian::from_color(Color::new(0.6, 0.45, 0.3)));
+
+    world.add(Box::new(XZRect::new(0.0, 10.0, 0.0, 10.0, 0.0, floor)));
+    world.add(Box::new(XZRect::new(
+        0.0,
+        10.0,
+        0.0,
+        10.0,
+        6.0,
+        white.clone(),
+    )));
+    world.add(Box::new(XYRect::new(
+        0.0,
+        10.0,
+        0.0,
+        6.0,
+        0.0,
+        white.clone(),
+    )));
+    world.add(Box::new(XYRect::new(
+        0.0,
+        10.0,
+        0.0,
+        6.0,
+        10.0,
+        white.clone(),
+    )));
+    world.add(Box::new(YZRect::new(
+        0.0,
+        6.0,
+        0.0,
+        10.0,
+        10.0,
+        white.clone(),
+    )));
+
+    // Wall around the window, which spans y in [2.5, 4] and z in [3, 6]
+    world.add(Box::new(YZRect::new(
+        0.0,
+        2.5,
+        0.0,
+        10.0,
+        0.0,
+        white.clone(),
+    )));
+    world.add(Box::new(YZRect::new(
+        4.0,
+        6.0,
+        0.0,
+        10.0,
+        0.0,
+        white.clone(),
+    )));
+    world.add(Box::new(YZRect::new(
+        2.5,
+        4.0,
+        0.0,
+        3.0,
+        0.0,
+        white.clone(),
+    )));
+    world.add(Box::new(YZRect::new(
+        2.5,
+        4.0,
+        6.0,
+        10.0,
+        0.0,
+        white.clone(),
+    )));
+
+    world.add(Box::new(YZRect::new(
+        -10.0,
+        20.0,
+        -10.0,
+        20.0,
+        -2.0,
+        Arc::new(DiffuseLight::from_color(Color::new(8.0, 8.0, 7.0))),
+    )));
+
+    world.add(Box::new(Cuboid::new(
+        Point3::new(3.0, 0.0, 1.0),
+        Point3::new(6.0, 1.5, 3.0),
+        white,
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(6.5, 1.0, 5.5),
+        1.0,
+        Arc::new(Lambertian::from_color(Color::new(0.2, 0.4, 0.7))),
+    )));
+
+    let cam = Camera::new(
+        &Vec3::new(9.0, 3.0, 9.5),
+        &Vec3::new(2.0, 2.0, 2.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        65.0,
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+
+    Scene::new(cam, world, |_| Color::new(0.0, 0.0, 0.0))
 }
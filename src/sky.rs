@@ -0,0 +1,207 @@
+use std::f64::consts::PI;
+
+use cgmath::InnerSpace;
+
+use crate::base::*;
+use crate::lights::directional::DirectionalLight;
+use crate::spectrum::xyz_to_rgb;
+
+/// Radiance in the scene per kcd/m² of luminance given by the model, chosen so that a sunny day
+/// is well exposed.
+const LUMINANCE_SCALE: f64 = 0.03;
+
+/// Illuminance from the sun above the atmosphere, in klx.
+const SOLAR_ILLUMINANCE: f64 = 128.0;
+
+/// Angular diameter of the sun seen from the ground, in degrees.
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+
+/// Effective wavelengths (in μm) of the red, green and blue channels, for the extinction of
+/// sunlight in the atmosphere.
+const RGB_WAVELENGTHS: [f64; 3] = [0.65, 0.55, 0.45];
+
+/// Analytic daylight sky from Preetham et al., "A Practical Analytic Model for Daylight". The sky
+/// is set by the position of the sun and the turbidity of the air, from 2 for a very clear day to
+/// about 10 for a hazy one.
+///
+/// The sun itself isn't part of the sky: it is added to the scene as a light with `sun`, so that
+/// it is sampled with shadow rays, and its disc is seen by the paths escaping towards it. Below
+/// the horizon, the sky shows a diffuse ground lit by the sun and the sky, which should have the
+/// same albedo as the ground of the scene.
+pub struct Sky {
+    sun_direction: Vec3,
+    sun_irradiance: Color,
+    zenith: Vec3,
+    perez: [[f64; 5]; 3],
+    ground: Color,
+    ground_albedo: Color,
+}
+
+impl Sky {
+    /// `elevation` is the angle of the sun above the horizon, and `azimuth` its angle around the
+    /// vertical axis, from -z towards +x (both in degrees). The model only holds for the sun
+    /// above the horizon, so lower elevations are clamped.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Sky {
+        let elevation = elevation.clamp(1.0, 90.0).to_radians();
+        let azimuth = azimuth.to_radians();
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        let theta_sun = PI / 2.0 - elevation;
+        let t = turbidity;
+
+        // Distribution coefficients A to E for the luminance Y and the chromaticities x and y
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_chromaticity = |m: [[f64; 4]; 3]| {
+            let thetas = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+            let ts = [t * t, t, 1.0];
+            (0..3)
+                .map(|i| ts[i] * (0..4).map(|j| m[i][j] * thetas[j]).sum::<f64>())
+                .sum::<f64>()
+        };
+        let zenith_x = zenith_chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = zenith_chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        // Values at the zenith, divided by the distribution there so that it only has to be
+        // multiplied by the distribution in other directions
+        let zenith = Vec3::new(
+            zenith_luminance / perez_function(&perez[0], 1.0, theta_sun),
+            zenith_x / perez_function(&perez[1], 1.0, theta_sun),
+            zenith_y / perez_function(&perez[2], 1.0, theta_sun),
+        );
+
+        let sun_irradiance =
+            SOLAR_ILLUMINANCE * LUMINANCE_SCALE * sun_transmittance(theta_sun, turbidity);
+
+        Sky {
+            sun_direction,
+            sun_irradiance,
+            zenith,
+            perez,
+            ground: Color::new(0.0, 0.0, 0.0),
+            ground_albedo: Color::new(0.0, 0.0, 0.0),
+        }
+        .with_ground_albedo(Color::new(0.3, 0.3, 0.3))
+    }
+
+    /// Albedo of the ground shown below the horizon.
+    pub fn with_ground_albedo(self, albedo: Color) -> Sky {
+        // Irradiance on the ground from the sky, integrated with the midpoint rule
+        let n = 64;
+        let mut irradiance = Color::new(0.0, 0.0, 0.0);
+        for i in 0..n {
+            let cos_theta = (i as f64 + 0.5) / n as f64;
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            for j in 0..(4 * n) {
+                let phi = 2.0 * PI * (j as f64 + 0.5) / (4 * n) as f64;
+                let direction = Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+                irradiance += self.sky_radiance(&direction) * cos_theta;
+            }
+        }
+        irradiance *= 2.0 * PI / (n * 4 * n) as f64;
+        irradiance += self.sun_irradiance * self.sun_direction.y;
+
+        Sky {
+            ground: albedo.multiply_with(&irradiance) / PI,
+            ground_albedo: albedo,
+            ..self
+        }
+    }
+
+    pub fn ground_albedo(&self) -> Color {
+        self.ground_albedo
+    }
+
+    /// Light of the sun, matching the position and turbidity of the sky.
+    pub fn sun(&self) -> DirectionalLight {
+        DirectionalLight::new(
+            self.sun_direction,
+            self.sun_irradiance,
+            SUN_ANGULAR_DIAMETER,
+        )
+    }
+
+    /// Radiance arriving from `direction`, from the sky or the ground.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let direction = direction.normalize();
+        if direction.y < 0.0 {
+            self.ground
+        } else {
+            self.sky_radiance(&direction)
+        }
+    }
+
+    fn sky_radiance(&self, direction: &Vec3) -> Color {
+        // The distribution goes to infinity at the horizon
+        let cos_theta = f64::max(direction.y, 0.01);
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let luminance = self.zenith.x * perez_function(&self.perez[0], cos_theta, gamma);
+        let x = self.zenith.y * perez_function(&self.perez[1], cos_theta, gamma);
+        let y = self.zenith.z * perez_function(&self.perez[2], cos_theta, gamma);
+
+        let xyz = Vec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = xyz_to_rgb(&xyz) * LUMINANCE_SCALE;
+
+        Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+}
+
+/// Perez distribution for a direction at an angle of cosine `cos_theta` from the zenith, and at an
+/// angle `gamma` from the sun.
+fn perez_function(c: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+}
+
+/// Fraction of sunlight going through the atmosphere for the sun at an angle `theta_sun` from the
+/// zenith, from Rayleigh scattering and aerosols.
+fn sun_transmittance(theta_sun: f64, turbidity: f64) -> Color {
+    // Relative optical mass of the air, accounting for the curvature of the earth
+    let air_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_sun.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let alpha = 1.3;
+
+    let [r, g, b] = RGB_WAVELENGTHS.map(|lambda| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+        let aerosols = (-beta * lambda.powf(-alpha) * air_mass).exp();
+        rayleigh * aerosols
+    });
+
+    Color::new(r, g, b)
+}
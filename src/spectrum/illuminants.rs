@@ -0,0 +1,217 @@
+use crate::base::*;
+use crate::spectrum::{cie_xyz, cie_y_integral, integrate, white_balanced_rgb, SampledWavelengths};
+use crate::spectrum::{SampledSpectrum, LAMBDA_MAX, LAMBDA_MIN};
+
+/// CIE standard illuminants.
+#[derive(Copy, Clone)]
+pub enum Illuminant {
+    /// Average noon daylight.
+    D65,
+    /// Incandescent tungsten lamp.
+    A,
+    /// Cool white fluorescent lamp.
+    F2,
+    /// Narrow band (triphosphor) fluorescent lamp.
+    F11,
+}
+
+#[derive(Copy, Clone)]
+enum Shape {
+    Blackbody {
+        temperature: f64,
+    },
+    Tabulated {
+        first: f64,
+        step: f64,
+        values: &'static [f64],
+    },
+}
+
+/// Emission spectrum of a light, scaled to a given luminance.
+#[derive(Copy, Clone)]
+pub struct Spectrum {
+    shape: Shape,
+    scale: f64,
+}
+
+impl Spectrum {
+    /// Emission of a black body at the given temperature (in Kelvin), with a luminance of 1.
+    pub fn blackbody(temperature: f64) -> Spectrum {
+        Spectrum::normalized(Shape::Blackbody { temperature })
+    }
+
+    /// Standard illuminant, with a luminance of 1.
+    pub fn illuminant(illuminant: Illuminant) -> Spectrum {
+        match illuminant {
+            // Illuminant A is defined as a black body at 2856 K
+            Illuminant::A => Spectrum::blackbody(2856.0),
+            Illuminant::D65 => Spectrum::normalized(Shape::Tabulated {
+                first: 380.0,
+                step: 10.0,
+                values: &CIE_D65,
+            }),
+            Illuminant::F2 => Spectrum::normalized(Shape::Tabulated {
+                first: 380.0,
+                step: 5.0,
+                values: &CIE_F2,
+            }),
+            Illuminant::F11 => Spectrum::normalized(Shape::Tabulated {
+                first: 380.0,
+                step: 5.0,
+                values: &CIE_F11,
+            }),
+        }
+    }
+
+    fn normalized(shape: Shape) -> Spectrum {
+        let unscaled = Spectrum { shape, scale: 1.0 };
+        let y = unscaled.to_xyz().y;
+
+        Spectrum {
+            shape,
+            scale: 1.0 / y,
+        }
+    }
+
+    pub fn scaled(&self, scale: f64) -> Spectrum {
+        Spectrum {
+            shape: self.shape,
+            scale: self.scale * scale,
+        }
+    }
+
+    pub fn value(&self, lambda: f64) -> f64 {
+        let value = match self.shape {
+            Shape::Blackbody { temperature } => blackbody(lambda, temperature),
+            Shape::Tabulated {
+                first,
+                step,
+                values,
+            } => {
+                let x = (lambda - first) / step;
+                if x < 0.0 || x > (values.len() - 1) as f64 {
+                    0.0
+                } else {
+                    let i = usize::min(x as usize, values.len() - 2);
+                    let t = x - i as f64;
+                    (1.0 - t) * values[i] + t * values[i + 1]
+                }
+            }
+        };
+
+        self.scale * value
+    }
+
+    pub fn sample(&self, lambdas: &SampledWavelengths) -> SampledSpectrum {
+        lambdas.sample(|lambda| self.value(lambda))
+    }
+
+    pub fn to_xyz(self) -> Vec3 {
+        integrate(|lambda| cie_xyz(lambda) * self.value(lambda)) / cie_y_integral()
+    }
+
+    /// Linear sRGB color of the light, used when rendering in RGB.
+    pub fn to_rgb(self) -> Color {
+        white_balanced_rgb(&self.to_xyz())
+    }
+}
+
+/// Spectral radiance of a black body (Planck's law), with `lambda` in nm.
+fn blackbody(lambda: f64, temperature: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) || temperature <= 0.0 {
+        return 0.0;
+    }
+
+    let c = 299792458.0;
+    let h = 6.62606957e-34;
+    let kb = 1.3806488e-23;
+    let l = lambda * 1e-9;
+
+    (2.0 * h * c * c) / (l.powi(5) * ((h * c / (l * kb * temperature)).exp() - 1.0))
+}
+
+// Relative spectral power distributions from CIE 15:2004, starting at 380 nm.
+
+#[rustfmt::skip]
+const CIE_D65: [f64; 41] = [
+    49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008, 117.812, 114.861,
+    115.923, 108.811, 109.354, 107.802, 104.790, 107.689, 104.405, 104.046, 100.000, 96.3342,
+    95.7880, 88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146, 82.2778,
+    78.2842, 69.7213, 71.6091, 74.3490, 61.6040, 69.8856, 75.0870, 63.5927, 46.4182, 66.8054,
+    63.3828,
+];
+
+#[rustfmt::skip]
+const CIE_F2: [f64; 81] = [
+    1.18, 1.48, 1.84, 2.15, 3.44, 15.69, 3.85, 3.74, 4.19, 4.62,
+    5.06, 34.98, 11.81, 6.27, 6.63, 6.93, 7.19, 7.40, 7.54, 7.62,
+    7.65, 7.62, 7.62, 7.45, 7.28, 7.15, 7.05, 7.04, 7.16, 7.47,
+    8.04, 8.88, 10.01, 24.88, 16.64, 14.59, 16.16, 17.56, 18.62, 21.47,
+    22.79, 19.29, 18.66, 17.73, 16.54, 15.21, 13.80, 12.36, 10.95, 9.65,
+    8.40, 7.32, 6.31, 5.43, 4.68, 4.02, 3.45, 2.96, 2.55, 2.19,
+    1.89, 1.64, 1.53, 1.27, 1.10, 0.99, 0.88, 0.76, 0.68, 0.61,
+    0.56, 0.54, 0.51, 0.47, 0.47, 0.43, 0.46, 0.47, 0.40, 0.33,
+    0.27,
+];
+
+#[rustfmt::skip]
+const CIE_F11: [f64; 81] = [
+    0.91, 0.63, 0.46, 0.37, 1.29, 12.68, 1.59, 1.79, 2.46, 3.33,
+    4.49, 33.94, 12.13, 6.95, 7.19, 7.12, 6.72, 6.13, 5.46, 4.79,
+    5.66, 14.29, 14.96, 8.97, 4.72, 2.33, 1.47, 1.10, 0.89, 0.83,
+    1.18, 4.90, 39.59, 72.84, 32.61, 7.52, 2.83, 1.96, 1.67, 4.43,
+    11.28, 14.76, 12.73, 9.74, 7.33, 9.72, 55.27, 42.58, 13.18, 13.16,
+    12.26, 5.11, 2.07, 2.34, 3.58, 3.01, 2.48, 2.14, 1.54, 1.33,
+    1.46, 1.94, 2.00, 1.20, 1.35, 4.10, 5.58, 2.51, 0.57, 0.27,
+    0.23, 0.21, 0.24, 0.24, 0.20, 0.24, 0.32, 0.26, 0.16, 0.12,
+    0.09,
+];
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+
+    #[test]
+    fn d65_is_white() {
+        let rgb = Spectrum::illuminant(Illuminant::D65).to_rgb();
+
+        assert!((rgb - Color::new(1.0, 1.0, 1.0)).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn blackbody_at_d65_temperature_is_close_to_white() {
+        let rgb = Spectrum::blackbody(6504.0).to_rgb();
+
+        assert!(
+            (rgb - Color::new(1.0, 1.0, 1.0)).magnitude() < 0.1,
+            "{:?}",
+            rgb
+        );
+    }
+
+    #[test]
+    fn blackbodies_get_warmer_as_they_cool_down() {
+        let warm = Spectrum::blackbody(2700.0).to_rgb();
+        let cold = Spectrum::blackbody(10000.0).to_rgb();
+
+        assert!(warm.x > warm.y && warm.y > warm.z);
+        assert!(cold.z > cold.y && cold.y > cold.x);
+    }
+
+    #[test]
+    fn illuminants_have_unit_luminance() {
+        for illuminant in [
+            Illuminant::D65,
+            Illuminant::A,
+            Illuminant::F2,
+            Illuminant::F11,
+        ] {
+            let y = Spectrum::illuminant(illuminant).to_xyz().y;
+            assert!((y - 1.0).abs() < 1e-9);
+        }
+        let y = Spectrum::blackbody(1800.0).scaled(3.0).to_xyz().y;
+        assert!((y - 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,498 @@
+use std::ops::{Add, Mul};
+use std::sync::OnceLock;
+
+use crate::base::*;
+use crate::hit::hit_record::HitRecord;
+use crate::lights::{EmissionSample, Light, LightSample};
+use crate::random::RNG;
+use crate::ray::Ray;
+use crate::spectrum::illuminants::{Illuminant, Spectrum};
+use crate::spectrum::upsampling::rgb_to_spectrum_table;
+
+pub mod illuminants;
+mod upsampling;
+
+/// Range of wavelengths (in nm) sampled when a path needs to pick a single wavelength.
+pub const VISIBLE_MIN: f64 = 380.0;
+pub const VISIBLE_MAX: f64 = 780.0;
+
+/// Range of wavelengths (in nm) over which spectra are defined.
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+/// Number of wavelengths carried by each path in spectral mode.
+pub const N_WAVELENGTHS: usize = 4;
+
+/*
+CIE 1931 standard observer matching functions, using the multi-lobe gaussian fit from Wyman, Sloan
+and Shirley, "Simple Analytic Approximations to the CIE XYZ Color Matching Functions" (2013).
+ */
+fn piecewise_gaussian(lambda: f64, mu: f64, sigma1: f64, sigma2: f64) -> f64 {
+    let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+    (-0.5 * t * t).exp()
+}
+
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let x = 1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8);
+
+    Vec3::new(x, y, z)
+}
+
+/// Integral of the `y` matching function, which maps a spectrum to a luminance of 1 when its
+/// integral against `y` is equal to it.
+pub fn cie_y_integral() -> f64 {
+    static INTEGRAL: OnceLock<f64> = OnceLock::new();
+
+    *INTEGRAL.get_or_init(|| integrate(|lambda| cie_xyz(lambda).y))
+}
+
+/// Integrates a function of the wavelength over `[LAMBDA_MIN, LAMBDA_MAX]`, with 1 nm steps.
+pub fn integrate<R: Add<Output = R> + Mul<f64, Output = R>>(f: impl Fn(f64) -> R) -> R {
+    let steps = (LAMBDA_MAX - LAMBDA_MIN) as u32;
+
+    (1..steps)
+        .map(|i| f(LAMBDA_MIN + i as f64))
+        .fold(f(LAMBDA_MIN) * 0.5 + f(LAMBDA_MAX) * 0.5, |a, b| a + b)
+}
+
+/// Converts CIE XYZ to linear sRGB (D65 white point).
+pub fn xyz_to_rgb(xyz: &Vec3) -> Color {
+    Color::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+/// Converts linear sRGB to CIE XYZ (D65 white point).
+pub fn rgb_to_xyz(rgb: &Color) -> Vec3 {
+    Vec3::new(
+        0.4124564 * rgb.x + 0.3575761 * rgb.y + 0.1804375 * rgb.z,
+        0.2126729 * rgb.x + 0.7151522 * rgb.y + 0.0721750 * rgb.z,
+        0.0193339 * rgb.x + 0.1191920 * rgb.y + 0.9503041 * rgb.z,
+    )
+}
+
+/// Linear sRGB response to a single wavelength, with the out of gamut (negative) part removed.
+fn wavelength_rgb(lambda: f64) -> Color {
+    let rgb = xyz_to_rgb(&cie_xyz(lambda));
+    Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+}
+
+/// Integral of `wavelength_rgb` over the visible range, used to normalize the path weights.
+fn wavelength_rgb_integral() -> Color {
+    static INTEGRAL: OnceLock<Color> = OnceLock::new();
+
+    *INTEGRAL.get_or_init(|| {
+        let steps = 4 * (VISIBLE_MAX - VISIBLE_MIN) as u32;
+        let step = (VISIBLE_MAX - VISIBLE_MIN) / steps as f64;
+
+        (0..steps)
+            .map(|i| wavelength_rgb(VISIBLE_MIN + (i as f64 + 0.5) * step) * step)
+            .sum()
+    })
+}
+
+/// Picks a single wavelength for a path, uniformly over the visible range. The returned RGB
+/// weight converts the radiance carried at that wavelength back to a color; it averages to white
+/// over many samples.
+pub fn sample_wavelength(rng: &mut RNG) -> (f64, Color) {
+    let lambda = rng.random_double_range(VISIBLE_MIN..VISIBLE_MAX);
+    let pdf = 1.0 / (VISIBLE_MAX - VISIBLE_MIN);
+
+    let rgb = wavelength_rgb(lambda);
+    let integral = wavelength_rgb_integral();
+    let weight = Color::new(
+        rgb.x / (integral.x * pdf),
+        rgb.y / (integral.y * pdf),
+        rgb.z / (integral.z * pdf),
+    );
+
+    (lambda, weight)
+}
+
+/// RGB color of a reflectance varying with the wavelength, averaged over the response of each
+/// channel. This is the expected weight of a path picking its wavelength with
+/// `sample_wavelength`.
+pub fn reflectance_to_rgb(reflectance: impl Fn(f64) -> f64) -> Color {
+    let steps = 40;
+    let step = (VISIBLE_MAX - VISIBLE_MIN) / steps as f64;
+    let integral = wavelength_rgb_integral();
+
+    let rgb: Color = (0..steps)
+        .map(|i| {
+            let lambda = VISIBLE_MIN + (i as f64 + 0.5) * step;
+            wavelength_rgb(lambda) * (reflectance(lambda) * step)
+        })
+        .sum();
+
+    Color::new(rgb.x / integral.x, rgb.y / integral.y, rgb.z / integral.z)
+}
+
+// Radiance
+
+/// Quantity carried along a path: an RGB triplet, or radiance at a few sampled wavelengths.
+pub trait Radiance: Copy + Send + Add<Output = Self> + Mul<f64, Output = Self> {
+    fn black() -> Self;
+    /// Value of 1 everywhere, e.g. the throughput at the start of a path.
+    fn white() -> Self;
+    fn modulate(&self, other: &Self) -> Self;
+    /// Largest value, e.g. to decide when to terminate a path.
+    fn max_value(&self) -> f64;
+}
+
+impl Radiance for Color {
+    fn black() -> Self {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn white() -> Self {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn modulate(&self, other: &Self) -> Self {
+        self.multiply_with(other)
+    }
+
+    fn max_value(&self) -> f64 {
+        self.x.max(self.y).max(self.z)
+    }
+}
+
+/// Turns the RGB colors returned by materials and backgrounds into the `Radiance` carried along
+/// a path.
+pub trait Wavelengths: Sync {
+    type Radiance: Radiance;
+
+    /// Converts a reflectance or transmittance.
+    fn albedo(&self, color: &Color) -> Self::Radiance;
+    /// Converts a BSDF value `f` (see `Material::eval`) for a direction the material samples
+    /// with density `pdf`, with the same spectrum as the weight returned by `scatter`.
+    fn scattered(&self, f: &Color, pdf: f64) -> Self::Radiance;
+    /// Converts the weight `color` of a path that `rec.material` scattered along `scattered`.
+    fn attenuation(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        scattered: &Ray,
+        color: &Color,
+    ) -> Self::Radiance;
+    /// Converts an emitted color.
+    fn illuminant(&self, color: &Color) -> Self::Radiance;
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Self::Radiance;
+    /// Converts the light of a `sample` of `light` taken from `p`.
+    fn light_sample(&self, light: &dyn Light, p: &Point3, sample: &LightSample) -> Self::Radiance;
+    /// Converts the light leaving `light` along an `emission` sample.
+    fn light_emission(&self, light: &dyn Light, emission: &EmissionSample) -> Self::Radiance;
+    /// Restricts the throughput of a path to its hero wavelength, when it goes through a
+    /// dispersive material. Paths which are already restricted are left unchanged.
+    fn single_wavelength(&self, throughput: Self::Radiance) -> Self::Radiance;
+    /// Converts the radiance carried by a path to linear sRGB.
+    fn to_rgb(&self, radiance: &Self::Radiance) -> Color;
+}
+
+/// RGB rendering, where colors are carried as they are.
+pub struct Rgb;
+
+impl Wavelengths for Rgb {
+    type Radiance = Color;
+
+    fn albedo(&self, color: &Color) -> Color {
+        *color
+    }
+
+    fn scattered(&self, f: &Color, _pdf: f64) -> Color {
+        *f
+    }
+
+    fn attenuation(&self, _ray: &Ray, _rec: &HitRecord, _scattered: &Ray, color: &Color) -> Color {
+        *color
+    }
+
+    fn illuminant(&self, color: &Color) -> Color {
+        *color
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        rec.material.emitted(ray, rec)
+    }
+
+    fn light_sample(&self, _light: &dyn Light, _p: &Point3, sample: &LightSample) -> Color {
+        sample.radiance
+    }
+
+    fn light_emission(&self, _light: &dyn Light, emission: &EmissionSample) -> Color {
+        emission.radiance
+    }
+
+    fn single_wavelength(&self, throughput: Color) -> Color {
+        // Dispersive materials account for the wavelength in their RGB weight
+        throughput
+    }
+
+    fn to_rgb(&self, radiance: &Color) -> Color {
+        *radiance
+    }
+}
+
+// Sampled spectra
+
+#[derive(Copy, Clone)]
+pub struct SampledSpectrum {
+    values: [f64; N_WAVELENGTHS],
+}
+
+impl SampledSpectrum {
+    pub fn new(values: [f64; N_WAVELENGTHS]) -> SampledSpectrum {
+        SampledSpectrum { values }
+    }
+
+    pub fn from_fn(f: impl Fn(usize) -> f64) -> SampledSpectrum {
+        let mut values = [0.0; N_WAVELENGTHS];
+        for (i, v) in values.iter_mut().enumerate() {
+            *v = f(i);
+        }
+        SampledSpectrum { values }
+    }
+
+    pub fn get(&self, i: usize) -> f64 {
+        self.values[i]
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add(self, rhs: SampledSpectrum) -> SampledSpectrum {
+        SampledSpectrum::from_fn(|i| self.values[i] + rhs.values[i])
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, rhs: f64) -> SampledSpectrum {
+        SampledSpectrum::from_fn(|i| self.values[i] * rhs)
+    }
+}
+
+impl Radiance for SampledSpectrum {
+    fn black() -> Self {
+        SampledSpectrum::new([0.0; N_WAVELENGTHS])
+    }
+
+    fn white() -> Self {
+        SampledSpectrum::new([1.0; N_WAVELENGTHS])
+    }
+
+    fn modulate(&self, other: &Self) -> Self {
+        SampledSpectrum::from_fn(|i| self.values[i] * other.values[i])
+    }
+
+    fn max_value(&self) -> f64 {
+        self.values.iter().copied().fold(0.0, f64::max)
+    }
+}
+
+/*
+Wavelengths are importance sampled following the visual response, using the distribution from
+Radziszewski et al. (2009).
+ */
+fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    0.0039398042 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+/// Set of wavelengths carried by a path in spectral mode. The first one is the hero wavelength,
+/// the others are evenly spread out from it so the whole range is covered by every path.
+#[derive(Copy, Clone)]
+pub struct SampledWavelengths {
+    lambda: [f64; N_WAVELENGTHS],
+    pdf: [f64; N_WAVELENGTHS],
+}
+
+impl SampledWavelengths {
+    pub fn sample_visible(u: f64) -> SampledWavelengths {
+        let mut lambda = [0.0; N_WAVELENGTHS];
+        let mut pdf = [0.0; N_WAVELENGTHS];
+
+        for i in 0..N_WAVELENGTHS {
+            let up = (u + i as f64 / N_WAVELENGTHS as f64).fract();
+            lambda[i] = sample_visible_wavelength(up);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+
+        SampledWavelengths { lambda, pdf }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn get(&self, i: usize) -> f64 {
+        self.lambda[i]
+    }
+
+    /// Samples a spectrum given as a function of the wavelength.
+    pub fn sample(&self, f: impl Fn(f64) -> f64) -> SampledSpectrum {
+        SampledSpectrum::from_fn(|i| f(self.lambda[i]))
+    }
+}
+
+/// Builds the tables used in spectral mode ahead of rendering.
+pub fn prepare() {
+    rgb_to_spectrum_table();
+    white_balanced_rgb(&Vec3::new(0.0, 0.0, 0.0));
+}
+
+/// Illuminant of the sRGB color space, normalized to a luminance of 1.
+pub fn d65() -> &'static Spectrum {
+    static D65: OnceLock<Spectrum> = OnceLock::new();
+
+    D65.get_or_init(|| Spectrum::illuminant(Illuminant::D65))
+}
+
+/// Converts CIE XYZ to linear sRGB, corrected so that the D65 illuminant maps exactly to white.
+/// The matching function fit and the tabulated illuminant only reproduce it approximately, and
+/// this keeps spectral renders consistent with RGB ones.
+pub fn white_balanced_rgb(xyz: &Vec3) -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+
+    let white = WHITE.get_or_init(|| xyz_to_rgb(&d65().to_xyz()));
+    let rgb = xyz_to_rgb(xyz);
+
+    Color::new(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+}
+
+impl Wavelengths for SampledWavelengths {
+    type Radiance = SampledSpectrum;
+
+    fn albedo(&self, color: &Color) -> SampledSpectrum {
+        let m = color.x.max(color.y).max(color.z);
+        if m <= 0.0 {
+            return SampledSpectrum::black();
+        }
+
+        if m <= 1.0 {
+            let polynomial = rgb_to_spectrum_table().lookup(color);
+            self.sample(|lambda| polynomial.eval(lambda))
+        } else {
+            // Unbounded values (e.g. sampling weights) are fit after scaling them down
+            let scale = 2.0 * m;
+            let polynomial = rgb_to_spectrum_table().lookup(&(color / scale));
+            self.sample(|lambda| scale * polynomial.eval(lambda))
+        }
+    }
+
+    fn scattered(&self, f: &Color, pdf: f64) -> SampledSpectrum {
+        if pdf <= 0.0 {
+            return self.albedo(f);
+        }
+
+        // Upsampling `f` itself gives the broader spectrum of a darker color, which differs from
+        // the one of paths hitting lights by scattering once lights have narrow peaks
+        self.albedo(&(f / pdf)) * pdf
+    }
+
+    fn attenuation(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        scattered: &Ray,
+        color: &Color,
+    ) -> SampledSpectrum {
+        rec.material
+            .attenuation_spectrum(ray, rec, scattered, color, self)
+    }
+
+    fn illuminant(&self, color: &Color) -> SampledSpectrum {
+        let m = color.x.max(color.y).max(color.z);
+        if m <= 0.0 {
+            return SampledSpectrum::black();
+        }
+
+        // The reflectance-like shape is lit by the color space illuminant, D65 for sRGB
+        let scale = 2.0 * m;
+        let polynomial = rgb_to_spectrum_table().lookup(&(color / scale));
+        self.sample(|lambda| scale * polynomial.eval(lambda) * d65().value(lambda))
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitRecord) -> SampledSpectrum {
+        rec.material.emitted_spectrum(ray, rec, self)
+    }
+
+    fn light_sample(&self, light: &dyn Light, p: &Point3, sample: &LightSample) -> SampledSpectrum {
+        light.sample_spectrum(p, sample, self)
+    }
+
+    fn light_emission(&self, light: &dyn Light, emission: &EmissionSample) -> SampledSpectrum {
+        light.emission_spectrum(emission, self)
+    }
+
+    fn single_wavelength(&self, throughput: SampledSpectrum) -> SampledSpectrum {
+        // The hero wavelength stands for the others once, when they are dropped
+        if (1..N_WAVELENGTHS).all(|i| throughput.get(i) == 0.0) {
+            return throughput;
+        }
+
+        SampledSpectrum::from_fn(|i| {
+            if i == 0 {
+                throughput.get(0) * N_WAVELENGTHS as f64
+            } else {
+                0.0
+            }
+        })
+    }
+
+    /// Converts through CIE XYZ.
+    fn to_rgb(&self, radiance: &SampledSpectrum) -> Color {
+        let xyz = (0..N_WAVELENGTHS)
+            .filter(|&i| self.pdf[i] > 0.0)
+            .map(|i| cie_xyz(self.lambda[i]) * (radiance.get(i) / self.pdf[i]))
+            .sum::<Vec3>()
+            / (N_WAVELENGTHS as f64 * cie_y_integral());
+
+        white_balanced_rgb(&xyz)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_restricted_to_the_hero_wavelength_once() {
+        let lambdas = SampledWavelengths::sample_visible(0.3);
+        let glass = SampledSpectrum::from_fn(|i| 0.9 - 0.1 * i as f64);
+
+        let once = lambdas
+            .single_wavelength(SampledSpectrum::white())
+            .modulate(&glass);
+        let twice = lambdas.single_wavelength(once).modulate(&glass);
+        let expected = once.modulate(&glass);
+
+        assert_eq!(lambdas.to_rgb(&twice), lambdas.to_rgb(&expected));
+        assert_eq!(
+            lambdas.to_rgb(&once),
+            lambdas.to_rgb(&SampledSpectrum::from_fn(|i| {
+                if i == 0 {
+                    0.9 * N_WAVELENGTHS as f64
+                } else {
+                    0.0
+                }
+            }))
+        );
+    }
+}
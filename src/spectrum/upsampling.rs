@@ -0,0 +1,403 @@
+use std::sync::OnceLock;
+
+use cgmath::InnerSpace;
+use rayon::prelude::*;
+
+use crate::base::*;
+use crate::spectrum::{cie_xyz, d65, rgb_to_xyz, xyz_to_rgb, LAMBDA_MAX, LAMBDA_MIN};
+
+/*
+RGB to spectrum conversion from Jakob and Hanika, "A Low-Dimensional Function Space for Efficient
+Spectral Upsampling" (2019). A reflectance spectrum is represented as a sigmoid of a quadratic
+polynomial of the wavelength, whose coefficients are fit so that the spectrum, lit by the color
+space illuminant, reproduces the requested RGB. Fits are precomputed on a grid over the RGB cube
+and interpolated.
+ */
+
+/// Resolution of the coefficient grid along each dimension.
+const TABLE_RES: usize = 32;
+
+/// Wavelength step used when fitting the coefficients.
+const FIT_STEP: f64 = 5.0;
+
+fn sigmoid(x: f64) -> f64 {
+    if x.is_infinite() {
+        return if x > 0.0 { 1.0 } else { 0.0 };
+    }
+    0.5 + x / (2.0 * (1.0 + x * x).sqrt())
+}
+
+/// Spectrum defined as `sigmoid(c0 x² + c1 x + c2)`, where `x` is the wavelength remapped from
+/// `[LAMBDA_MIN, LAMBDA_MAX]` to `[0, 1]`.
+#[derive(Copy, Clone)]
+pub struct SigmoidPolynomial {
+    c: [f64; 3],
+}
+
+impl SigmoidPolynomial {
+    pub fn eval(&self, lambda: f64) -> f64 {
+        let x = (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN);
+        sigmoid((self.c[0] * x + self.c[1]) * x + self.c[2])
+    }
+}
+
+pub struct RgbToSpectrumTable {
+    z_nodes: Vec<f64>,
+    // Indexed by [max component][z][y][x]
+    coefficients: Vec<[f64; 3]>,
+}
+
+pub fn rgb_to_spectrum_table() -> &'static RgbToSpectrumTable {
+    static TABLE: OnceLock<RgbToSpectrumTable> = OnceLock::new();
+
+    TABLE.get_or_init(RgbToSpectrumTable::build)
+}
+
+impl RgbToSpectrumTable {
+    /// Finds the spectrum matching a color with components in `[0, 1]`.
+    pub fn lookup(&self, rgb: &Color) -> SigmoidPolynomial {
+        let rgb = Color::new(
+            rgb.x.clamp(0.0, 1.0),
+            rgb.y.clamp(0.0, 1.0),
+            rgb.z.clamp(0.0, 1.0),
+        );
+
+        // Grays are matched exactly by a constant spectrum
+        if rgb.x == rgb.y && rgb.y == rgb.z {
+            return SigmoidPolynomial {
+                c: [0.0, 0.0, (rgb.x - 0.5) / (rgb.x * (1.0 - rgb.x)).sqrt()],
+            };
+        }
+
+        // The grid is parametrized by the largest component, and the two others relative to it
+        let max_c = if rgb.x >= rgb.y && rgb.x >= rgb.z {
+            0
+        } else if rgb.y >= rgb.z {
+            1
+        } else {
+            2
+        };
+        let z = rgb[max_c];
+        let res = (TABLE_RES - 1) as f64;
+        let x = rgb[(max_c + 1) % 3] * res / z;
+        let y = rgb[(max_c + 2) % 3] * res / z;
+
+        let xi = usize::min(x as usize, TABLE_RES - 2);
+        let yi = usize::min(y as usize, TABLE_RES - 2);
+        let zi = self
+            .z_nodes
+            .partition_point(|&node| node <= z)
+            .clamp(1, TABLE_RES - 1)
+            - 1;
+        let dx = x - xi as f64;
+        let dy = y - yi as f64;
+        let dz = (z - self.z_nodes[zi]) / (self.z_nodes[zi + 1] - self.z_nodes[zi]);
+
+        let co = |xo: usize, yo: usize, zo: usize| {
+            self.coefficients[index(max_c, zi + zo, yi + yo, xi + xo)]
+        };
+        let lerp = |t: f64, a: [f64; 3], b: [f64; 3]| {
+            [
+                (1.0 - t) * a[0] + t * b[0],
+                (1.0 - t) * a[1] + t * b[1],
+                (1.0 - t) * a[2] + t * b[2],
+            ]
+        };
+
+        let c = lerp(
+            dz,
+            lerp(
+                dy,
+                lerp(dx, co(0, 0, 0), co(1, 0, 0)),
+                lerp(dx, co(0, 1, 0), co(1, 1, 0)),
+            ),
+            lerp(
+                dy,
+                lerp(dx, co(0, 0, 1), co(1, 0, 1)),
+                lerp(dx, co(0, 1, 1), co(1, 1, 1)),
+            ),
+        );
+
+        SigmoidPolynomial { c }
+    }
+
+    fn build() -> RgbToSpectrumTable {
+        let fit = FitTables::new();
+
+        let smoothstep = |x: f64| x * x * (3.0 - 2.0 * x);
+        let z_nodes: Vec<f64> = (0..TABLE_RES)
+            .map(|k| smoothstep(smoothstep(k as f64 / (TABLE_RES - 1) as f64)))
+            .collect();
+
+        // Each (max component, y, x) line is fit independently, walking along z from the middle so
+        // each fit starts from the previous solution
+        let lines: Vec<(usize, usize, Vec<[f64; 3]>)> = (0..3)
+            .flat_map(|l| (0..TABLE_RES).map(move |j| (l, j)))
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .flat_map_iter(|(l, j)| {
+                let fit = &fit;
+                let z_nodes = &z_nodes;
+                (0..TABLE_RES).map(move |i| {
+                    let y = j as f64 / (TABLE_RES - 1) as f64;
+                    let x = i as f64 / (TABLE_RES - 1) as f64;
+                    let mut line = vec![[0.0; 3]; TABLE_RES];
+
+                    let start = TABLE_RES / 5;
+                    let mut solve = |k: usize, coefficients: &mut [f64; 3]| {
+                        let b = z_nodes[k];
+                        let mut rgb = Color::new(0.0, 0.0, 0.0);
+                        rgb[l] = b;
+                        rgb[(l + 1) % 3] = x * b;
+                        rgb[(l + 2) % 3] = y * b;
+                        // Starting from the neighbouring solution can get stuck when the
+                        // coefficients saturate, in which case a fresh start usually converges
+                        let error = fit.gauss_newton(&rgb, coefficients);
+                        if error > 1e-6 {
+                            let mut fresh = [0.0; 3];
+                            if fit.gauss_newton(&rgb, &mut fresh) < error {
+                                *coefficients = fresh;
+                            }
+                        }
+                        line[k] = *coefficients;
+                    };
+
+                    let mut coefficients = [0.0; 3];
+                    for k in start..TABLE_RES {
+                        solve(k, &mut coefficients);
+                    }
+                    let mut coefficients = [0.0; 3];
+                    for k in (0..=start).rev() {
+                        solve(k, &mut coefficients);
+                    }
+
+                    (l, j * TABLE_RES + i, line)
+                })
+            })
+            .collect();
+
+        let mut coefficients = vec![[0.0; 3]; 3 * TABLE_RES * TABLE_RES * TABLE_RES];
+        for (l, ji, line) in lines {
+            let (j, i) = (ji / TABLE_RES, ji % TABLE_RES);
+            for (k, c) in line.into_iter().enumerate() {
+                coefficients[index(l, k, j, i)] = c;
+            }
+        }
+
+        RgbToSpectrumTable {
+            z_nodes,
+            coefficients,
+        }
+    }
+}
+
+fn index(max_c: usize, z: usize, y: usize, x: usize) -> usize {
+    ((max_c * TABLE_RES + z) * TABLE_RES + y) * TABLE_RES + x
+}
+
+/// Data needed to compare the color of a candidate spectrum with its target.
+struct FitTables {
+    lambdas: Vec<f64>,
+    // Matching functions weighted by the illuminant, normalized to a white luminance of 1
+    xyz: Vec<Vec3>,
+    // RGB of the illuminant, which the film maps back to white
+    white_rgb: Color,
+}
+
+impl FitTables {
+    fn new() -> FitTables {
+        let illuminant = d65();
+        let n = ((LAMBDA_MAX - LAMBDA_MIN) / FIT_STEP) as usize + 1;
+        let lambdas: Vec<f64> = (0..n).map(|i| LAMBDA_MIN + i as f64 * FIT_STEP).collect();
+
+        let xyz: Vec<Vec3> = lambdas
+            .iter()
+            .map(|&lambda| cie_xyz(lambda) * illuminant.value(lambda))
+            .collect();
+        let norm: f64 = xyz.iter().map(|xyz| xyz.y).sum();
+        let xyz: Vec<Vec3> = xyz.into_iter().map(|xyz| xyz / norm).collect();
+        let white_rgb = xyz_to_rgb(&xyz.iter().sum());
+
+        FitTables {
+            lambdas,
+            xyz,
+            white_rgb,
+        }
+    }
+
+    fn cie_lab(rgb: &Color) -> Vec3 {
+        let xyz = rgb_to_xyz(rgb);
+        let white = rgb_to_xyz(&Color::new(1.0, 1.0, 1.0));
+
+        let f = |t: f64| {
+            let delta: f64 = 6.0 / 29.0;
+            if t > delta.powi(3) {
+                t.cbrt()
+            } else {
+                t / (3.0 * delta * delta) + 4.0 / 29.0
+            }
+        };
+
+        let fx = f(xyz.x / white.x);
+        let fy = f(xyz.y / white.y);
+        let fz = f(xyz.z / white.z);
+
+        Vec3::new(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+    }
+
+    /// Difference in CIELAB between the target color and the color of the spectrum, as seen
+    /// through the white balanced film.
+    fn residual(&self, coefficients: &[f64; 3], target: &Color) -> Vec3 {
+        let polynomial = SigmoidPolynomial { c: *coefficients };
+
+        let xyz: Vec3 = self
+            .lambdas
+            .iter()
+            .zip(self.xyz.iter())
+            .map(|(&lambda, xyz)| xyz * polynomial.eval(lambda))
+            .sum();
+
+        let rgb = xyz_to_rgb(&xyz);
+        let rgb = Color::new(
+            rgb.x / self.white_rgb.x,
+            rgb.y / self.white_rgb.y,
+            rgb.z / self.white_rgb.z,
+        );
+
+        FitTables::cie_lab(target) - FitTables::cie_lab(&rgb)
+    }
+
+    /// Refines the coefficients fitting `target`, and returns the remaining squared error.
+    fn gauss_newton(&self, target: &Color, coefficients: &mut [f64; 3]) -> f64 {
+        for _ in 0..15 {
+            let r = self.residual(coefficients, target);
+
+            // Jacobian from central differences
+            let mut jacobian = [[0.0; 3]; 3];
+            for i in 0..3 {
+                let eps = 1e-5;
+                let mut c0 = *coefficients;
+                let mut c1 = *coefficients;
+                c0[i] -= eps;
+                c1[i] += eps;
+                let r0 = self.residual(&c0, target);
+                let r1 = self.residual(&c1, target);
+                for j in 0..3 {
+                    jacobian[j][i] = (r1[j] - r0[j]) / (2.0 * eps);
+                }
+            }
+
+            let x = match solve3(jacobian, [r.x, r.y, r.z]) {
+                Some(x) => x,
+                None => break,
+            };
+            for i in 0..3 {
+                coefficients[i] -= x[i];
+            }
+
+            let max = coefficients.iter().fold(0.0, |m: f64, c| m.max(c.abs()));
+            if max > 200.0 {
+                for c in coefficients.iter_mut() {
+                    *c *= 200.0 / max;
+                }
+            }
+
+            if r.magnitude2() < 1e-12 {
+                break;
+            }
+        }
+
+        self.residual(coefficients, target).magnitude2()
+    }
+}
+
+/// Solves the 3x3 linear system `a x = b` by gaussian elimination with partial pivoting.
+fn solve3(mut a: [[f64; 3]; 3], mut b: [f64; 3]) -> Option<[f64; 3]> {
+    for col in 0..3 {
+        let pivot = (col..3)
+            .max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())
+            .unwrap();
+        if a[pivot][col].abs() < 1e-15 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        for row in (col + 1)..3 {
+            let factor = a[row][col] / a[col][col];
+            let pivot_row = a[col];
+            for (x, p) in a[row].iter_mut().zip(pivot_row.iter()).skip(col) {
+                *x -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = [0.0; 3];
+    for row in (0..3).rev() {
+        let sum: f64 = ((row + 1)..3).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::{cie_y_integral, integrate, white_balanced_rgb};
+
+    /// Color of a reflectance spectrum lit by the color space illuminant.
+    fn reflected_rgb(polynomial: &SigmoidPolynomial) -> Color {
+        let xyz =
+            integrate(|lambda| cie_xyz(lambda) * (d65().value(lambda) * polynomial.eval(lambda)))
+                / cie_y_integral();
+        white_balanced_rgb(&xyz)
+    }
+
+    fn assert_round_trip(rgb: Color, tolerance: f64) {
+        let result = reflected_rgb(&rgb_to_spectrum_table().lookup(&rgb));
+        assert!(
+            (result - rgb).magnitude() < tolerance,
+            "{:?} came back as {:?}",
+            rgb,
+            result
+        );
+    }
+
+    #[test]
+    fn grays_round_trip() {
+        for gray in [0.0, 0.05, 0.18, 0.5, 0.8, 1.0] {
+            assert_round_trip(Color::new(gray, gray, gray), 1e-3);
+        }
+    }
+
+    #[test]
+    fn colors_round_trip() {
+        // Grid over the RGB cube, including the primaries on its corners
+        let n = 6;
+        for r in 0..=n {
+            for g in 0..=n {
+                for b in 0..=n {
+                    let rgb = Color::new(r as f64, g as f64, b as f64) / n as f64;
+                    assert_round_trip(rgb, 0.01);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn solves_linear_systems() {
+        let a = [[2.0, 1.0, -1.0], [-3.0, -1.0, 2.0], [-2.0, 1.0, 2.0]];
+        let x = solve3(a, [8.0, -11.0, -3.0]).unwrap();
+
+        for (x, expected) in x.iter().zip([2.0, 3.0, -1.0]) {
+            assert!((x - expected).abs() < 1e-12);
+        }
+        assert!(solve3(
+            [[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [0.0, 0.0, 1.0]],
+            [1.0; 3]
+        )
+        .is_none());
+    }
+}